@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Compiles every `*.rs` file of the plugin directory into the `rpc_cache_handler::plugins` module and
+/// calls its `register` function on startup. See the README for the plugin layout.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CACHED_ETH_RPC_PLUGIN_DIR");
+
+    let plugin_dir = match env::var_os("CACHED_ETH_RPC_PLUGIN_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("plugins"),
+    };
+    println!("cargo:rerun-if-changed={}", plugin_dir.display());
+
+    let mut plugins = fs::read_dir(&plugin_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    plugins.sort();
+
+    let mut modules = String::new();
+    let mut registrations = String::new();
+
+    for path in &plugins {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("plugin file name must be valid utf-8");
+        let path = path.canonicalize().expect("fail to resolve plugin path");
+
+        modules += &format!("#[path = {:?}]\nmod {name};\n", path.display().to_string());
+        registrations += &format!("    {name}::register(registry);\n");
+    }
+
+    let code = format!(
+        "{modules}\n#[allow(unused_variables)]\npub fn register_plugins(registry: &mut super::HandlerRegistry) {{\n{registrations}}}\n"
+    );
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out_dir.join("plugins.rs"), code).expect("fail to write plugin registrations");
+}
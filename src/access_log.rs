@@ -0,0 +1,45 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpRequest};
+
+/// What an RPC request consisted of, filled in by the handler for sampled requests.
+#[derive(Default)]
+pub struct RequestSummary {
+    pub methods: BTreeSet<String>,
+    pub batch_size: usize,
+    pub cache_hits: usize,
+}
+
+pub fn is_sampled(sample_rate: f64) -> bool {
+    sample_rate > 0.0 && rand::random::<f64>() < sample_rate
+}
+
+pub fn log(req: &HttpRequest, client_ip: Option<IpAddr>, status: StatusCode, duration: Duration) {
+    let duration_ms = duration.as_secs_f64() * 1000.0;
+    let client = client_ip.map_or("-".to_string(), |ip| ip.to_string());
+    let extensions = req.extensions();
+
+    match extensions.get::<RequestSummary>() {
+        Some(summary) if summary.batch_size > 0 => tracing::info!(
+            target: "access_log",
+            "{client} {} {} status={} duration_ms={duration_ms:.1} batch_size={} cache_hits={} hit_ratio={:.2} methods={}",
+            req.method(),
+            req.path(),
+            status.as_u16(),
+            summary.batch_size,
+            summary.cache_hits,
+            summary.cache_hits as f64 / summary.batch_size as f64,
+            summary.methods.iter().map(String::as_str).collect::<Vec<_>>().join(","),
+        ),
+        _ => tracing::info!(
+            target: "access_log",
+            "{client} {} {} status={} duration_ms={duration_ms:.1}",
+            req.method(),
+            req.path(),
+            status.as_u16(),
+        ),
+    }
+}
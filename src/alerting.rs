@@ -0,0 +1,38 @@
+use env_logger::Env;
+use sentry::integrations::log::{LogFilter, SentryLogger};
+
+use crate::args::Args;
+
+/// Logs through `env_logger`, turning errors into Sentry events and warnings into breadcrumbs once Sentry is
+/// initialized.
+pub fn init_logger() {
+    let logger = env_logger::Builder::from_env(Env::default().default_filter_or("info")).build();
+    let max_level = logger.filter();
+
+    let logger = SentryLogger::with_dest(logger).filter(|metadata| match metadata.level() {
+        log::Level::Error => LogFilter::Event,
+        log::Level::Warn => LogFilter::Breadcrumb,
+        _ => LogFilter::Ignore,
+    });
+
+    log::set_boxed_logger(Box::new(logger)).expect("fail to set logger");
+    log::set_max_level(max_level);
+}
+
+/// Reports panics and errors to Sentry until the returned guard is dropped, if a DSN is configured.
+pub fn init_sentry(args: &Args) -> Option<sentry::ClientInitGuard> {
+    let dsn = args.sentry_dsn.as_ref()?;
+
+    let guard = sentry::init((
+        dsn.as_str(),
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            environment: args.sentry_environment.clone().map(Into::into),
+            attach_stacktrace: true,
+            ..Default::default()
+        },
+    ));
+
+    tracing::info!("Reporting errors to Sentry");
+    Some(guard)
+}
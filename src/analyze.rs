@@ -0,0 +1,277 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use crate::cache::EntryMetadata;
+use crate::METADATA_KEY_SUFFIX;
+
+/// Keys fetched per `SCAN` call.
+const SCAN_BATCH_SIZE: usize = 1000;
+
+/// Prefixes of the keys the proxy keeps next to the entries, e.g. locks and quota accounting.
+const INTERNAL_KEY_PREFIXES: &[&str] = &["lock:", "stored-at:", "stored-bytes:"];
+
+/// Upper bounds in seconds of the age buckets, and their labels.
+const AGE_BUCKETS: &[(u64, &str)] = &[
+    (3600, "<1h"),
+    (86400, "<1d"),
+    (7 * 86400, "<7d"),
+    (30 * 86400, "<30d"),
+    (u64::MAX, ">=30d"),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    #[arg(
+        long,
+        help = "Redis instance to analyze, e.g. `redis://127.0.0.1:6379/1`."
+    )]
+    pub url: String,
+
+    #[arg(
+        long,
+        help = "Only analyze the entries of this key prefix, the chain id unless configured otherwise."
+    )]
+    pub key_prefix: Option<String>,
+
+    #[arg(long, default_value = "10", help = "Largest entries to list.")]
+    pub top: usize,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct Usage {
+    entries: u64,
+    /// Of the keys and values, metadata included.
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct ChainUsage {
+    methods: BTreeMap<String, Usage>,
+    /// Entries by age bucket, known from their metadata.
+    ages: [u64; AGE_BUCKETS.len()],
+}
+
+#[derive(Default)]
+struct Analysis {
+    chains: BTreeMap<String, ChainUsage>,
+    /// Min-heap of the largest entries seen so far.
+    largest: BinaryHeap<Reverse<(u64, String)>>,
+}
+
+impl Analysis {
+    /// Accounts for the key `key` of `bytes`, along with the value of its metadata if it's a metadata key.
+    fn record(&mut self, key: &str, bytes: u64, metadata: Option<&str>, now: u64, top: usize) {
+        let mut parts = key.splitn(3, ':');
+        let (Some(prefix), Some(method), Some(params_key)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return;
+        };
+
+        let chain = self.chains.entry(prefix.to_string()).or_default();
+        let usage = chain.methods.entry(method.to_string()).or_default();
+        usage.bytes += bytes;
+
+        if params_key.ends_with(METADATA_KEY_SUFFIX) {
+            let stored_at = metadata
+                .and_then(|metadata| serde_json::from_str::<EntryMetadata>(metadata).ok())
+                .map(|metadata| metadata.stored_at);
+            if let Some(stored_at) = stored_at {
+                let age = now.saturating_sub(stored_at);
+                let bucket = AGE_BUCKETS
+                    .iter()
+                    .position(|(bound, _)| age < *bound)
+                    .unwrap_or(AGE_BUCKETS.len() - 1);
+                chain.ages[bucket] += 1;
+            }
+            return;
+        }
+
+        usage.entries += 1;
+
+        if top > 0 {
+            self.largest.push(Reverse((bytes, key.to_string())));
+            if self.largest.len() > top {
+                self.largest.pop();
+            }
+        }
+    }
+
+    fn print(self) {
+        for (prefix, chain) in &self.chains {
+            let entries = chain
+                .methods
+                .values()
+                .map(|usage| usage.entries)
+                .sum::<u64>();
+            let bytes = chain.methods.values().map(|usage| usage.bytes).sum::<u64>();
+            println!("{prefix}: {entries} entries, {bytes} bytes");
+
+            let mut methods = chain.methods.iter().collect::<Vec<_>>();
+            methods.sort_by_key(|(_, usage)| Reverse(usage.bytes));
+            for (method, usage) in methods {
+                println!(
+                    "  {method}: entries={} bytes={} avg_bytes={}",
+                    usage.entries,
+                    usage.bytes,
+                    usage.bytes / usage.entries.max(1)
+                );
+            }
+
+            let with_age = chain.ages.iter().sum::<u64>();
+            let ages = AGE_BUCKETS
+                .iter()
+                .zip(&chain.ages)
+                .map(|((_, label), count)| format!("{label}={count}"))
+                .collect::<Vec<_>>();
+            println!(
+                "  ages: {} unknown={}",
+                ages.join(" "),
+                entries.saturating_sub(with_age)
+            );
+        }
+
+        println!("largest entries:");
+        for Reverse((bytes, key)) in self.largest.into_sorted_vec() {
+            println!("  {bytes} {key}");
+        }
+    }
+}
+
+/// Scans the keys of a redis instance and prints entry counts and sizes by key prefix and method, the largest
+/// entries and the age of the entries with metadata.
+pub fn run(args: &AnalyzeArgs) -> anyhow::Result<()> {
+    let client = redis::Client::open(args.url.as_str()).context("fail to create redis client")?;
+    let mut conn = client
+        .get_connection()
+        .context("fail to connect to redis")?;
+
+    let pattern = match &args.key_prefix {
+        Some(key_prefix) => format!("{key_prefix}:*"),
+        None => "*".to_string(),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut analysis = Analysis::default();
+    let mut cursor = 0u64;
+
+    loop {
+        let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(SCAN_BATCH_SIZE)
+            .query(&mut conn)
+            .context("fail to scan keys")?;
+
+        let keys = keys
+            .into_iter()
+            .filter(|key| {
+                !INTERNAL_KEY_PREFIXES
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix))
+            })
+            .collect::<Vec<_>>();
+
+        if !keys.is_empty() {
+            let mut pipe = redis::pipe();
+            for key in &keys {
+                pipe.cmd("STRLEN").arg(key);
+            }
+            let value_lengths: Vec<u64> = pipe.query(&mut conn).context("fail to read sizes")?;
+
+            let metadata_keys = keys
+                .iter()
+                .filter(|key| key.ends_with(METADATA_KEY_SUFFIX))
+                .collect::<Vec<_>>();
+            let mut metadata = match metadata_keys.is_empty() {
+                true => vec![],
+                false => redis::cmd("MGET")
+                    .arg(&metadata_keys)
+                    .query::<Vec<Option<String>>>(&mut conn)
+                    .context("fail to read metadata")?,
+            }
+            .into_iter();
+
+            for (key, value_len) in keys.iter().zip(value_lengths) {
+                let metadata = if key.ends_with(METADATA_KEY_SUFFIX) {
+                    metadata.next().flatten()
+                } else {
+                    None
+                };
+                let bytes = key.len() as u64 + value_len;
+                analysis.record(key, bytes, metadata.as_deref(), now, args.top);
+            }
+        }
+
+        cursor = next_cursor;
+        if cursor == 0 {
+            break;
+        }
+    }
+
+    analysis.print();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let now = 10 * 86400;
+        let metadata = |stored_at: u64| {
+            format!(r#"{{"stored_at":{stored_at},"upstream":null,"head_block":null}}"#)
+        };
+
+        let mut analysis = Analysis::default();
+        analysis.record("1:eth_getBalance:0x10-0xab", 40, None, now, 2);
+        analysis.record("1:eth_getBalance:0x11-0xab", 60, None, now, 2);
+        analysis.record(
+            "1:eth_getBalance:0x11-0xab:meta",
+            90,
+            Some(&metadata(now - 60)),
+            now,
+            2,
+        );
+        analysis.record("1:eth_getLogs:0x10-0xcd", 500, None, now, 2);
+        analysis.record(
+            "1:eth_getLogs:0x10-0xcd:meta",
+            90,
+            Some(&metadata(now - 2 * 86400)),
+            now,
+            2,
+        );
+        analysis.record("137:eth_chainId:", 20, None, now, 2);
+        // Not an entry.
+        analysis.record("usage", 5, None, now, 2);
+
+        let chain = &analysis.chains["1"];
+        assert_eq!(
+            chain.methods["eth_getBalance"],
+            Usage {
+                entries: 2,
+                bytes: 190
+            }
+        );
+        assert_eq!(chain.ages, [1, 0, 1, 0, 0]);
+        assert_eq!(analysis.chains["137"].methods["eth_chainId"].entries, 1);
+        assert_eq!(analysis.chains.len(), 2);
+
+        let largest = analysis
+            .largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((bytes, _))| bytes)
+            .collect::<Vec<_>>();
+        assert_eq!(largest, vec![500, 60]);
+    }
+}
@@ -1,25 +1,420 @@
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
 use reqwest::Url;
+use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::analyze::AnalyzeArgs;
+use crate::bench::BenchArgs;
+use crate::cache::mode::CacheMode;
+use crate::client_ip::Cidr;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, default_value = "127.0.0.1")]
-    pub bind: String,
+    #[arg(
+        short,
+        long,
+        env,
+        default_value = "127.0.0.1",
+        value_delimiter = ',',
+        value_parser = bind_parser,
+        help = "Address to listen on, repeatable, e.g. `0.0.0.0` or `[::]:8125`. Addresses without a port use `--port`."
+    )]
+    pub bind: Vec<(String, Option<u16>)>,
 
-    #[arg(short, long, default_value = "8124")]
+    #[arg(short, long, env, default_value = "8124")]
     pub port: u16,
 
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        value_parser = admin_bind_parser,
+        help = "Address to serve `/health`, `/metrics`, `/stats`, `/{chain}/inspect` and `/{chain}/maintenance` on instead of the public addresses, repeatable, e.g. `127.0.0.1` or `unix:/run/cached-eth-rpc/admin.sock`. Addresses without a port use `--admin-port`."
+    )]
+    pub admin_bind: Vec<AdminAddress>,
+
+    #[arg(long, env, default_value = "9124")]
+    pub admin_port: u16,
+
     #[arg(short, long = "endpoint", value_parser = endpoint_parser)]
     pub endpoints: Vec<(String, Url)>,
 
     #[arg(
         short,
         long,
+        env,
         help = "Redis URL. If not suppiled, in memory cache backend will be used."
     )]
     pub redis_url: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        default_value = "300",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Maximum number of connections of each chain's redis pool."
+    )]
+    pub redis_pool_max_size: u32,
+
+    #[arg(
+        long,
+        env,
+        help = "Idle connections each chain's redis pool keeps open. Defaults to the maximum size."
+    )]
+    pub redis_pool_min_idle: Option<u32>,
+
+    #[arg(
+        long,
+        env,
+        default_value = "30",
+        help = "Seconds to wait for a connection of the redis pool before the request fails."
+    )]
+    pub redis_pool_connection_timeout_secs: u64,
+
+    #[arg(
+        long,
+        env,
+        help = "Check connections with a `PING` before handing them out of the redis pool."
+    )]
+    pub redis_pool_test_on_check_out: bool,
+
+    #[arg(
+        long,
+        env,
+        help = "Lock missed keys in redis while fetching them, so that instances sharing the redis wait for each other's results instead of all fetching them."
+    )]
+    pub redis_fetch_locks: bool,
+
+    #[arg(
+        long,
+        env,
+        default_value = "0",
+        value_parser = sample_rate_parser,
+        help = "Fraction of cache hits re-queried from the upstream in the background to detect cache mismatches."
+    )]
+    pub verify_sample_rate: f64,
+
+    #[arg(
+        long,
+        env,
+        default_value = "0",
+        value_parser = sample_rate_parser,
+        help = "Fraction of HTTP requests logged with their methods, batch size, cache hits, status and duration."
+    )]
+    pub access_log_sample_rate: f64,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated CIDRs of proxies in front, e.g. `10.0.0.0/8`, whose `X-Forwarded-For` gives the client address."
+    )]
+    pub trusted_proxies: Vec<Cidr>,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated CIDRs of clients allowed to use the RPC endpoints, any client if empty."
+    )]
+    pub allow_ips: Vec<Cidr>,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated CIDRs of clients rejected from the RPC endpoints, even if allowed."
+    )]
+    pub deny_ips: Vec<Cidr>,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated CIDRs of clients allowed to use the management endpoints, any client if empty."
+    )]
+    pub admin_allow_ips: Vec<Cidr>,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated CIDRs of clients rejected from the management endpoints, even if allowed."
+    )]
+    pub admin_deny_ips: Vec<Cidr>,
+
+    #[arg(long, env, help = "YAML file with per-chain settings.")]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        requires = "tls_key",
+        help = "PEM encoded certificate chain. If supplied, the server listens over HTTPS."
+    )]
+    pub tls_cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        requires = "tls_cert",
+        help = "PEM encoded private key for `--tls-cert`."
+    )]
+    pub tls_key: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma separated origins allowed to make cross-origin requests. Use `*` to allow any origin."
+    )]
+    pub cors_origins: Vec<String>,
+
+    #[arg(
+        long,
+        env,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Worker threads serving requests. Defaults to the number of CPU cores."
+    )]
+    pub workers: Option<usize>,
+
+    #[arg(
+        long,
+        env,
+        help = "Concurrent connections accepted per worker. Defaults to 25000."
+    )]
+    pub max_connections: Option<usize>,
+
+    #[arg(
+        long,
+        env,
+        help = "Pending connections the listening socket queues before refusing new ones. Defaults to 1024."
+    )]
+    pub backlog: Option<u32>,
+
+    #[arg(
+        long,
+        env,
+        help = "Milliseconds a client has to send the request head before it is answered with 408. Defaults to 5000."
+    )]
+    pub client_request_timeout_ms: Option<u64>,
+
+    #[arg(
+        long,
+        env,
+        help = "Seconds idle keep-alive connections are kept open, `0` disables keep-alive. Defaults to 5."
+    )]
+    pub keep_alive_secs: Option<u64>,
+
+    #[arg(
+        long,
+        env,
+        help = "Reports panics and errors, with the request they occurred in, to this Sentry DSN."
+    )]
+    pub sentry_dsn: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        requires = "sentry_dsn",
+        help = "Environment reported to Sentry."
+    )]
+    pub sentry_environment: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        default_value = "5",
+        help = "Consecutive failed requests of an upstream that are reported as an error."
+    )]
+    pub upstream_failure_alert_threshold: u32,
+
+    #[arg(
+        long = "confirmation-depth",
+        env,
+        value_delimiter = ',',
+        value_parser = chain_number_parser,
+        help = "Blocks a block-number-keyed result must be behind the head before it is cached, e.g. `polygon=5`. Overrides `confirmation_depth` of the config file."
+    )]
+    pub confirmation_depths: Vec<(String, u64)>,
+
+    #[arg(
+        long,
+        env,
+        value_enum,
+        default_value_t = CacheMode::ReadWrite,
+        help = "Whether this instance reads from and writes to the cache, e.g. `read-only` for replicas sharing a cache filled by a `write-only` instance."
+    )]
+    pub cache_mode: CacheMode,
+
+    #[arg(
+        long,
+        env,
+        help = "Upstream requests in flight at once across all chains, unbounded if unset. See `max_concurrent_upstream_requests` of the config file for a per-chain limit."
+    )]
+    pub max_concurrent_upstream_requests: Option<usize>,
+
+    #[arg(
+        long,
+        env,
+        default_value = "5000",
+        help = "Milliseconds a request waits for a free upstream request slot before it fails."
+    )]
+    pub upstream_queue_timeout_ms: u64,
+
+    #[arg(
+        long,
+        env,
+        help = "YAML file mapping API keys to their daily and monthly quotas. Requests without a known key are rejected if set."
+    )]
+    pub api_keys_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        help = "Hex encoded HS256 secret, e.g. geth's `jwtsecret`. Requests must carry a bearer token signed with it, issued within the last 60 seconds, if set."
+    )]
+    pub jwt_secret_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        conflicts_with = "verify_sample_rate",
+        help = "Never contact upstreams and answer cache misses with error code -32056. Chains need a `chain_id` in the config file."
+    )]
+    pub offline: bool,
+
+    #[arg(
+        long,
+        env,
+        help = "Directory the cache entries kept forever of each chain are periodically saved to, as `<chain>.jsonl.gz`, e.g. to restore the memory backend after a restart."
+    )]
+    pub snapshot_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env,
+        default_value = "3600",
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..),
+        help = "Seconds between cache snapshots of `--snapshot-dir`."
+    )]
+    pub snapshot_interval_secs: u64,
+
+    #[arg(
+        long,
+        env,
+        help = "Directory of cache snapshots, e.g. the `--snapshot-dir` of a previous run, loaded into the cache of each chain at startup. Chains without a snapshot start empty."
+    )]
+    pub restore_from: Option<PathBuf>,
+
+    #[arg(
+        long = "peer",
+        env = "PEERS",
+        value_delimiter = ',',
+        requires = "peer_token",
+        help = "Admin address of another instance, e.g. `http://10.0.0.2:9124`, the cache writes of every chain are sent to on a best-effort basis. Repeat for several peers."
+    )]
+    pub peers: Vec<Url>,
+
+    #[arg(
+        long,
+        env,
+        help = "Secret shared by the peers to authenticate the cache writes they send each other. Writes from peers are only accepted if set."
+    )]
+    pub peer_token: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        help = "Answer batches of more than 1000 requests in the order their responses are known, starting with cache hits while misses are still being fetched."
+    )]
+    pub reorder_batches: bool,
+
+    #[arg(
+        long = "pin-block",
+        env,
+        value_delimiter = ',',
+        value_parser = chain_number_parser,
+        help = "Freezes a chain at a block, e.g. `eth=19000000`: block tags resolve to it and later blocks are rejected. Overrides `pinned_block` of the config file."
+    )]
+    pub pinned_blocks: Vec<(String, u64)>,
+
+    #[arg(
+        long,
+        env,
+        help = "Chain, by name or alias, also served at `POST /`, e.g. to replace the URL of a single node."
+    )]
+    pub default_chain: Option<String>,
+
+    #[arg(
+        long,
+        env,
+        help = "Answer `web3_clientVersion` with the version of the proxy instead of forwarding it."
+    )]
+    pub local_client_version: bool,
+
+    #[arg(
+        long,
+        env,
+        help = "Honor `failure_injection` of the config file, which breaks upstream responses on purpose. For testing only."
+    )]
+    pub failure_injection: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Replays a request log, or synthesized traffic, against a running instance and reports throughput, latencies
+    /// and hit rates.
+    Bench(BenchArgs),
+
+    /// Scans a redis instance and reports entry counts and sizes per key prefix and method, the largest entries and
+    /// the age of entries, to tune ttls and quotas.
+    Analyze(AnalyzeArgs),
+}
+
+/// Prefix of environment variables naming an endpoint after the rest of their name, e.g. `ENDPOINT_ETH=https://..`.
+const ENDPOINT_ENV_PREFIX: &str = "ENDPOINT_";
+
+impl Args {
+    /// Parses the command line, falling back to environment variables. Endpoints given as `ENDPOINT_<CHAIN>`
+    /// variables, comma separated for several upstreams, are added to the ones of `--endpoint`.
+    pub fn load() -> Self {
+        let mut args = Self::parse();
+
+        match env_endpoints(std::env::vars()) {
+            Ok(endpoints) => args.endpoints.extend(endpoints),
+            Err(err) => Self::command()
+                .error(ErrorKind::ValueValidation, err)
+                .exit(),
+        }
+
+        args
+    }
+}
+
+fn env_endpoints(
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<(String, Url)>, String> {
+    let mut endpoints = vars
+        .filter_map(|(key, value)| {
+            Some((key.strip_prefix(ENDPOINT_ENV_PREFIX)?.to_string(), value))
+        })
+        .flat_map(|(name, value)| {
+            value
+                .split(',')
+                .map(|url| endpoint_parser(&format!("{name}={}", url.trim())))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Environment variables come in no particular order.
+    endpoints.sort();
+    Ok(endpoints)
 }
 
 fn endpoint_parser(s: &str) -> Result<(String, Url), String> {
@@ -34,3 +429,139 @@ fn endpoint_parser(s: &str) -> Result<(String, Url), String> {
 
     Ok((name, url))
 }
+
+/// Host and optional port of a `--bind` address. IPv6 addresses need brackets to be given a port, e.g. `[::1]:8124`.
+fn bind_parser(s: &str) -> Result<(String, Option<u16>), String> {
+    let (host, port) = match s.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("Invalid address, missing `]`: {s}"))?;
+            match rest {
+                "" => (host, None),
+                _ => {
+                    let port = rest.strip_prefix(':').ok_or_else(|| {
+                        format!("Invalid address, expected `:port` after `]`: {s}")
+                    })?;
+                    (host, Some(port))
+                }
+            }
+        }
+        None => match s.split_once(':') {
+            // More colons make an IPv6 address without a port.
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (s, None),
+        },
+    };
+
+    if host.is_empty() {
+        return Err(format!("Invalid address, missing host: {s}"));
+    }
+
+    let port = port
+        .map(|port| u16::from_str(port).map_err(|e| format!("Invalid port in {s}: {e}")))
+        .transpose()?;
+
+    Ok((host.to_string(), port))
+}
+
+/// Where the management endpoints are served when they are kept off the public addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminAddress {
+    Tcp(String, Option<u16>),
+    Unix(PathBuf),
+}
+
+/// A `--bind` style address, or the path of a unix socket prefixed with `unix:`.
+fn admin_bind_parser(s: &str) -> Result<AdminAddress, String> {
+    match s.strip_prefix("unix:") {
+        Some("") => Err(format!("Invalid address, missing socket path: {s}")),
+        Some(path) => Ok(AdminAddress::Unix(PathBuf::from(path))),
+        None => bind_parser(s).map(|(host, port)| AdminAddress::Tcp(host, port)),
+    }
+}
+
+fn chain_number_parser(s: &str) -> Result<(String, u64), String> {
+    let (name, number) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid format, expected `chain=number`: {s}"))?;
+
+    let number = u64::from_str(number).map_err(|e| e.to_string())?;
+
+    Ok((name.to_uppercase(), number))
+}
+
+fn sample_rate_parser(s: &str) -> Result<f64, String> {
+    let rate = f64::from_str(s).map_err(|e| e.to_string())?;
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("sample rate must be between 0 and 1, got {rate}"));
+    }
+
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bind_parser() {
+        assert_eq!(bind_parser("0.0.0.0"), Ok(("0.0.0.0".to_string(), None)));
+        assert_eq!(
+            bind_parser("127.0.0.1:8125"),
+            Ok(("127.0.0.1".to_string(), Some(8125)))
+        );
+        assert_eq!(bind_parser("::"), Ok(("::".to_string(), None)));
+        assert_eq!(bind_parser("[::]"), Ok(("::".to_string(), None)));
+        assert_eq!(
+            bind_parser("[::1]:8125"),
+            Ok(("::1".to_string(), Some(8125)))
+        );
+
+        assert!(bind_parser("[::1").is_err());
+        assert!(bind_parser("[::1]8125").is_err());
+        assert!(bind_parser(":8125").is_err());
+        assert!(bind_parser("localhost:http").is_err());
+    }
+
+    #[test]
+    fn test_admin_bind_parser() {
+        assert_eq!(
+            admin_bind_parser("unix:/run/admin.sock"),
+            Ok(AdminAddress::Unix(PathBuf::from("/run/admin.sock")))
+        );
+        assert_eq!(
+            admin_bind_parser("[::1]:9124"),
+            Ok(AdminAddress::Tcp("::1".to_string(), Some(9124)))
+        );
+        assert!(admin_bind_parser("unix:").is_err());
+    }
+
+    #[test]
+    fn test_env_endpoints() {
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("ENDPOINT_ETH", "https://b.example, https://a.example"),
+            ("ENDPOINT_bsc", "https://bsc.example"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+
+        let endpoints = env_endpoints(vars.into_iter()).unwrap();
+        let endpoints = endpoints
+            .iter()
+            .map(|(name, url)| (name.as_str(), url.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            endpoints,
+            [
+                ("BSC", "https://bsc.example/"),
+                ("ETH", "https://a.example/"),
+                ("ETH", "https://b.example/"),
+            ]
+        );
+
+        let vars = [("ENDPOINT_ETH".to_string(), "not a url".to_string())];
+        assert!(env_endpoints(vars.into_iter()).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::cache::CacheBackend;
+use crate::ChainState;
+
+/// Snapshot file of the chain `name` in `dir`.
+pub fn snapshot_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.jsonl.gz"))
+}
+
+/// Writes the entries kept forever to `path`, gzipped with one `[method, params_key, value]` array per line, and
+/// returns how many were written. The snapshot is written next to `path` first, so that a crash never leaves half
+/// of one.
+pub fn save(cache_backend: &mut dyn CacheBackend, path: &Path) -> anyhow::Result<u64> {
+    let tmp_path = path.with_extension("gz.tmp");
+    let file = File::create(&tmp_path).context("fail to create snapshot file")?;
+    let mut writer = GzEncoder::new(BufWriter::new(file), Compression::default());
+
+    let mut write_entry = |method: &str, params_key: &str, value: &str| -> anyhow::Result<()> {
+        serde_json::to_writer(&mut writer, &(method, params_key, value))?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    };
+
+    let mut entries = 0;
+    let mut result = Ok(());
+    cache_backend.export(&mut |method, params_key, value| {
+        if result.is_ok() {
+            result = write_entry(method, params_key, value);
+            entries += 1;
+        }
+    })?;
+    result.context("fail to write snapshot")?;
+
+    writer
+        .finish()
+        .and_then(|writer| writer.into_inner().map_err(|err| err.into_error()))
+        .and_then(|file| file.sync_all())
+        .context("fail to flush snapshot")?;
+    std::fs::rename(&tmp_path, path).context("fail to move snapshot in place")?;
+
+    Ok(entries)
+}
+
+/// Writes the entries of the snapshot at `path` to the cache, kept forever, and returns how many were written.
+pub fn restore(cache_backend: &mut dyn CacheBackend, path: &Path) -> anyhow::Result<u64> {
+    let file = File::open(path).context("fail to open snapshot file")?;
+
+    let mut entries = 0;
+    for line in BufReader::new(GzDecoder::new(file)).lines() {
+        let line = line.context("fail to read snapshot")?;
+        let (method, params_key, value): (String, String, String) =
+            serde_json::from_str(&line).context("fail to parse snapshot entry")?;
+
+        let key = cache_backend.key(&method, &params_key);
+        cache_backend.write(&key, &value, None)?;
+        entries += 1;
+    }
+
+    Ok(entries)
+}
+
+/// Periodically saves the cache of the chain to `path`. Exports run on the blocking thread pool since cache
+/// backends are synchronous.
+pub async fn run_backups(chain_state: Arc<ChainState>, path: PathBuf, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    // The first tick completes immediately, right after the cache was restored.
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let state = chain_state.clone();
+        let snapshot_path = path.clone();
+        let result = web::block(move || {
+            let mut cache_backend = state.cache_factory.get_instance()?;
+            save(&mut *cache_backend, &snapshot_path)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(entries)) => {
+                tracing::info!("saved {entries} cache entries to {}", path.display())
+            }
+            Ok(Err(err)) => tracing::warn!("fail to save cache snapshot: {err:#}"),
+            Err(err) => tracing::warn!("fail to run cache snapshot: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+    use crate::cache::{CacheBackendFactory, CacheStatus};
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut backend = MemoryBackendFactory::new().get_instance().unwrap();
+        backend
+            .write(
+                "eth_getBlockByNumber:0x1-false",
+                r#"{"number":"0x1"}"#,
+                None,
+            )
+            .unwrap();
+        backend
+            .write("eth_getBalance:0xab-0x1", "\"0x10\"", None)
+            .unwrap();
+        backend
+            .write("eth_blockNumber:", "\"0x2\"", Some(Duration::from_secs(10)))
+            .unwrap();
+
+        let dir = std::env::temp_dir();
+        let path = snapshot_path(&dir, &format!("backup-{:016x}", rand::random::<u64>()));
+        assert_eq!(save(&mut *backend, &path).unwrap(), 2);
+
+        let mut restored = MemoryBackendFactory::new().get_instance().unwrap();
+        assert_eq!(restore(&mut *restored, &path).unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+
+        match restored.read("eth_getBlockByNumber", "0x1-false").unwrap() {
+            CacheStatus::Cached { value, .. } => assert_eq!(value["number"], "0x1"),
+            CacheStatus::Missed { .. } => panic!("entry not restored"),
+        }
+        assert!(matches!(
+            restored.read("eth_blockNumber", "").unwrap(),
+            CacheStatus::Missed { .. }
+        ));
+    }
+}
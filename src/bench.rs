@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use futures::stream::{self, StreamExt};
+use hdrhistogram::Histogram;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::rpc_cache_handler::common::parse_block_number;
+
+/// Upper bound of recorded latencies, slower requests are clamped to it.
+const MAX_LATENCY_MICROS: u64 = 60_000_000;
+
+/// Synthesized requests only refer to blocks this far behind the head, so that their results can be cached.
+const SYNTHETIC_BLOCK_DEPTH: u64 = 128;
+
+/// Blocks synthesized requests pick from, few enough for blocks to be requested repeatedly like in real traffic.
+const SYNTHETIC_BLOCK_WINDOW: u64 = 1000;
+
+const TOKENS: &[&str] = &[
+    "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+    "0xdac17f958d2ee523a2206206994597c13d831ec7",
+    "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+    "0x6b175474e89094c44da98b954eedeac495271d0f",
+];
+
+const ACCOUNTS: &[&str] = &[
+    "0xd8da6bf26964af9d7eed9e03e53415d37aa96045",
+    "0x28c6c06298d514db089934071355e5743bf21d60",
+    "0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503",
+    "0xf977814e90da44bfa03b6295a0616a897441acec",
+    "0x21a31ee1afc51d94c2efccaa2092ad1028285549",
+];
+
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Traffic {
+    /// Balances, nonces and token balances at `latest`, like wallets polling accounts.
+    #[default]
+    Wallet,
+
+    /// Blocks, receipts, logs and state of recent finalized blocks, like indexers catching up.
+    Indexer,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    #[arg(
+        long,
+        help = "Chain endpoint of the instance to benchmark, e.g. `http://127.0.0.1:8124/eth`."
+    )]
+    pub url: Url,
+
+    #[arg(
+        long,
+        help = "Log with one JSON-RPC request or batch per line to replay. Traffic is synthesized if not supplied."
+    )]
+    pub requests_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Traffic::Wallet,
+        conflicts_with = "requests_file",
+        help = "Kind of traffic synthesized without `--requests-file`."
+    )]
+    pub traffic: Traffic,
+
+    #[arg(
+        long,
+        help = "HTTP requests to send. Defaults to every line of `--requests-file`, repeated if higher, or 10000."
+    )]
+    pub requests: Option<usize>,
+
+    #[arg(
+        long,
+        default_value = "32",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "HTTP requests in flight at once."
+    )]
+    pub concurrency: usize,
+}
+
+/// Sends the requests of the benchmark to the instance and prints throughput, latencies and hit rates.
+pub async fn run(args: &BenchArgs) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+
+    let bodies = match &args.requests_file {
+        Some(path) => {
+            let log = load_request_log(path)?;
+            anyhow::ensure!(!log.is_empty(), "request log is empty");
+
+            let count = args.requests.unwrap_or(log.len());
+            log.into_iter().cycle().take(count).collect::<Vec<_>>()
+        }
+        None => {
+            let head = block_number(&client, &args.url).await?;
+            synthesize(args.traffic, head, args.requests.unwrap_or(10_000))
+        }
+    };
+
+    // Hit rates are derived from the stats of the instance, which also count requests of other clients.
+    let stats_url = args.url.join("/stats").context("fail to build stats url")?;
+    let hits_before = cache_hits(&client, &stats_url).await;
+
+    let started_at = Instant::now();
+    let outcomes = stream::iter(&bodies)
+        .map(|body| send(&client, &args.url, body))
+        .buffer_unordered(args.concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    let elapsed = started_at.elapsed();
+
+    let hits_after = cache_hits(&client, &stats_url).await;
+
+    let mut latency_micros = Histogram::<u64>::new_with_bounds(1, MAX_LATENCY_MICROS, 3).unwrap();
+    let mut errors = 0;
+    for outcome in &outcomes {
+        let micros = (outcome.latency.as_micros() as u64).clamp(1, MAX_LATENCY_MICROS);
+        latency_micros.saturating_record(micros);
+        errors += outcome.errors;
+    }
+
+    let mut calls = BTreeMap::<&str, u64>::new();
+    for method in bodies.iter().flat_map(methods) {
+        *calls.entry(method).or_default() += 1;
+    }
+    let total_calls = calls.values().sum::<u64>();
+
+    let secs = elapsed.as_secs_f64();
+    let quantile_ms = |quantile: f64| latency_micros.value_at_quantile(quantile) as f64 / 1000.0;
+
+    println!(
+        "{} requests ({total_calls} calls) in {secs:.2}s, {} in flight",
+        bodies.len(),
+        args.concurrency
+    );
+    println!(
+        "throughput: {:.1} requests/s, {:.1} calls/s",
+        bodies.len() as f64 / secs,
+        total_calls as f64 / secs
+    );
+    println!(
+        "latency ms: p50={:.1} p90={:.1} p99={:.1} max={:.1}",
+        quantile_ms(0.5),
+        quantile_ms(0.9),
+        quantile_ms(0.99),
+        latency_micros.max() as f64 / 1000.0
+    );
+    println!("errors: {errors} calls");
+
+    match (hits_before, hits_after) {
+        (Ok(before), Ok(after)) => {
+            let hits = |method: &str| {
+                let before = before.get(method).copied().unwrap_or_default();
+                after
+                    .get(method)
+                    .copied()
+                    .unwrap_or_default()
+                    .saturating_sub(before)
+            };
+            let total_hits = calls.keys().map(|method| hits(method)).sum::<u64>();
+
+            println!("hit rate: {:.2}", total_hits as f64 / total_calls as f64);
+            for (method, count) in &calls {
+                println!(
+                    "  {method}: calls={count} cache_hits={} hit_rate={:.2}",
+                    hits(method),
+                    hits(method) as f64 / *count as f64
+                );
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            println!("hit rate: unknown, fail to query {stats_url}: {err:#}")
+        }
+    }
+
+    Ok(())
+}
+
+struct Outcome {
+    latency: Duration,
+    /// Calls answered with an error, every call of the request if it failed entirely.
+    errors: usize,
+}
+
+async fn send(client: &reqwest::Client, url: &Url, body: &Value) -> Outcome {
+    let started_at = Instant::now();
+    let response = post(client, url, body).await;
+    let latency = started_at.elapsed();
+
+    let errors = match response {
+        Ok(Value::Array(responses)) => responses
+            .iter()
+            .filter(|response| !response["error"].is_null())
+            .count(),
+        Ok(response) => usize::from(!response["error"].is_null()),
+        Err(err) => {
+            tracing::debug!("fail to send benchmark request because: {err:#}");
+            methods(body).count().max(1)
+        }
+    };
+
+    Outcome { latency, errors }
+}
+
+async fn post(client: &reqwest::Client, url: &Url, body: &Value) -> anyhow::Result<Value> {
+    client
+        .post(url.clone())
+        .json(body)
+        .send()
+        .await
+        .context("fail to send request")?
+        .error_for_status()
+        .context("request failed")?
+        .json()
+        .await
+        .context("fail to parse response")
+}
+
+/// Methods of the calls of a request or batch.
+fn methods(body: &Value) -> impl Iterator<Item = &str> {
+    let calls = match body {
+        Value::Array(calls) => calls.as_slice(),
+        call => std::slice::from_ref(call),
+    };
+
+    calls.iter().filter_map(|call| call["method"].as_str())
+}
+
+/// Requests of a log with one JSON-RPC request or batch per line. Empty lines are skipped.
+fn load_request_log(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let content = std::fs::read_to_string(path).context("fail to read request log")?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("fail to parse line {} of request log", index + 1))
+        })
+        .collect()
+}
+
+async fn block_number(client: &reqwest::Client, url: &Url) -> anyhow::Result<u64> {
+    let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] });
+    let response = post(client, url, &body)
+        .await
+        .context("fail to fetch block number")?;
+
+    parse_block_number(&response["result"])?.context("block number not a number")
+}
+
+/// Cache hits per method summed over every chain, from `/stats` of the instance.
+async fn cache_hits(
+    client: &reqwest::Client,
+    stats_url: &Url,
+) -> anyhow::Result<HashMap<String, u64>> {
+    let stats: Value = client
+        .get(stats_url.clone())
+        .send()
+        .await
+        .context("fail to send request")?
+        .error_for_status()
+        .context("request failed")?
+        .json()
+        .await
+        .context("fail to parse stats")?;
+
+    let mut hits = HashMap::new();
+    for chain in stats.as_object().context("stats not an object")?.values() {
+        for (method, stats) in chain["methods"].as_object().into_iter().flatten() {
+            *hits.entry(method.clone()).or_default() +=
+                stats["cache_hits"].as_u64().unwrap_or_default();
+        }
+    }
+
+    Ok(hits)
+}
+
+fn synthesize(traffic: Traffic, head: u64, count: usize) -> Vec<Value> {
+    let mut rng = rand::thread_rng();
+    let newest = head.saturating_sub(SYNTHETIC_BLOCK_DEPTH);
+    let oldest = newest.saturating_sub(SYNTHETIC_BLOCK_WINDOW);
+
+    (0..count)
+        .map(|id| {
+            let account = *ACCOUNTS.choose(&mut rng).unwrap();
+            let balance_of = json!({
+                "to": TOKENS.choose(&mut rng).unwrap(),
+                "data": format!("0x70a08231{:0>64}", &account[2..]),
+            });
+            let block = format!("0x{:x}", rng.gen_range(oldest..=newest));
+
+            let (method, params) = match traffic {
+                Traffic::Wallet => match rng.gen_range(0..6) {
+                    0 => ("eth_chainId", json!([])),
+                    1 => ("eth_blockNumber", json!([])),
+                    2 => ("eth_getBalance", json!([account, "latest"])),
+                    3 => ("eth_getTransactionCount", json!([account, "pending"])),
+                    4 => ("eth_call", json!([balance_of, "latest"])),
+                    _ => ("eth_getBlockByNumber", json!(["latest", false])),
+                },
+                Traffic::Indexer => match rng.gen_range(0..5) {
+                    0 => ("eth_getBlockByNumber", json!([block, true])),
+                    1 => ("eth_getBlockReceipts", json!([block])),
+                    2 => (
+                        "eth_getLogs",
+                        json!([{ "fromBlock": block, "toBlock": block, "topics": [TRANSFER_TOPIC] }]),
+                    ),
+                    3 => ("eth_call", json!([balance_of, block])),
+                    _ => ("eth_getBalance", json!([account, block])),
+                },
+            };
+
+            json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_synthesize() {
+        let requests = synthesize(Traffic::Indexer, 20_000_000, 100);
+        assert_eq!(requests.len(), 100);
+
+        for request in &requests {
+            let block = match request["method"].as_str().unwrap() {
+                "eth_getLogs" => &request["params"][0]["fromBlock"],
+                "eth_call" | "eth_getBalance" => &request["params"][1],
+                _ => &request["params"][0],
+            };
+            let block = parse_block_number(block).unwrap().unwrap();
+            assert!((19_998_872..=19_999_872).contains(&block), "{request}");
+        }
+
+        let requests = synthesize(Traffic::Wallet, 0, 10);
+        assert!(requests.iter().all(|request| request["method"].is_string()));
+    }
+
+    #[test]
+    fn test_methods() {
+        let batch = json!([
+            { "id": 1, "method": "eth_chainId" },
+            { "id": 2, "method": "eth_blockNumber" },
+        ]);
+        assert_eq!(
+            methods(&batch).collect::<Vec<_>>(),
+            ["eth_chainId", "eth_blockNumber"]
+        );
+
+        let single = json!({ "id": 1, "method": "eth_chainId" });
+        assert_eq!(methods(&single).collect::<Vec<_>>(), ["eth_chainId"]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::f64::consts::LN_2;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::{CacheBackend, CacheBackendFactory, CacheStatus, GcStats};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct KeyFilterConfig {
+    /// Entries the filter is sized for. More entries raise the false positive rate.
+    pub expected_keys: usize,
+
+    /// Fraction of reads of missing keys that still reach the backend.
+    #[serde(default = "default_false_positive_rate")]
+    pub false_positive_rate: f64,
+}
+
+fn default_false_positive_rate() -> f64 {
+    0.01
+}
+
+/// Bloom filter of the keys written to the cache. Keys it doesn't contain are known to be missing, so that reads
+/// of them skip the backend.
+pub struct KeyFilter {
+    bits: Vec<AtomicU64>,
+    hashes: u64,
+    hasher: ahash::RandomState,
+    /// Set once the keys already in the backend have been added. Reads aren't filtered before.
+    ready: AtomicBool,
+}
+
+impl KeyFilter {
+    pub fn new(config: &KeyFilterConfig) -> Self {
+        let expected_keys = config.expected_keys.max(1) as f64;
+        let bits = -expected_keys * config.false_positive_rate.ln() / LN_2.powi(2);
+        let words = (bits / 64.0).ceil().max(1.0) as usize;
+        let hashes = (words as f64 * 64.0 / expected_keys * LN_2)
+            .round()
+            .clamp(1.0, 16.0);
+
+        Self {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            hashes: hashes as u64,
+            hasher: ahash::RandomState::new(),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub fn insert(&self, key: &str) {
+        for bit in self.bits_of(key) {
+            self.bits[bit / 64].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Whether `key` may have been inserted. Always `true` until the filter is ready.
+    pub fn may_contain(&self, key: &str) -> bool {
+        !self.ready.load(Ordering::Relaxed)
+            || self
+                .bits_of(key)
+                .all(|bit| self.bits[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0)
+    }
+
+    /// Adds the keys already in `backend` and starts filtering reads. Returns the number of keys added.
+    pub fn warm_up(&self, backend: &mut dyn CacheBackend) -> anyhow::Result<u64> {
+        let mut count = 0;
+        backend.scan_keys(&mut |key| {
+            self.insert(key);
+            count += 1;
+        })?;
+
+        self.ready.store(true, Ordering::Relaxed);
+        Ok(count)
+    }
+
+    /// Positions of the bits of `key`, by double hashing.
+    fn bits_of(&self, key: &str) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 64;
+        let first = self.hasher.hash_one(key);
+        let second = self.hasher.hash_one((key, 1u8)) | 1;
+
+        (0..self.hashes).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+}
+
+/// Answers reads of keys the filter doesn't contain as misses without asking `inner`.
+pub struct KeyFilterBackendFactory {
+    inner: Box<dyn CacheBackendFactory>,
+    filter: Arc<KeyFilter>,
+}
+
+impl KeyFilterBackendFactory {
+    pub fn new(inner: Box<dyn CacheBackendFactory>, filter: Arc<KeyFilter>) -> Self {
+        Self { inner, filter }
+    }
+}
+
+impl CacheBackendFactory for KeyFilterBackendFactory {
+    fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>> {
+        Ok(Box::new(KeyFilterBackend {
+            inner: self.inner.get_instance()?,
+            filter: self.filter.clone(),
+        }))
+    }
+}
+
+struct KeyFilterBackend {
+    inner: Box<dyn CacheBackend>,
+    filter: Arc<KeyFilter>,
+}
+
+impl CacheBackend for KeyFilterBackend {
+    fn key(&self, method: &str, params_key: &str) -> String {
+        self.inner.key(method, params_key)
+    }
+
+    fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus> {
+        let key = self.key(method, params_key);
+
+        match self.filter.may_contain(&key) {
+            true => self.inner.read(method, params_key),
+            false => Ok(CacheStatus::Missed { key }),
+        }
+    }
+
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        self.filter.insert(key);
+        self.inner.write(key, value, ttl)
+    }
+
+    fn try_lock(&mut self, key: &str, ttl: Duration) -> anyhow::Result<bool> {
+        self.inner.try_lock(key, ttl)
+    }
+
+    fn unlock(&mut self, key: &str) -> anyhow::Result<()> {
+        self.inner.unlock(key)
+    }
+
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64> {
+        self.inner.incr(key, delta, ttl)
+    }
+
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()> {
+        self.inner.scan_keys(visit)
+    }
+
+    fn collect_garbage(
+        &mut self,
+        is_stale: &dyn Fn(&str, &str) -> bool,
+    ) -> anyhow::Result<GcStats> {
+        self.inner.collect_garbage(is_stale)
+    }
+
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()> {
+        self.inner.export(visit)
+    }
+
+    fn stored_bytes(&mut self) -> anyhow::Result<Option<u64>> {
+        self.inner.stored_bytes()
+    }
+
+    fn evict_oldest(&mut self, max_bytes: u64) -> anyhow::Result<GcStats> {
+        self.inner.evict_oldest(max_bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+
+    #[test]
+    fn test_may_contain() {
+        let filter = KeyFilter::new(&KeyFilterConfig {
+            expected_keys: 1000,
+            false_positive_rate: 0.01,
+        });
+        filter.insert("eth_chainId:");
+        assert!(filter.may_contain("eth_blockNumber:"));
+
+        filter.ready.store(true, Ordering::Relaxed);
+        assert!(filter.may_contain("eth_chainId:"));
+
+        let false_positives = (0..1000)
+            .filter(|i| filter.may_contain(&format!("eth_getBalance:{i}")))
+            .count();
+        assert!(false_positives < 50, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let inner = MemoryBackendFactory::new();
+        inner
+            .get_instance()
+            .unwrap()
+            .write("eth_chainId:", "\"0x1\"", None)
+            .unwrap();
+
+        let filter = Arc::new(KeyFilter::new(&KeyFilterConfig {
+            expected_keys: 1000,
+            false_positive_rate: 0.01,
+        }));
+        let factory = KeyFilterBackendFactory::new(Box::new(inner), filter.clone());
+        let mut backend = factory.get_instance().unwrap();
+
+        assert_eq!(filter.warm_up(&mut *backend).unwrap(), 1);
+        assert!(matches!(
+            backend.read("eth_chainId", "").unwrap(),
+            CacheStatus::Cached { .. }
+        ));
+
+        backend.write("eth_blockNumber:", "\"0x2\"", None).unwrap();
+        assert!(matches!(
+            backend.read("eth_blockNumber", "").unwrap(),
+            CacheStatus::Cached { .. }
+        ));
+    }
+}
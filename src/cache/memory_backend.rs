@@ -1,13 +1,19 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use dashmap::DashMap;
 use serde_json::{from_str, Value};
 
-use super::{CacheBackend, CacheBackendFactory, CacheStatus};
+use super::{CacheBackend, CacheBackendFactory, CacheStatus, GcStats};
+
+struct MemoryEntry {
+    value: String,
+    expires_at: Option<Instant>,
+}
 
 pub struct MemoryBackendFactory {
-    data: Arc<DashMap<String, String>>,
+    data: Arc<DashMap<String, MemoryEntry>>,
 }
 
 impl MemoryBackendFactory {
@@ -27,16 +33,28 @@ impl CacheBackendFactory for MemoryBackendFactory {
 }
 
 pub struct MemoryBackend {
-    data: Arc<DashMap<String, String>>,
+    data: Arc<DashMap<String, MemoryEntry>>,
 }
 
 impl CacheBackend for MemoryBackend {
+    fn key(&self, method: &str, params_key: &str) -> String {
+        format!("{method}:{params_key}")
+    }
+
     fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus> {
-        let key = format!("{method}:{params_key}");
+        let key = self.key(method, params_key);
+
+        // Expired entries are dropped lazily on read.
+        self.data.remove_if(&key, |_, entry| {
+            entry
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= Instant::now())
+        });
 
         let v = match self.data.get(&key) {
-            Some(value) => {
-                let value = from_str::<Value>(&value).context("fail to deserialize cache value")?;
+            Some(entry) => {
+                let value =
+                    from_str::<Value>(&entry.value).context("fail to deserialize cache value")?;
 
                 CacheStatus::Cached { key, value }
             }
@@ -47,8 +65,127 @@ impl CacheBackend for MemoryBackend {
         Ok(v)
     }
 
-    fn write(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
-        let _ = self.data.insert(key.to_string(), value.to_string());
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        let entry = MemoryEntry {
+            value: value.to_string(),
+            expires_at: ttl.map(|ttl| Instant::now() + ttl),
+        };
+
+        let _ = self.data.insert(key.to_string(), entry);
+        Ok(())
+    }
+
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64> {
+        let now = Instant::now();
+        let new_counter = || MemoryEntry {
+            value: "0".to_string(),
+            expires_at: Some(now + ttl),
+        };
+
+        let mut entry = self.data.entry(key.to_string()).or_insert_with(new_counter);
+        if entry.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            *entry = new_counter();
+        }
+
+        let count = entry
+            .value
+            .parse::<u64>()
+            .context("fail to parse counter")?
+            + delta;
+        entry.value = count.to_string();
+
+        Ok(count)
+    }
+
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()> {
+        for entry in self.data.iter() {
+            visit(entry.key());
+        }
+        Ok(())
+    }
+
+    fn collect_garbage(
+        &mut self,
+        is_stale: &dyn Fn(&str, &str) -> bool,
+    ) -> anyhow::Result<GcStats> {
+        let now = Instant::now();
+        let mut stats = GcStats::default();
+
+        self.data.retain(|key, entry| {
+            let expired = entry.expires_at.is_some_and(|expires_at| expires_at <= now);
+            let stale = key
+                .split_once(':')
+                .is_some_and(|(method, params_key)| is_stale(method, params_key));
+
+            if expired || stale {
+                stats.entries += 1;
+                stats.bytes += (key.len() + entry.value.len()) as u64;
+            }
+
+            !(expired || stale)
+        });
+
+        Ok(stats)
+    }
+
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()> {
+        for entry in self.data.iter() {
+            if entry.expires_at.is_some() {
+                continue;
+            }
+            if let Some((method, params_key)) = entry.key().split_once(':') {
+                visit(method, params_key, &entry.value);
+            }
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut backend = MemoryBackendFactory::new().get_instance().unwrap();
+        backend
+            .write("eth_getBlockByNumber:0x1", "{}", None)
+            .unwrap();
+        backend
+            .write("eth_getBlockByNumber:0x2", "{}", None)
+            .unwrap();
+        backend
+            .write("eth_chainId:", "\"0x1\"", Some(Duration::ZERO))
+            .unwrap();
+
+        let stats = backend
+            .collect_garbage(&|_, params_key| params_key == "0x1")
+            .unwrap();
+        assert_eq!(
+            stats,
+            GcStats {
+                entries: 2,
+                bytes: 43
+            }
+        );
+
+        assert!(matches!(
+            backend.read("eth_getBlockByNumber", "0x2").unwrap(),
+            CacheStatus::Cached { .. }
+        ));
+    }
+
+    #[test]
+    fn test_incr() {
+        let mut backend = MemoryBackendFactory::new().get_instance().unwrap();
+        let ttl = Duration::from_secs(10);
+
+        assert_eq!(backend.incr("requests", 2, ttl).unwrap(), 2);
+        assert_eq!(backend.incr("requests", 3, ttl).unwrap(), 5);
+        assert_eq!(backend.incr("bytes", 0, ttl).unwrap(), 0);
+
+        // Expired counters start over.
+        assert_eq!(backend.incr("expired", 1, Duration::ZERO).unwrap(), 1);
+        assert_eq!(backend.incr("expired", 1, Duration::ZERO).unwrap(), 1);
+    }
+}
@@ -1,18 +1,120 @@
+pub mod key_filter;
 pub mod memory_backend;
+pub mod mode;
 pub mod redis_backend;
+pub mod replication;
 
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::upstream::Upstream;
+
 pub enum CacheStatus {
     Cached { key: String, value: Value },
     Missed { key: String },
 }
 
+/// Entries deleted by a garbage collection run and the size of their keys and values.
+#[derive(Debug, Default, PartialEq)]
+pub struct GcStats {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// When and where a cached result was fetched, to help diagnosing stale entries.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EntryMetadata {
+    /// Unix timestamp in seconds.
+    pub stored_at: u64,
+    /// Host of the upstream the result was fetched from. The full url may contain api keys.
+    pub upstream: Option<String>,
+    /// Head of the chain when the result was stored, if known.
+    pub head_block: Option<u64>,
+}
+
+impl EntryMetadata {
+    pub fn new(source: &Upstream, head_block: Option<u64>) -> Self {
+        let stored_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self {
+            stored_at,
+            upstream: source.url.host_str().map(str::to_string),
+            head_block,
+        }
+    }
+}
+
+/// Every connection of the pool is in use and none was returned in time.
+#[derive(Debug)]
+pub struct PoolExhausted;
+
+impl fmt::Display for PoolExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cache connection pool exhausted")
+    }
+}
+
+impl std::error::Error for PoolExhausted {}
+
 pub trait CacheBackendFactory: Send + Sync {
     fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>>;
 }
 
+impl<T: CacheBackendFactory + ?Sized> CacheBackendFactory for Arc<T> {
+    fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>> {
+        (**self).get_instance()
+    }
+}
+
 pub trait CacheBackend {
+    /// Full key of the entry storing results of `method` for requests with `params_key`.
+    fn key(&self, method: &str, params_key: &str) -> String;
+
     fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus>;
-    fn write(&mut self, key: &str, value: &str) -> anyhow::Result<()>;
+    /// Entries without `ttl` are kept forever.
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()>;
+
+    /// Locks `key` for `ttl` across the instances sharing the backend. Returns `false` if another instance holds
+    /// it. Backends local to this instance have nothing to lock.
+    fn try_lock(&mut self, _key: &str, _ttl: Duration) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    /// Releases a lock taken by this instance with [`CacheBackend::try_lock`].
+    fn unlock(&mut self, _key: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Adds `delta` to the counter at `key`, created with `ttl` if missing, and returns the new count.
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64>;
+
+    /// Visits the full key of every entry.
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()>;
+
+    /// Deletes the entries `is_stale(method, params_key)` flags, along with expired entries the backend
+    /// doesn't expire by itself.
+    fn collect_garbage(&mut self, is_stale: &dyn Fn(&str, &str) -> bool)
+        -> anyhow::Result<GcStats>;
+
+    /// Visits the method, params key and value of every entry kept forever, e.g. to back up the cache.
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()>;
+
+    /// Approximate size of the keys and values of the entries kept forever, `None` if the backend doesn't track
+    /// it.
+    fn stored_bytes(&mut self) -> anyhow::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Deletes the oldest entries kept forever until they fit in `max_bytes`. Backends that don't track their
+    /// size bound it otherwise, if at all.
+    fn evict_oldest(&mut self, _max_bytes: u64) -> anyhow::Result<GcStats> {
+        Ok(GcStats::default())
+    }
 }
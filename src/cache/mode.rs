@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use super::{CacheBackend, CacheBackendFactory, CacheStatus, GcStats};
+
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    #[default]
+    ReadWrite,
+
+    /// Serve cached results but never write, e.g. replicas sharing a cache filled by another instance.
+    ReadOnly,
+
+    /// Write results but never serve from the cache, e.g. an instance filling a cache shared by replicas.
+    WriteOnly,
+}
+
+/// Restricts the backends of `inner` to what the mode allows.
+pub struct ModeBackendFactory {
+    inner: Box<dyn CacheBackendFactory>,
+    mode: CacheMode,
+}
+
+impl ModeBackendFactory {
+    pub fn new(inner: Box<dyn CacheBackendFactory>, mode: CacheMode) -> Self {
+        Self { inner, mode }
+    }
+}
+
+impl CacheBackendFactory for ModeBackendFactory {
+    fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>> {
+        Ok(Box::new(ModeBackend {
+            inner: self.inner.get_instance()?,
+            mode: self.mode,
+        }))
+    }
+}
+
+struct ModeBackend {
+    inner: Box<dyn CacheBackend>,
+    mode: CacheMode,
+}
+
+impl CacheBackend for ModeBackend {
+    fn key(&self, method: &str, params_key: &str) -> String {
+        self.inner.key(method, params_key)
+    }
+
+    fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus> {
+        match self.mode {
+            CacheMode::WriteOnly => Ok(CacheStatus::Missed {
+                key: self.key(method, params_key),
+            }),
+            _ => self.inner.read(method, params_key),
+        }
+    }
+
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        match self.mode {
+            CacheMode::ReadOnly => Ok(()),
+            _ => self.inner.write(key, value, ttl),
+        }
+    }
+
+    fn try_lock(&mut self, key: &str, ttl: Duration) -> anyhow::Result<bool> {
+        match self.mode {
+            // Results fetched by read-only instances are never cached, so others shouldn't wait for them.
+            CacheMode::ReadOnly => Ok(true),
+            _ => self.inner.try_lock(key, ttl),
+        }
+    }
+
+    fn unlock(&mut self, key: &str) -> anyhow::Result<()> {
+        match self.mode {
+            CacheMode::ReadOnly => Ok(()),
+            _ => self.inner.unlock(key),
+        }
+    }
+
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64> {
+        self.inner.incr(key, delta, ttl)
+    }
+
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()> {
+        self.inner.scan_keys(visit)
+    }
+
+    fn collect_garbage(
+        &mut self,
+        is_stale: &dyn Fn(&str, &str) -> bool,
+    ) -> anyhow::Result<GcStats> {
+        match self.mode {
+            CacheMode::ReadOnly => Ok(GcStats::default()),
+            _ => self.inner.collect_garbage(is_stale),
+        }
+    }
+
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()> {
+        self.inner.export(visit)
+    }
+
+    fn stored_bytes(&mut self) -> anyhow::Result<Option<u64>> {
+        self.inner.stored_bytes()
+    }
+
+    fn evict_oldest(&mut self, max_bytes: u64) -> anyhow::Result<GcStats> {
+        match self.mode {
+            CacheMode::ReadOnly => Ok(GcStats::default()),
+            _ => self.inner.evict_oldest(max_bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+
+    fn backend(mode: CacheMode) -> ModeBackend {
+        ModeBackend {
+            inner: MemoryBackendFactory::new().get_instance().unwrap(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut backend = backend(CacheMode::ReadOnly);
+        backend.write("eth_chainId:", "\"0x1\"", None).unwrap();
+
+        assert!(matches!(
+            backend.inner.read("eth_chainId", "").unwrap(),
+            CacheStatus::Missed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_write_only() {
+        let mut backend = backend(CacheMode::WriteOnly);
+        backend.write("eth_chainId:", "\"0x1\"", None).unwrap();
+
+        assert!(matches!(
+            backend.read("eth_chainId", "").unwrap(),
+            CacheStatus::Missed { .. }
+        ));
+        assert!(matches!(
+            backend.inner.read("eth_chainId", "").unwrap(),
+            CacheStatus::Cached { .. }
+        ));
+    }
+}
@@ -1,37 +1,166 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use anyhow::Context;
 use redis::Commands;
 use serde_json::{from_str, Value};
 
-use super::{CacheBackend, CacheBackendFactory, CacheStatus};
+use super::{CacheBackend, CacheBackendFactory, CacheStatus, GcStats, PoolExhausted};
+
+/// Deletes a lock only if it's still held by the instance, in case it expired and was taken by another one.
+const UNLOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Stores an entry kept forever and accounts for it: the entry joins the set of entries by write time and the
+/// byte counter is adjusted by the difference in size, or by the full size of the key and value if it's new.
+const TRACKED_WRITE_SCRIPT: &str = r#"
+local old_len = redis.call("STRLEN", KEYS[1])
+redis.call("SET", KEYS[1], ARGV[1])
+local delta = #ARGV[1] - old_len
+if redis.call("ZADD", KEYS[2], ARGV[2], KEYS[1]) == 1 then
+    delta = #ARGV[1] + #KEYS[1]
+end
+return redis.call("INCRBY", KEYS[3], delta)
+"#;
+
+/// Stores an entry with a ttl and takes it off the accounting if it was kept forever until now, since redis
+/// expires it without the byte counter knowing.
+const UNTRACKED_WRITE_SCRIPT: &str = r#"
+local old_len = redis.call("STRLEN", KEYS[1])
+redis.call("SET", KEYS[1], ARGV[1], "PX", ARGV[2])
+if redis.call("ZREM", KEYS[2], KEYS[1]) == 1 then
+    redis.call("DECRBY", KEYS[3], old_len + #KEYS[1])
+end
+return 0
+"#;
+
+/// Keys fetched per `SCAN` call, and thus deleted per batch at most, during garbage collection.
+const GC_BATCH_SIZE: usize = 1000;
 
 pub struct RedisBackendFactory {
-    chain_id: u64,
+    /// Prepended to every key, the chain id unless configured otherwise.
+    key_prefix: String,
     client: r2d2::Pool<redis::Client>,
+    /// Value of the locks taken by this instance, `None` if locking is disabled.
+    lock_token: Option<Arc<str>>,
+    /// Whether entries kept forever are accounted for, to enforce a quota on them.
+    track_bytes: bool,
 }
 
 impl RedisBackendFactory {
-    pub fn new(chain_id: u64, client: r2d2::Pool<redis::Client>) -> Self {
-        Self { chain_id, client }
+    pub fn new(
+        key_prefix: String,
+        client: r2d2::Pool<redis::Client>,
+        locking: bool,
+        track_bytes: bool,
+    ) -> Self {
+        let lock_token = locking.then(|| format!("{:016x}", rand::random::<u64>()).into());
+
+        Self {
+            key_prefix,
+            client,
+            lock_token,
+            track_bytes,
+        }
     }
 }
 
 impl CacheBackendFactory for RedisBackendFactory {
     fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>> {
+        let conn = self.client.get().map_err(|err| {
+            let state = self.client.state();
+            if state.connections == self.client.max_size() && state.idle_connections == 0 {
+                anyhow::Error::new(PoolExhausted).context(err)
+            } else {
+                err.into()
+            }
+        })?;
+
         Ok(Box::new(RedisBackend {
-            chain_id: self.chain_id,
-            conn: self.client.get()?,
+            key_prefix: self.key_prefix.clone(),
+            conn,
+            lock_token: self.lock_token.clone(),
+            track_bytes: self.track_bytes,
         }))
     }
 }
 
 pub struct RedisBackend {
-    chain_id: u64,
+    key_prefix: String,
     conn: r2d2::PooledConnection<redis::Client>,
+    lock_token: Option<Arc<str>>,
+    track_bytes: bool,
+}
+
+impl RedisBackend {
+    /// Sorted set of the entries kept forever, scored by write time in milliseconds. Like the locks, the
+    /// accounting keys live outside of `{key_prefix}:*`.
+    fn stored_at_key(&self) -> String {
+        format!("stored-at:{}", self.key_prefix)
+    }
+
+    fn stored_bytes_key(&self) -> String {
+        format!("stored-bytes:{}", self.key_prefix)
+    }
+
+    /// Deletes `keys` and returns the number of entries deleted and their size. Entries that were accounted for
+    /// are taken off the accounting.
+    fn delete(&mut self, keys: &[String]) -> anyhow::Result<GcStats> {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("STRLEN").arg(key);
+        }
+        let value_lengths: Vec<u64> = pipe.query(&mut *self.conn)?;
+
+        let deleted: u64 = redis::cmd("DEL")
+            .arg(keys)
+            .query(&mut *self.conn)
+            .context("fail to delete keys")?;
+
+        let sizes = keys
+            .iter()
+            .zip(&value_lengths)
+            .map(|(key, value_len)| key.len() as u64 + value_len)
+            .collect::<Vec<_>>();
+
+        if self.track_bytes {
+            let mut pipe = redis::pipe();
+            for key in keys {
+                pipe.cmd("ZREM").arg(self.stored_at_key()).arg(key);
+            }
+            let tracked: Vec<u64> = pipe.query(&mut *self.conn)?;
+
+            let tracked_bytes = sizes
+                .iter()
+                .zip(&tracked)
+                .filter(|(_, tracked)| **tracked == 1)
+                .map(|(size, _)| size)
+                .sum::<u64>();
+            if tracked_bytes > 0 {
+                self.conn
+                    .decr::<_, _, ()>(self.stored_bytes_key(), tracked_bytes)
+                    .context("fail to update stored bytes")?;
+            }
+        }
+
+        Ok(GcStats {
+            entries: deleted,
+            bytes: sizes.iter().sum(),
+        })
+    }
 }
 
 impl CacheBackend for RedisBackend {
+    fn key(&self, method: &str, params_key: &str) -> String {
+        format!("{}:{method}:{params_key}", self.key_prefix)
+    }
+
     fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus> {
-        let cache_key = format!("{}:{method}:{params_key}", self.chain_id);
+        let cache_key = self.key(method, params_key);
         let value: Option<String> = self.conn.get(&cache_key)?;
 
         let v = match value {
@@ -48,8 +177,325 @@ impl CacheBackend for RedisBackend {
         Ok(v)
     }
 
-    fn write(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
-        let _ = self.conn.set::<_, _, String>(key, value);
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        let _ = match ttl {
+            Some(ttl) if self.track_bytes => redis::Script::new(UNTRACKED_WRITE_SCRIPT)
+                .key(key)
+                .key(self.stored_at_key())
+                .key(self.stored_bytes_key())
+                .arg(value)
+                .arg(ttl.as_millis().max(1) as u64)
+                .invoke::<()>(&mut *self.conn),
+            Some(ttl) => redis::cmd("SET")
+                .arg(key)
+                .arg(value)
+                .arg("PX")
+                .arg(ttl.as_millis().max(1) as u64)
+                .query::<()>(&mut *self.conn),
+            None if self.track_bytes => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                redis::Script::new(TRACKED_WRITE_SCRIPT)
+                    .key(key)
+                    .key(self.stored_at_key())
+                    .key(self.stored_bytes_key())
+                    .arg(value)
+                    .arg(now)
+                    .invoke::<()>(&mut *self.conn)
+            }
+            None => self.conn.set::<_, _, ()>(key, value),
+        };
+        Ok(())
+    }
+
+    fn try_lock(&mut self, key: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let Some(lock_token) = &self.lock_token else {
+            return Ok(true);
+        };
+
+        // Locks live outside of `{key_prefix}:*` so that garbage collection doesn't scan them.
+        let locked: Option<String> = redis::cmd("SET")
+            .arg(format!("lock:{key}"))
+            .arg(&**lock_token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query(&mut *self.conn)
+            .context("fail to take lock")?;
+
+        Ok(locked.is_some())
+    }
+
+    fn unlock(&mut self, key: &str) -> anyhow::Result<()> {
+        let Some(lock_token) = &self.lock_token else {
+            return Ok(());
+        };
+
+        redis::Script::new(UNLOCK_SCRIPT)
+            .key(format!("lock:{key}"))
+            .arg(&**lock_token)
+            .invoke::<()>(&mut *self.conn)
+            .context("fail to release lock")
+    }
+
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64> {
+        let count: u64 = self.conn.incr(key, delta)?;
+        if count == delta {
+            self.conn
+                .pexpire::<_, ()>(key, ttl.as_millis().max(1) as i64)?;
+        }
+        Ok(count)
+    }
+
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()> {
+        let pattern = format!("{}:*", self.key_prefix);
+        let mut cursor = 0u64;
+
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(GC_BATCH_SIZE)
+                .query(&mut *self.conn)
+                .context("fail to scan keys")?;
+
+            keys.iter().for_each(|key| visit(key));
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn collect_garbage(
+        &mut self,
+        is_stale: &dyn Fn(&str, &str) -> bool,
+    ) -> anyhow::Result<GcStats> {
+        let pattern = format!("{}:*", self.key_prefix);
+        let mut stats = GcStats::default();
+        let mut cursor = 0u64;
+
+        // Redis expires TTL entries by itself, so only stale entries are deleted here.
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(GC_BATCH_SIZE)
+                .query(&mut *self.conn)
+                .context("fail to scan keys")?;
+
+            let stale_keys = keys
+                .into_iter()
+                .filter(|key| {
+                    let mut parts = key.splitn(3, ':').skip(1);
+                    match (parts.next(), parts.next()) {
+                        (Some(method), Some(params_key)) => is_stale(method, params_key),
+                        _ => false,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if !stale_keys.is_empty() {
+                let deleted = self.delete(&stale_keys)?;
+                stats.entries += deleted.entries;
+                stats.bytes += deleted.bytes;
+            }
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()> {
+        let mut keys = vec![];
+        self.scan_keys(&mut |key| keys.push(key.to_string()))?;
+
+        for keys in keys.chunks(GC_BATCH_SIZE) {
+            let mut pipe = redis::pipe();
+            for key in keys {
+                pipe.cmd("PTTL").arg(key);
+            }
+            let ttls: Vec<i64> = pipe.query(&mut *self.conn)?;
+            let values: Vec<Option<String>> = redis::cmd("MGET")
+                .arg(keys)
+                .query(&mut *self.conn)
+                .context("fail to read entries")?;
+
+            // Entries with a ttl, expired in the meantime or of other types are left out.
+            for ((key, ttl), value) in keys.iter().zip(ttls).zip(values) {
+                let mut parts = key.splitn(3, ':').skip(1);
+                if let (-1, Some(value), Some(method), Some(params_key)) =
+                    (ttl, value, parts.next(), parts.next())
+                {
+                    visit(method, params_key, &value);
+                }
+            }
+        }
+
         Ok(())
     }
+
+    fn stored_bytes(&mut self) -> anyhow::Result<Option<u64>> {
+        if !self.track_bytes {
+            return Ok(None);
+        }
+
+        let bytes: Option<i64> = self
+            .conn
+            .get(self.stored_bytes_key())
+            .context("fail to read stored bytes")?;
+        Ok(Some(bytes.unwrap_or_default().max(0) as u64))
+    }
+
+    fn evict_oldest(&mut self, max_bytes: u64) -> anyhow::Result<GcStats> {
+        let mut stats = GcStats::default();
+
+        while let Some(excess) = self
+            .stored_bytes()?
+            .and_then(|bytes| bytes.checked_sub(max_bytes))
+            .filter(|excess| *excess > 0)
+        {
+            let mut oldest: Vec<String> = self
+                .conn
+                .zrange(self.stored_at_key(), 0, GC_BATCH_SIZE as isize - 1)
+                .context("fail to list oldest entries")?;
+
+            // Nothing left to evict, the counter drifted, e.g. because accounted entries were deleted by hand.
+            if oldest.is_empty() {
+                self.conn
+                    .set::<_, _, ()>(self.stored_bytes_key(), 0)
+                    .context("fail to reset stored bytes")?;
+                break;
+            }
+
+            // Only as many of the oldest entries as it takes to get back under the quota are evicted.
+            let mut pipe = redis::pipe();
+            for key in &oldest {
+                pipe.cmd("STRLEN").arg(key);
+            }
+            let value_lengths: Vec<u64> = pipe.query(&mut *self.conn)?;
+            let mut freed = 0;
+            let needed = oldest
+                .iter()
+                .zip(value_lengths)
+                .take_while(|(key, value_len)| {
+                    let enough = freed >= excess;
+                    freed += key.len() as u64 + value_len;
+                    !enough
+                })
+                .count();
+            oldest.truncate(needed);
+
+            let evicted = self.delete(&oldest)?;
+            stats.entries += evicted.entries;
+            stats.bytes += evicted.bytes;
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A backend of a fresh key prefix on the redis instance of `REDIS_URL`, `None` if it's unset so that the
+    /// tests pass without a redis instance.
+    fn backend() -> Option<RedisBackend> {
+        let url = std::env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url).unwrap();
+        let pool = r2d2::Pool::builder().max_size(1).build(client).unwrap();
+
+        Some(RedisBackend {
+            key_prefix: format!("test-{:016x}", rand::random::<u64>()),
+            conn: pool.get().unwrap(),
+            lock_token: None,
+            track_bytes: true,
+        })
+    }
+
+    fn cleanup(backend: &mut RedisBackend) {
+        let mut keys = vec![backend.stored_at_key(), backend.stored_bytes_key()];
+        backend
+            .scan_keys(&mut |key| keys.push(key.to_string()))
+            .unwrap();
+        redis::cmd("DEL")
+            .arg(keys)
+            .query::<()>(&mut *backend.conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_accounting() {
+        let Some(mut backend) = backend() else {
+            return;
+        };
+
+        let key = backend.key("eth_getBalance", "0x10-0xab");
+        backend.write(&key, "\"0x1\"", None).unwrap();
+        assert_eq!(backend.stored_bytes().unwrap(), Some(key.len() as u64 + 5));
+
+        backend.write(&key, "\"0x100\"", None).unwrap();
+        assert_eq!(backend.stored_bytes().unwrap(), Some(key.len() as u64 + 7));
+
+        // Entries with a ttl expire without the accounting knowing, so they are taken off it.
+        let ttl = Some(Duration::from_secs(60));
+        backend.write(&key, "\"0x100\"", ttl).unwrap();
+        assert_eq!(backend.stored_bytes().unwrap(), Some(0));
+        let stored_at_key = backend.stored_at_key();
+        let tracked: u64 = backend.conn.zcard(stored_at_key).unwrap();
+        assert_eq!(tracked, 0);
+
+        let other_key = backend.key("eth_getBalance", "0x11-0xab");
+        backend.write(&other_key, "\"0x2\"", ttl).unwrap();
+        assert_eq!(backend.stored_bytes().unwrap(), Some(0));
+
+        cleanup(&mut backend);
+    }
+
+    #[test]
+    fn test_evict_oldest() {
+        let Some(mut backend) = backend() else {
+            return;
+        };
+
+        let keys = (0..3)
+            .map(|block| backend.key("eth_getBlockByNumber", &format!("{block:#x}-false")))
+            .collect::<Vec<_>>();
+        for key in &keys {
+            backend.write(key, "\"0x1\"", None).unwrap();
+        }
+        let entry_bytes = keys[0].len() as u64 + 5;
+
+        // Rewritten with a ttl, the entry isn't evicted anymore.
+        backend
+            .write(&keys[0], "\"0x1\"", Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(backend.stored_bytes().unwrap(), Some(2 * entry_bytes));
+
+        // Evicting the oldest entry is enough to fit the quota, the newest one survives.
+        let evicted = backend.evict_oldest(entry_bytes).unwrap();
+        assert_eq!(evicted.entries, 1);
+        assert_eq!(evicted.bytes, entry_bytes);
+        assert_eq!(backend.stored_bytes().unwrap(), Some(entry_bytes));
+
+        let exists: Vec<bool> = keys
+            .iter()
+            .map(|key| backend.conn.exists(key).unwrap())
+            .collect();
+        assert_eq!(exists, vec![true, false, true]);
+
+        cleanup(&mut backend);
+    }
 }
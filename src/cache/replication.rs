@@ -0,0 +1,165 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CacheBackend, CacheBackendFactory, CacheStatus, GcStats};
+
+/// Writes waiting to be sent to the peers at most, further writes are dropped until the queue is sent.
+const MAX_QUEUED_WRITES: usize = 10_000;
+
+/// A cache write of an instance, replayed by its peers.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReplicatedWrite {
+    pub key: String,
+    pub value: String,
+    /// Kept forever if unset.
+    pub ttl_ms: Option<u64>,
+}
+
+/// Writes of this instance waiting to be sent to its peers.
+#[derive(Default)]
+pub struct WriteQueue {
+    writes: Mutex<Vec<ReplicatedWrite>>,
+    /// Writes not replicated because the queue was full.
+    pub dropped: AtomicU64,
+}
+
+impl WriteQueue {
+    fn push(&self, write: ReplicatedWrite) {
+        let mut writes = self.writes.lock().unwrap();
+        if writes.len() >= MAX_QUEUED_WRITES {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        writes.push(write);
+    }
+
+    /// Empties the queue.
+    pub fn take(&self) -> Vec<ReplicatedWrite> {
+        std::mem::take(&mut *self.writes.lock().unwrap())
+    }
+}
+
+/// Queues the writes to the backends of `inner` for the peers of the instance.
+pub struct ReplicatedBackendFactory {
+    inner: Arc<dyn CacheBackendFactory>,
+    queue: Arc<WriteQueue>,
+}
+
+impl ReplicatedBackendFactory {
+    pub fn new(inner: Arc<dyn CacheBackendFactory>, queue: Arc<WriteQueue>) -> Self {
+        Self { inner, queue }
+    }
+}
+
+impl CacheBackendFactory for ReplicatedBackendFactory {
+    fn get_instance(&self) -> anyhow::Result<Box<dyn CacheBackend>> {
+        Ok(Box::new(ReplicatedBackend {
+            inner: self.inner.get_instance()?,
+            queue: self.queue.clone(),
+        }))
+    }
+}
+
+struct ReplicatedBackend {
+    inner: Box<dyn CacheBackend>,
+    queue: Arc<WriteQueue>,
+}
+
+impl CacheBackend for ReplicatedBackend {
+    fn key(&self, method: &str, params_key: &str) -> String {
+        self.inner.key(method, params_key)
+    }
+
+    fn read(&mut self, method: &str, params_key: &str) -> anyhow::Result<CacheStatus> {
+        self.inner.read(method, params_key)
+    }
+
+    fn write(&mut self, key: &str, value: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        self.inner.write(key, value, ttl)?;
+        self.queue.push(ReplicatedWrite {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_ms: ttl.map(|ttl| ttl.as_millis() as u64),
+        });
+        Ok(())
+    }
+
+    fn try_lock(&mut self, key: &str, ttl: Duration) -> anyhow::Result<bool> {
+        self.inner.try_lock(key, ttl)
+    }
+
+    fn unlock(&mut self, key: &str) -> anyhow::Result<()> {
+        self.inner.unlock(key)
+    }
+
+    fn incr(&mut self, key: &str, delta: u64, ttl: Duration) -> anyhow::Result<u64> {
+        self.inner.incr(key, delta, ttl)
+    }
+
+    fn scan_keys(&mut self, visit: &mut dyn FnMut(&str)) -> anyhow::Result<()> {
+        self.inner.scan_keys(visit)
+    }
+
+    fn collect_garbage(
+        &mut self,
+        is_stale: &dyn Fn(&str, &str) -> bool,
+    ) -> anyhow::Result<GcStats> {
+        self.inner.collect_garbage(is_stale)
+    }
+
+    fn export(&mut self, visit: &mut dyn FnMut(&str, &str, &str)) -> anyhow::Result<()> {
+        self.inner.export(visit)
+    }
+
+    fn stored_bytes(&mut self) -> anyhow::Result<Option<u64>> {
+        self.inner.stored_bytes()
+    }
+
+    fn evict_oldest(&mut self, max_bytes: u64) -> anyhow::Result<GcStats> {
+        self.inner.evict_oldest(max_bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+
+    #[test]
+    fn test_write() {
+        let queue = Arc::new(WriteQueue::default());
+        let factory =
+            ReplicatedBackendFactory::new(Arc::new(MemoryBackendFactory::new()), queue.clone());
+        let mut backend = factory.get_instance().unwrap();
+
+        backend.write("eth_chainId:", "\"0x1\"", None).unwrap();
+        backend
+            .write("eth_blockNumber:", "\"0x10\"", Some(Duration::from_secs(2)))
+            .unwrap();
+        backend.incr("requests", 1, Duration::from_secs(1)).unwrap();
+
+        assert!(matches!(
+            backend.read("eth_chainId", "").unwrap(),
+            CacheStatus::Cached { .. }
+        ));
+        assert_eq!(
+            queue.take(),
+            vec![
+                ReplicatedWrite {
+                    key: "eth_chainId:".to_string(),
+                    value: "\"0x1\"".to_string(),
+                    ttl_ms: None,
+                },
+                ReplicatedWrite {
+                    key: "eth_blockNumber:".to_string(),
+                    value: "\"0x10\"".to_string(),
+                    ttl_ms: Some(2000),
+                },
+            ]
+        );
+        assert!(queue.take().is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::json_rpc::{JsonRpcRequest, RequestId};
+use crate::transport::UpstreamClient;
+use crate::upstream::Upstream;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CanaryConfig {
+    pub url: Url,
+
+    /// Fraction of cacheable requests answered by the upstreams that are mirrored to the canary.
+    pub sample_rate: f64,
+}
+
+/// An upstream that is sent copies of sampled requests, e.g. a node running a new client version, whose responses
+/// are compared to the ones clients got but never served.
+pub struct Canary {
+    upstream: Upstream,
+    sample_rate: f64,
+    pub requests: AtomicU64,
+    /// Requests the canary answered differently than the upstreams.
+    pub mismatches: AtomicU64,
+    /// Requests the canary failed to answer at all.
+    pub errors: AtomicU64,
+}
+
+/// A request answered by the upstreams, to be mirrored to the canary.
+pub struct Sample {
+    method: String,
+    params: Value,
+    response: Value,
+}
+
+impl Canary {
+    pub fn new(config: &CanaryConfig, http_client: &UpstreamClient) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&config.sample_rate),
+            "canary.sample_rate must be between 0 and 1"
+        );
+
+        Ok(Self {
+            upstream: Upstream::new(config.url.clone().into(), http_client)?,
+            sample_rate: config.sample_rate,
+            requests: Default::default(),
+            mismatches: Default::default(),
+            errors: Default::default(),
+        })
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.upstream.url
+    }
+
+    /// Picks the upstream `response` to a request at the sample rate.
+    pub fn sample(&self, method: &str, params: &Value, response: &Value) -> Option<Sample> {
+        (rand::random::<f64>() < self.sample_rate).then(|| Sample {
+            method: method.to_string(),
+            params: params.clone(),
+            response: response.clone(),
+        })
+    }
+
+    /// Mirrors `samples` to the canary one by one and reports the responses that differ.
+    pub async fn compare(self: Arc<Self>, samples: Vec<Sample>) {
+        // It's safe to unwrap here because a number is always a valid request id.
+        let request_id = RequestId::try_from(json!(1)).unwrap();
+
+        for sample in samples {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+
+            let request = JsonRpcRequest::new(Some(&request_id), &sample.method, &sample.params);
+            let response = match self.upstream.request(&request).await {
+                Ok(response) => response,
+                Err(err) => {
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(
+                        method = sample.method,
+                        "fail to mirror request to canary {}: {err:#}",
+                        self.upstream.url
+                    );
+                    continue;
+                }
+            };
+
+            if diverges(&sample.response, &response) {
+                self.mismatches.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    method = sample.method,
+                    params = format_args!("{}", sample.params),
+                    canary = self.upstream.url.as_str(),
+                    "canary diverges, upstream: {}, canary: {}",
+                    sample.response,
+                    response
+                );
+            }
+        }
+    }
+}
+
+/// Whether two responses to the same request differ. Errors only need to agree on the request failing, since
+/// clients word them differently.
+fn diverges(expected: &Value, actual: &Value) -> bool {
+    match (expected["error"].is_null(), actual["error"].is_null()) {
+        (true, true) => expected["result"] != actual["result"],
+        (false, false) => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diverges() {
+        let result = |result: Value| json!({ "jsonrpc": "2.0", "id": 1, "result": result });
+        let error = |message: &str| json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32000, "message": message } });
+
+        assert!(!diverges(&result(json!("0x1")), &result(json!("0x1"))));
+        assert!(diverges(&result(json!("0x1")), &result(json!("0x2"))));
+        assert!(diverges(&result(json!(null)), &error("header not found")));
+        assert!(!diverges(
+            &error("execution reverted"),
+            &error("execution reverted: 0x")
+        ));
+    }
+}
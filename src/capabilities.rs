@@ -0,0 +1,102 @@
+use std::collections::BTreeSet;
+
+use serde_json::{json, Value};
+
+use crate::upstream::Upstream;
+
+/// Namespaces of methods that clients commonly lack or disable, which are probed for.
+const OPTIONAL_NAMESPACES: [&str; 3] = ["debug", "trace", "ots"];
+
+/// Optional namespaces of well-known clients, by the lowercase name `web3_clientVersion` starts with. Used for
+/// upstreams that don't answer `rpc_modules`.
+const CLIENT_NAMESPACES: [(&str, &[&str]); 5] = [
+    ("geth", &["debug"]),
+    ("erigon", &["debug", "trace", "ots"]),
+    ("reth", &["debug", "trace", "ots"]),
+    ("nethermind", &["debug", "trace"]),
+    ("besu", &["debug", "trace"]),
+];
+
+/// Optional namespaces some of `upstreams` lack. Requests are spread across the upstreams, so a namespace is only
+/// usable if every upstream supports it. Upstreams whose namespaces can't be told are assumed to support them all.
+pub async fn unsupported_namespaces<'a>(
+    upstreams: impl IntoIterator<Item = &'a Upstream>,
+) -> BTreeSet<String> {
+    let mut unsupported = BTreeSet::new();
+
+    for upstream in upstreams {
+        let Some(supported) = probe(upstream).await else {
+            tracing::warn!(
+                "fail to tell the namespaces {} supports, assuming all of them",
+                upstream.url
+            );
+            continue;
+        };
+
+        for namespace in OPTIONAL_NAMESPACES {
+            if !supported.contains(namespace) && unsupported.insert(namespace.to_string()) {
+                tracing::info!("upstream {} doesn't support `{namespace}_*`", upstream.url);
+            }
+        }
+    }
+
+    unsupported
+}
+
+/// Namespaces supported by `upstream`, from `rpc_modules` or else from the client it runs.
+async fn probe(upstream: &Upstream) -> Option<BTreeSet<String>> {
+    if let Some(Value::Object(modules)) = request(upstream, "rpc_modules").await {
+        return Some(modules.keys().cloned().collect());
+    }
+
+    let client_version = request(upstream, "web3_clientVersion").await?;
+    client_namespaces(client_version.as_str()?)
+}
+
+fn client_namespaces(client_version: &str) -> Option<BTreeSet<String>> {
+    let client_version = client_version.to_lowercase();
+
+    CLIENT_NAMESPACES
+        .iter()
+        .find(|(client, _)| client_version.starts_with(client))
+        .map(|(_, namespaces)| {
+            namespaces
+                .iter()
+                .map(|namespace| namespace.to_string())
+                .collect()
+        })
+}
+
+async fn request(upstream: &Upstream, method: &str) -> Option<Value> {
+    let request_payload = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": [],
+        "id": 1
+    });
+
+    let mut response = upstream.request(&request_payload).await.ok()?;
+    match response["result"].take() {
+        Value::Null => None,
+        result => Some(result),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_namespaces() {
+        assert_eq!(
+            client_namespaces("Geth/v1.13.14-stable/linux-amd64/go1.21.7"),
+            Some(BTreeSet::from(["debug".to_string()]))
+        );
+        assert_eq!(
+            client_namespaces("erigon/2.58.1/linux-amd64/go1.21.5")
+                .map(|namespaces| namespaces.len()),
+            Some(3)
+        );
+        assert_eq!(client_namespaces("anvil/v0.2.0"), None);
+    }
+}
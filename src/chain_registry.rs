@@ -0,0 +1,59 @@
+/// Name of `--endpoint` entries whose chain is named after the chain id they serve, e.g. `auto=https://..`.
+pub const AUTO_ENDPOINT_NAME: &str = "AUTO";
+
+/// Names of well-known chains, after chainlist.org.
+const CHAINS: &[(u64, &str)] = &[
+    (1, "eth"),
+    (10, "optimism"),
+    (25, "cronos"),
+    (56, "bsc"),
+    (100, "gnosis"),
+    (137, "polygon"),
+    (250, "fantom"),
+    (324, "zksync"),
+    (1101, "polygon-zkevm"),
+    (1284, "moonbeam"),
+    (5000, "mantle"),
+    (8453, "base"),
+    (17000, "holesky"),
+    (42161, "arbitrum"),
+    (42170, "arbitrum-nova"),
+    (42220, "celo"),
+    (43114, "avalanche"),
+    (59144, "linea"),
+    (81457, "blast"),
+    (84532, "base-sepolia"),
+    (421614, "arbitrum-sepolia"),
+    (534352, "scroll"),
+    (11155111, "sepolia"),
+    (11155420, "optimism-sepolia"),
+];
+
+/// Name of the chain with `chain_id`, the chain id itself for chains missing from the registry.
+pub fn chain_name(chain_id: u64) -> String {
+    CHAINS
+        .iter()
+        .find(|(id, _)| *id == chain_id)
+        .map(|(_, name)| name.to_uppercase())
+        .unwrap_or_else(|| chain_id.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_chain_name() {
+        assert_eq!(chain_name(1), "ETH");
+        assert_eq!(chain_name(8453), "BASE");
+        assert_eq!(chain_name(123456789), "123456789");
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let names = CHAINS.iter().map(|(_, name)| name).collect::<HashSet<_>>();
+        assert_eq!(names.len(), CHAINS.len());
+    }
+}
@@ -0,0 +1,165 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use actix_web::HttpRequest;
+
+/// A network in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`. A bare address is a network of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let network = IpAddr::from_str(address).map_err(|e| format!("Invalid CIDR {s}: {e}"))?;
+        let max_prefix_len = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => u8::from_str(prefix_len)
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| format!("Invalid prefix length in {s}"))?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, canonical(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IPv4 clients of dual-stack sockets show up as IPv4-mapped IPv6 addresses.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    }
+}
+
+/// Address of the client behind `req`. `X-Forwarded-For` is only believed when the connection comes from one of
+/// `trusted_proxies`, and then read from the right, skipping the trusted proxies it went through, so that clients
+/// can't pass off an address of their choosing.
+pub fn resolve(req: &HttpRequest, trusted_proxies: &[Cidr]) -> Option<IpAddr> {
+    let peer_ip = canonical(req.peer_addr()?.ip());
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|cidr| cidr.contains(ip));
+
+    if !is_trusted(peer_ip) {
+        return Some(peer_ip);
+    }
+
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|address| IpAddr::from_str(address.trim()).map(canonical))
+        .collect::<Vec<_>>();
+
+    let mut client_ip = peer_ip;
+    for address in forwarded.into_iter().rev() {
+        // Anything left of a garbled entry may have been made up by the client.
+        let Ok(ip) = address else {
+            break;
+        };
+        client_ip = ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+
+    Some(client_ip)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn cidrs(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs.iter().map(|cidr| cidr.parse().unwrap()).collect()
+    }
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let req = TestRequest::default().peer_addr(SocketAddr::from_str(peer).unwrap());
+        match forwarded_for {
+            Some(forwarded_for) => req.insert_header(("x-forwarded-for", forwarded_for)),
+            None => req,
+        }
+        .to_http_request()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = Cidr::from_str("10.1.0.0/16").unwrap();
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+
+        let cidr = Cidr::from_str("fd00::/8").unwrap();
+        assert!(cidr.contains("fd12::1".parse().unwrap()));
+        assert!(!cidr.contains("fe80::1".parse().unwrap()));
+
+        assert!(Cidr::from_str("0.0.0.0/0")
+            .unwrap()
+            .contains("1.2.3.4".parse().unwrap()));
+        assert!(Cidr::from_str("1.2.3.4")
+            .unwrap()
+            .contains("1.2.3.4".parse().unwrap()));
+
+        assert!(Cidr::from_str("10.0.0.0/33").is_err());
+        assert!(Cidr::from_str("example.com/8").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let trusted = cidrs(&["10.0.0.0/8"]);
+        let ip = |ip: &str| Some(IpAddr::from_str(ip).unwrap());
+
+        // Untrusted peers can't spoof their address.
+        let req = request("1.2.3.4:1000", Some("5.6.7.8"));
+        assert_eq!(resolve(&req, &trusted), ip("1.2.3.4"));
+
+        let req = request("10.0.0.1:1000", Some("9.9.9.9, 5.6.7.8, 10.0.0.2"));
+        assert_eq!(resolve(&req, &trusted), ip("5.6.7.8"));
+
+        let req = request("10.0.0.1:1000", Some("garbage, 10.0.0.2"));
+        assert_eq!(resolve(&req, &trusted), ip("10.0.0.2"));
+
+        let req = request("10.0.0.1:1000", None);
+        assert_eq!(resolve(&req, &trusted), ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::HttpRequest;
+use anyhow::Context;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+
+/// Tags a request as `interactive` (the default) or `bulk`, e.g. backfill jobs.
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Bulk,
+}
+
+impl Priority {
+    pub fn of(req: &HttpRequest) -> Self {
+        match req.headers().get(PRIORITY_HEADER) {
+            Some(value) if value.as_bytes().eq_ignore_ascii_case(b"bulk") => Priority::Bulk,
+            _ => Priority::Interactive,
+        }
+    }
+}
+
+/// Bounds the upstream requests of a chain in flight at once, per chain and across all chains, so that a
+/// flood of cache misses can't overwhelm the upstreams. Excess requests queue for up to `queue_timeout`.
+pub struct UpstreamLimits {
+    chain: Option<Semaphore>,
+    global: Option<Arc<Semaphore>>,
+    queue_timeout: Duration,
+    /// Bulk requests queue here first, so that at most one of them is queued ahead of interactive requests.
+    bulk_lane: Mutex<()>,
+}
+
+/// Held for the duration of an upstream request.
+pub struct UpstreamPermit<'a> {
+    _chain: Option<SemaphorePermit<'a>>,
+    _global: Option<SemaphorePermit<'a>>,
+}
+
+impl UpstreamLimits {
+    pub fn new(
+        chain_limit: Option<usize>,
+        global: Option<Arc<Semaphore>>,
+        queue_timeout: Duration,
+    ) -> Self {
+        Self {
+            chain: chain_limit.map(Semaphore::new),
+            global,
+            queue_timeout,
+            bulk_lane: Mutex::new(()),
+        }
+    }
+
+    pub async fn acquire(&self, priority: Priority) -> anyhow::Result<UpstreamPermit<'_>> {
+        let acquire = async {
+            let _bulk_lane = match priority {
+                Priority::Bulk => Some(self.bulk_lane.lock().await),
+                Priority::Interactive => None,
+            };
+
+            // The chain's own slot comes first so that waiting for it doesn't block a global slot.
+            let chain = match &self.chain {
+                Some(semaphore) => Some(semaphore.acquire().await?),
+                None => None,
+            };
+            let global = match &self.global {
+                Some(semaphore) => Some(semaphore.acquire().await?),
+                None => None,
+            };
+
+            anyhow::Ok(UpstreamPermit {
+                _chain: chain,
+                _global: global,
+            })
+        };
+
+        actix_web::rt::time::timeout(self.queue_timeout, acquire)
+            .await
+            .context("timed out waiting for a free upstream request slot")?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_acquire() {
+        let global = Arc::new(Semaphore::new(2));
+        let limits = UpstreamLimits::new(Some(1), Some(global.clone()), Duration::from_millis(10));
+
+        let permit = limits.acquire(Priority::Interactive).await.unwrap();
+        assert_eq!(global.available_permits(), 1);
+        assert!(limits.acquire(Priority::Interactive).await.is_err());
+
+        drop(permit);
+        assert_eq!(global.available_permits(), 2);
+        assert!(limits.acquire(Priority::Interactive).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_bulk_lane() {
+        let limits = UpstreamLimits::new(Some(1), None, Duration::from_millis(10));
+        let permit = limits.acquire(Priority::Interactive).await.unwrap();
+
+        // A queued bulk request keeps other bulk requests out of the queue, not interactive ones.
+        let queued = limits.acquire(Priority::Bulk);
+        futures::pin_mut!(queued);
+        assert!(futures::poll!(&mut queued).is_pending());
+        assert!(limits.bulk_lane.try_lock().is_err());
+        assert!(limits.chain.as_ref().unwrap().try_acquire().is_err());
+
+        drop(permit);
+        assert!(queued.await.is_ok());
+        assert!(limits.bulk_lane.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_priority_of() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((PRIORITY_HEADER, "Bulk"))
+            .to_http_request();
+        assert_eq!(Priority::of(&req), Priority::Bulk);
+
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        assert_eq!(Priority::of(&req), Priority::Interactive);
+    }
+
+    #[actix_web::test]
+    async fn test_unlimited() {
+        let limits = UpstreamLimits::new(None, None, Duration::from_millis(10));
+
+        let _permits = (
+            limits.acquire(Priority::Interactive).await,
+            limits.acquire(Priority::Bulk).await,
+        );
+        assert!(limits.acquire(Priority::Bulk).await.is_ok());
+    }
+}
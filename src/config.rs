@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+
+use crate::cache::key_filter::KeyFilterConfig;
+use crate::canary::CanaryConfig;
+use crate::failure_injection::FailureInjectionConfig;
+use crate::gc::GcConfig;
+use crate::hot_keys::HotKeyRefreshConfig;
+use crate::log_index::LogIndexConfig;
+use crate::method_aliases::MethodAliasConfig;
+use crate::mirror::MirrorConfig;
+use crate::params_rewrite::ParamsRewriteConfig;
+use crate::quorum::QuorumConfig;
+use crate::rpc_cache_handler::common;
+use crate::rpc_cache_handler::declarative::DeclarativeHandlerConfig;
+use crate::rpc_cache_handler::wasm::WasmHandlerConfig;
+use crate::static_responses::StaticResponseConfig;
+use crate::stats::ComputeUnitsConfig;
+use crate::transactions::TransactionConfig;
+use crate::transport::UpstreamClient;
+use crate::upstream::{
+    HistoricalRouteConfig, LoadBalanceStrategy, MethodRouteConfig, UpstreamConfig,
+};
+
+/// Settings loaded from the file passed via `--config`. Chain names are matched case-insensitively
+/// against the names given in `--endpoint`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub chains: HashMap<String, ChainConfig>,
+
+    /// Handlers defined by key paths and cacheability conditions, available to all chains.
+    #[serde(default)]
+    pub handlers: Vec<DeclarativeHandlerConfig>,
+
+    /// Handlers loaded from WASM modules, available to all chains.
+    #[serde(default)]
+    pub wasm_handlers: Vec<WasmHandlerConfig>,
+
+    /// Cost of upstream requests per method, reported at `/stats/upstream-usage`.
+    #[serde(default)]
+    pub compute_units: ComputeUnitsConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("fail to read config file {}", path.display()))?;
+
+        let mut config: Config =
+            serde_yaml::from_str(&content).context("fail to parse config file")?;
+
+        config.chains = config
+            .chains
+            .into_iter()
+            .map(|(name, chain)| (name.to_uppercase(), chain))
+            .collect();
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, chain) in &self.chains {
+            // Shorter keys would need a truncated hash, letting different requests share an entry.
+            if let Some(max_len) = chain.max_params_key_len {
+                anyhow::ensure!(
+                    max_len >= common::HASHED_PARAMS_KEY_LEN,
+                    "max_params_key_len of `{name}` must be at least {}",
+                    common::HASHED_PARAMS_KEY_LEN
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn chain(&self, name: &str) -> ChainConfig {
+        self.chains.get(name).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Upstreams in addition to the ones given via `--endpoint`.
+    pub upstreams: Vec<UpstreamConfig>,
+
+    /// Other names the chain is served at, e.g. `mainnet` or `1` for `eth`. Case-insensitive like chain names.
+    pub aliases: Vec<String>,
+
+    /// How uncached batches are spread across the upstreams.
+    pub load_balance: LoadBalanceStrategy,
+
+    /// Methods sent to dedicated upstreams instead of the ones above, e.g. `debug_*` to an archive node.
+    pub routes: Vec<MethodRouteConfig>,
+
+    /// Requests for old blocks sent to dedicated upstreams, e.g. an archive node.
+    pub historical_route: Option<HistoricalRouteConfig>,
+
+    /// Public upstreams, e.g. from chainlist.org, tried only when every upstream a request is routed to failed.
+    /// Their results are never cached since their quality is unknown.
+    pub fallback_upstreams: Vec<UpstreamConfig>,
+
+    /// Seconds between polls of the latest block number.
+    pub head_poll_interval_secs: Option<u64>,
+
+    /// Used instead of querying it from the upstreams, required with `--offline`.
+    pub chain_id: Option<u64>,
+
+    /// Freezes the chain at this block: block tags resolve to it and later blocks are rejected.
+    pub pinned_block: Option<u64>,
+
+    /// Serve `latest` requests of handlers supporting it from the entry of the latest polled block number.
+    /// Responses may lag the chain by up to one poll interval.
+    pub resolve_latest_block: bool,
+
+    /// Match upstream responses whose ids differ in type from the request ids (`1` vs `"1"`) and hand
+    /// responses with unknown or missing ids to the remaining requests by position.
+    pub lenient_id_matching: bool,
+
+    /// Send upstreams sequential numbers as request ids instead of the ids of the clients, and map them back, for
+    /// upstreams mishandling string or mixed-type ids in batches.
+    pub sequential_upstream_ids: bool,
+
+    /// Most blocks an `eth_getLogs` request sent upstream may span. Larger ranges are split into sub-ranges whose
+    /// logs are merged, and confirmed sub-ranges are cached by themselves. Ranges are never split if unset.
+    pub max_logs_range: Option<u64>,
+
+    /// Answers `eth_getLogs` filters restricted to some addresses from cached chunks of the logs of each address,
+    /// so that filters over overlapping ranges or with different topics share them.
+    pub log_index: Option<LogIndexConfig>,
+
+    /// Serves `eth_newFilter`, `eth_getFilterChanges` and the other filter methods from the proxy itself by
+    /// following the head, for upstreams behind load balancers that don't keep filters on a single node.
+    pub emulate_filters: bool,
+
+    /// Retry batches answered with a rate-limit error on the next upstream of the pool instead of
+    /// forwarding the error. Transport errors are always retried.
+    pub retry_rate_limited: bool,
+
+    /// Methods whose permanent upstream errors, e.g. a reverted `eth_call` at a fixed block, are cached for
+    /// the given number of seconds. Errors are never cached for other methods.
+    pub error_cache_ttl_secs: HashMap<String, u64>,
+
+    /// Seconds a CDN in front may keep cache hits of single requests, a year for final results of methods whose
+    /// results never change and the TTL for the others by default. `0` disables the `Cache-Control` header for the
+    /// method.
+    pub http_max_age_secs: HashMap<String, u32>,
+
+    /// Blocks a result keyed by block number must be behind the head before it is cached. Results of
+    /// younger blocks are served but not cached since they may still be reorged away.
+    pub confirmation_depth: u64,
+
+    /// Redis settings of the chain, overriding `--redis-url`.
+    pub redis: RedisConfig,
+
+    /// Deletes expired and old entries from the cache periodically if set.
+    pub gc: Option<GcConfig>,
+
+    /// Answers reads of keys never written by this instance as misses without asking the cache, e.g. for backfills
+    /// hitting mostly new keys. Only for instances that are the only one writing to their cache.
+    pub key_filter: Option<KeyFilterConfig>,
+
+    /// Stores when, from which upstream and at which head every result was cached, see `/{chain}/inspect`.
+    pub store_metadata: bool,
+
+    /// Refreshes frequently hit entries with a TTL shortly before they expire if set.
+    pub hot_key_refresh: Option<HotKeyRefreshConfig>,
+
+    /// Upstream requests of the chain in flight at once, unbounded if unset.
+    pub max_concurrent_upstream_requests: Option<usize>,
+
+    /// Client request headers sent along to http upstreams, e.g. `x-forwarded-for`, which gets the client
+    /// address appended.
+    pub forward_headers: Vec<String>,
+
+    /// Sends uncached requests to several upstreams and only caches results enough of them agree on if set.
+    pub quorum: Option<QuorumConfig>,
+
+    /// Renames of methods applied before anything else, e.g. for upstreams serving `parity_*` as `trace_*`.
+    pub method_aliases: Vec<MethodAliasConfig>,
+
+    /// Params filled in for methods clients call without ones an upstream requires, applied after `method_aliases`.
+    pub params_rewrites: Vec<ParamsRewriteConfig>,
+
+    /// Fixed answers to matching requests, which never reach the cache or an upstream.
+    pub static_responses: Vec<StaticResponseConfig>,
+
+    /// How `eth_sendRawTransaction` is sent, which is never cached.
+    pub transactions: TransactionConfig,
+
+    /// Seconds pending results, e.g. of transactions not mined yet, are cached. They're not cached if unset.
+    pub pending_cache_ttl_secs: Option<u64>,
+
+    /// Params keys longer than this are hashed, e.g. of `eth_getLogs` with many addresses, keeping the block they
+    /// start with. Keys are kept as is if unset. At least 41, the length of the hash.
+    pub max_params_key_len: Option<usize>,
+
+    /// Breaks a fraction of upstream responses on purpose, e.g. to test how clients handle a failing upstream.
+    /// Only honored with `--failure-injection`, so that it can't be enabled in production by accident.
+    pub failure_injection: Option<FailureInjectionConfig>,
+
+    /// Upstream a sample of requests is mirrored to, e.g. a node running a new client version, to compare its
+    /// responses before cutting over to it.
+    pub canary: Option<CanaryConfig>,
+
+    /// Probes the upstreams for the optional namespaces they support (`debug`, `trace`, `ots`) at startup, with
+    /// `rpc_modules` or else `web3_clientVersion`. Methods of namespaces some upstream lacks fail at the proxy.
+    pub probe_capabilities: bool,
+
+    /// Url uncached batches are copied to without waiting for its responses, e.g. of a staging environment.
+    pub mirror: Option<MirrorConfig>,
+
+    pub http_client: HttpClientConfig,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    /// Redis instance of the chain, `--redis-url` if unset.
+    pub url: Option<String>,
+
+    /// Logical database, overriding the one in the url.
+    pub db: Option<i64>,
+
+    /// Prepended to the keys of the chain instead of the chain id. Chains with the same chain id need
+    /// different prefixes to keep their entries apart, e.g. when pointing at different nodes on purpose.
+    pub key_prefix: Option<String>,
+
+    /// Approximate bytes the entries of the chain kept forever may take, keys included. The oldest entries of
+    /// the chain are evicted beyond it, so that a busy chain can't push the entries of others out of a shared
+    /// instance.
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpClientConfig {
+    /// Maximum idle connections kept per upstream host.
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Seconds an idle pooled connection is kept before being closed.
+    pub pool_idle_timeout_secs: Option<u64>,
+
+    /// Talk HTTP/2 to the upstream without the HTTP/1.1 upgrade dance.
+    pub http2_prior_knowledge: bool,
+
+    /// Interval of TCP keepalive probes on upstream connections.
+    pub tcp_keepalive_secs: Option<u64>,
+
+    /// `User-Agent` sent to the upstreams of the chain.
+    pub user_agent: Option<String>,
+
+    /// Headers sent with every upstream request, e.g. ones identifying the caller to the provider.
+    pub headers: HashMap<String, String>,
+
+    /// Seconds an upstream request may take in total before it fails.
+    pub timeout_secs: Option<u64>,
+}
+
+impl HttpClientConfig {
+    pub fn build_client(&self) -> anyhow::Result<UpstreamClient> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        if let Some(idle_timeout) = self.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
+        }
+
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        if let Some(keepalive) = self.tcp_keepalive_secs {
+            builder = builder.tcp_keepalive(Duration::from_secs(keepalive));
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        if !self.headers.is_empty() {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &self.headers {
+                let name = HeaderName::try_from(name)
+                    .with_context(|| format!("invalid header name `{name}`"))?;
+                let value = HeaderValue::try_from(value)
+                    .with_context(|| format!("invalid value of header `{name}`"))?;
+                headers.insert(name, value);
+            }
+            builder = builder.default_headers(headers);
+        }
+
+        let timeout = self.timeout_secs.map(Duration::from_secs);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let http = builder.build().context("fail to build http client")?;
+        Ok(UpstreamClient { http, timeout })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_chain_config() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+chains:
+  eth:
+    http_client:
+      pool_max_idle_per_host: 64
+      http2_prior_knowledge: true
+"#,
+        )
+        .unwrap();
+
+        let chain = &config.chains["eth"];
+        assert_eq!(chain.http_client.pool_max_idle_per_host, Some(64));
+        assert!(chain.http_client.http2_prior_knowledge);
+        assert_eq!(chain.http_client.tcp_keepalive_secs, None);
+    }
+
+    #[test]
+    fn test_build_client_with_headers() {
+        let mut config = HttpClientConfig {
+            user_agent: Some("cached-eth-rpc".to_string()),
+            ..Default::default()
+        };
+        config
+            .headers
+            .insert("x-client-id".to_string(), "team-a".to_string());
+        assert!(config.build_client().is_ok());
+
+        config
+            .headers
+            .insert("invalid header".to_string(), "value".to_string());
+        assert!(config.build_client().is_err());
+    }
+
+    #[test]
+    fn test_parse_upstreams() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+chains:
+  eth:
+    load_balance: weighted
+    upstreams:
+      - url: http://node-a:8545
+        weight: 3
+      - url: http://node-b:8545
+"#,
+        )
+        .unwrap();
+
+        let chain = &config.chains["eth"];
+        assert_eq!(chain.load_balance, LoadBalanceStrategy::Weighted);
+        assert_eq!(chain.upstreams.len(), 2);
+        assert_eq!(chain.upstreams[0].weight, 3);
+        assert_eq!(chain.upstreams[1].weight, 1);
+    }
+
+    #[test]
+    fn test_validate_max_params_key_len() {
+        let config = |max_len: usize| {
+            let yaml = format!("chains:\n  eth:\n    max_params_key_len: {max_len}\n");
+            serde_yaml::from_str::<Config>(&yaml).unwrap()
+        };
+
+        assert!(config(41).validate().is_ok());
+        let err = config(40).validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "max_params_key_len of `eth` must be at least 41"
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let err = serde_yaml::from_str::<Config>("chains:\n  eth:\n    foo: 1\n").unwrap_err();
+        assert!(err.to_string().contains("unknown field `foo`"));
+    }
+}
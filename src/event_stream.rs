@@ -0,0 +1,195 @@
+use std::cell::Cell;
+
+use actix_web::http::header::ACCEPT;
+use actix_web::web::Bytes;
+use actix_web::HttpRequest;
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+
+use crate::json_rpc::JsonRpcResponse;
+
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+/// Whether the client asked for the responses of its batch as server-sent events.
+pub fn is_requested(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.trim().starts_with(CONTENT_TYPE))
+}
+
+/// Sends the responses of a batch as soon as each of them is known, in the order they become known rather than
+/// the order of the batch. Clients tell them apart by id.
+pub struct EventStream {
+    sender: mpsc::UnboundedSender<Bytes>,
+    format: Format,
+    /// Whether anything was written, JSON arrays are opened along with their first element.
+    started: Cell<bool>,
+    /// Whether the response at each index of the batch was sent.
+    sent: Vec<bool>,
+}
+
+enum Format {
+    /// One server-sent event per response.
+    ServerSentEvents,
+    /// The elements of a regular batch response.
+    JsonArray,
+}
+
+impl EventStream {
+    /// Returns the stream of server-sent events along with the body of the HTTP response carrying them.
+    pub fn new() -> (Self, impl Stream<Item = Result<Bytes, actix_web::Error>>) {
+        Self::with_format(Format::ServerSentEvents)
+    }
+
+    /// Like [`EventStream::new`], writing the responses as a JSON array, closed by [`EventStream::finish`].
+    pub fn json_array() -> (Self, impl Stream<Item = Result<Bytes, actix_web::Error>>) {
+        Self::with_format(Format::JsonArray)
+    }
+
+    fn with_format(format: Format) -> (Self, impl Stream<Item = Result<Bytes, actix_web::Error>>) {
+        let (sender, receiver) = mpsc::unbounded();
+        let stream = Self {
+            sender,
+            format,
+            started: Cell::new(false),
+            sent: vec![],
+        };
+
+        (stream, receiver.map(Ok))
+    }
+
+    /// Sends `response`, which is dropped if the client went away.
+    pub fn send(&self, response: &JsonRpcResponse) {
+        // Serializing a response never fails.
+        if let Ok(data) = serde_json::to_vec(response) {
+            self.send_data(&data);
+        }
+    }
+
+    /// Sends `data` as an event, e.g. the body of a request failing as a whole, or as an element of the array.
+    pub fn send_data(&self, data: &[u8]) {
+        let mut chunk = vec![];
+
+        match self.format {
+            Format::ServerSentEvents => {
+                for line in data.split(|byte| *byte == b'\n') {
+                    chunk.extend_from_slice(b"data: ");
+                    chunk.extend_from_slice(line);
+                    chunk.push(b'\n');
+                }
+                chunk.push(b'\n');
+            }
+            Format::JsonArray => {
+                chunk.push(if self.started.get() { b',' } else { b'[' });
+                chunk.extend_from_slice(data);
+            }
+        }
+
+        self.started.set(true);
+        let _ = self.sender.unbounded_send(Bytes::from(chunk));
+    }
+
+    /// Sends the known responses of the batch that weren't sent yet.
+    pub fn send_new(&mut self, responses: &[Option<JsonRpcResponse>]) {
+        self.sent.resize(responses.len(), false);
+
+        for (index, response) in responses.iter().enumerate() {
+            if let (Some(response), false) = (response, self.sent[index]) {
+                self.send(response);
+                self.sent[index] = true;
+            }
+        }
+    }
+
+    /// Records that the response at `index` of the batch was sent with [`EventStream::send`].
+    pub fn mark_sent(&mut self, index: usize) {
+        if self.sent.len() <= index {
+            self.sent.resize(index + 1, false);
+        }
+        self.sent[index] = true;
+    }
+
+    pub fn has_sent(&self) -> bool {
+        self.started.get()
+    }
+
+    /// Closes the JSON array once every response was sent.
+    pub fn finish(&self) {
+        if let (Format::JsonArray, true) = (&self.format, self.started.get()) {
+            let _ = self.sender.unbounded_send(Bytes::from_static(b"]"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::json_rpc::RequestId;
+
+    fn response(id: u64) -> JsonRpcResponse {
+        let id = RequestId::try_from(json!(id)).unwrap();
+        JsonRpcResponse::from_result(id, json!("0x1"))
+    }
+
+    #[test]
+    fn test_is_requested() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((ACCEPT, "application/json, text/event-stream"))
+            .to_http_request();
+        assert!(is_requested(&req));
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((ACCEPT, "application/json"))
+            .to_http_request();
+        assert!(!is_requested(&req));
+    }
+
+    #[test]
+    fn test_send_new() {
+        let (mut events, body) = EventStream::new();
+        assert!(!events.has_sent());
+
+        events.send_new(&[None, Some(response(2))]);
+        events.send(&response(1));
+        events.mark_sent(0);
+        events.send_new(&[Some(response(1)), Some(response(2)), Some(response(3))]);
+        events.send_data(b"first\nsecond");
+        assert!(events.has_sent());
+        drop(events);
+
+        let body = futures::executor::block_on(body.collect::<Vec<_>>())
+            .into_iter()
+            .map(|event| String::from_utf8(event.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            body,
+            [
+                "data: {\"jsonrpc\":\"2.0\",\"id\":2,\"result\":\"0x1\"}\n\n",
+                "data: {\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0x1\"}\n\n",
+                "data: {\"jsonrpc\":\"2.0\",\"id\":3,\"result\":\"0x1\"}\n\n",
+                "data: first\ndata: second\n\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_array() {
+        let (mut events, body) = EventStream::json_array();
+        events.send_new(&[None, Some(response(2))]);
+        events.send_new(&[Some(response(1)), Some(response(2))]);
+        events.finish();
+        drop(events);
+
+        let body = futures::executor::block_on(body.collect::<Vec<_>>())
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect::<Vec<_>>();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body[0]["id"], 2);
+        assert_eq!(body[1]["id"], 1);
+    }
+}
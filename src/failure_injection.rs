@@ -0,0 +1,184 @@
+use std::future::Future;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+const TIMEOUT_ERROR: &str = "injected failure: operation timed out";
+
+/// Fractions of upstream batches that fail in each way, for testing how clients and the proxy handle broken
+/// upstreams. Only honored with `--failure-injection`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailureInjectionConfig {
+    /// Batches that hang for `timeout_ms` and then fail like a timed out request.
+    pub timeout_rate: f64,
+    pub timeout_ms: u64,
+
+    /// Batches whose response fails to parse.
+    pub malformed_json_rate: f64,
+
+    /// Batches whose responses carry ids that match no request.
+    pub wrong_id_rate: f64,
+
+    /// Batches missing the second half of their responses.
+    pub truncated_batch_rate: f64,
+}
+
+impl Default for FailureInjectionConfig {
+    fn default() -> Self {
+        Self {
+            timeout_rate: 0.0,
+            timeout_ms: 10_000,
+            malformed_json_rate: 0.0,
+            wrong_id_rate: 0.0,
+            truncated_batch_rate: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    Timeout,
+    MalformedJson,
+    WrongIds,
+    TruncatedBatch,
+}
+
+impl FailureInjectionConfig {
+    fn rates(&self) -> [(Failure, f64); 4] {
+        [
+            (Failure::Timeout, self.timeout_rate),
+            (Failure::MalformedJson, self.malformed_json_rate),
+            (Failure::WrongIds, self.wrong_id_rate),
+            (Failure::TruncatedBatch, self.truncated_batch_rate),
+        ]
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let rates = self.rates();
+
+        anyhow::ensure!(
+            rates.iter().all(|(_, rate)| (0.0..=1.0).contains(rate)),
+            "failure injection rates must be between 0 and 1"
+        );
+        anyhow::ensure!(
+            rates.iter().map(|(_, rate)| rate).sum::<f64>() <= 1.0,
+            "failure injection rates must add up to at most 1"
+        );
+
+        Ok(())
+    }
+
+    /// Failure of the next batch at the configured rates, `None` if it goes through unharmed.
+    fn draw(&self, mut roll: f64) -> Option<Failure> {
+        for (failure, rate) in self.rates() {
+            if roll < rate {
+                return Some(failure);
+            }
+            roll -= rate;
+        }
+
+        None
+    }
+}
+
+/// Awaits the upstream `request`, failing it at the rates of `config` if any.
+pub async fn inject(
+    config: Option<&FailureInjectionConfig>,
+    request: impl Future<Output = anyhow::Result<Value>>,
+) -> anyhow::Result<Value> {
+    let Some(config) = config else {
+        return request.await;
+    };
+
+    match config.draw(rand::random()) {
+        None => request.await,
+        Some(Failure::Timeout) => {
+            actix_web::rt::time::sleep(Duration::from_millis(config.timeout_ms)).await;
+            anyhow::bail!(TIMEOUT_ERROR)
+        }
+        Some(failure) => {
+            tracing::debug!("injecting {failure:?} into upstream response");
+            corrupt(failure, request.await?)
+        }
+    }
+}
+
+/// `response` broken in the way of `failure`.
+fn corrupt(failure: Failure, mut response: Value) -> anyhow::Result<Value> {
+    match failure {
+        Failure::Timeout => anyhow::bail!(TIMEOUT_ERROR),
+        Failure::MalformedJson => {
+            anyhow::bail!(
+                "injected failure: error decoding response body: expected value at line 1 column 1"
+            )
+        }
+        Failure::WrongIds => {
+            let responses = match &mut response {
+                Value::Array(responses) => responses.iter_mut().collect::<Vec<_>>(),
+                response => vec![response],
+            };
+
+            for response in responses {
+                response["id"] = Value::String(format!("injected-{}", response["id"]));
+            }
+        }
+        Failure::TruncatedBatch => {
+            if let Value::Array(responses) = &mut response {
+                responses.truncate(responses.len() / 2);
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        let config = FailureInjectionConfig {
+            timeout_rate: 0.1,
+            wrong_id_rate: 0.2,
+            ..Default::default()
+        };
+        config.validate().unwrap();
+
+        assert_eq!(config.draw(0.05), Some(Failure::Timeout));
+        assert_eq!(config.draw(0.15), Some(Failure::WrongIds));
+        assert_eq!(config.draw(0.5), None);
+
+        let config = FailureInjectionConfig {
+            timeout_rate: 0.6,
+            truncated_batch_rate: 0.6,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "result": "0x1" },
+            { "jsonrpc": "2.0", "id": 2, "result": "0x2" },
+            { "jsonrpc": "2.0", "id": 3, "result": "0x3" },
+        ]);
+
+        let truncated = corrupt(Failure::TruncatedBatch, batch.clone()).unwrap();
+        assert_eq!(
+            truncated,
+            json!([{ "jsonrpc": "2.0", "id": 1, "result": "0x1" }])
+        );
+
+        let wrong_ids = corrupt(Failure::WrongIds, batch.clone()).unwrap();
+        assert_eq!(wrong_ids[0]["id"], "injected-1");
+        assert_eq!(wrong_ids[2]["result"], "0x3");
+
+        assert!(corrupt(Failure::MalformedJson, batch).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+
+use crate::concurrency::Priority;
+use crate::json_rpc::{DefinedError, JsonRpcResponse, RequestId, ResultOrError};
+use crate::rpc_cache_handler::common;
+use crate::{forward_uncached_requests, log_ranges, ChainState, RpcRequest};
+
+/// Filters not polled for this long are uninstalled, like nodes do.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Blocks a single poll of a block filter reports at most, the most recent ones.
+const MAX_BLOCK_CHANGES: u64 = 128;
+
+const FILTER_METHODS: [&str; 6] = [
+    "eth_newFilter",
+    "eth_newBlockFilter",
+    "eth_newPendingTransactionFilter",
+    "eth_getFilterChanges",
+    "eth_getFilterLogs",
+    "eth_uninstallFilter",
+];
+
+pub fn is_filter_method(method: &str) -> bool {
+    FILTER_METHODS.contains(&method)
+}
+
+#[derive(Clone)]
+enum FilterKind {
+    /// The filter object of `eth_newFilter`.
+    Logs(Value),
+    Blocks,
+}
+
+struct Filter {
+    kind: FilterKind,
+    /// The last block whose changes were reported.
+    last_block: u64,
+    last_poll: Instant,
+}
+
+/// Filters installed on this instance, polled for the changes since the previous poll by following the head
+/// instead of relying on an upstream keeping them. Each instance keeps its own filters.
+#[derive(Default)]
+pub struct Filters {
+    filters: DashMap<String, Filter>,
+}
+
+impl Filters {
+    fn install(&self, kind: FilterKind, head: u64) -> String {
+        self.filters
+            .retain(|_, filter| filter.last_poll.elapsed() < FILTER_TIMEOUT);
+
+        let id = format!("{:#x}", rand::random::<u128>());
+        let filter = Filter {
+            kind,
+            last_block: head,
+            last_poll: Instant::now(),
+        };
+        self.filters.insert(id.clone(), filter);
+        id
+    }
+
+    /// The filter and the blocks whose changes weren't reported yet, which are marked as reported.
+    fn poll(&self, id: &str, head: u64) -> Option<(FilterKind, u64, u64)> {
+        let mut filter = self.filters.get_mut(id)?;
+        filter.last_poll = Instant::now();
+
+        let from_block = filter.last_block + 1;
+        filter.last_block = filter.last_block.max(head);
+        Some((filter.kind.clone(), from_block, head))
+    }
+
+    /// Reports the blocks of a failed poll again on the next one.
+    fn rewind(&self, id: &str, from_block: u64, to_block: u64) {
+        if let Some(mut filter) = self.filters.get_mut(id) {
+            if filter.last_block == to_block {
+                filter.last_block = from_block - 1;
+            }
+        }
+    }
+
+    fn kind(&self, id: &str) -> Option<FilterKind> {
+        let mut filter = self.filters.get_mut(id)?;
+        filter.last_poll = Instant::now();
+        Some(filter.kind.clone())
+    }
+
+    fn uninstall(&self, id: &str) -> bool {
+        self.filters.remove(id).is_some()
+    }
+}
+
+/// Answers a request of one of the filter methods. Polls fail instead of reaching the upstreams if `cache_only` and
+/// the changes aren't cached.
+pub async fn serve(
+    chain_state: Arc<ChainState>,
+    rpc_request: RpcRequest,
+    cache_only: bool,
+    priority: Priority,
+    forwarded_headers: HeaderMap,
+) -> (usize, JsonRpcResponse) {
+    let (index, id) = (rpc_request.index, rpc_request.id.clone());
+    let error = |err: DefinedError| (index, JsonRpcResponse::from_error(Some(id.clone()), err));
+    let result = |result: Value| (index, JsonRpcResponse::from_result(id.clone(), result));
+
+    let Some(head) = chain_state.head.latest() else {
+        return error(DefinedError::UpstreamUnavailable(Some(json!({
+            "reason": "the latest block isn't known yet",
+        }))));
+    };
+    let filter_id = rpc_request.params[0].as_str().unwrap_or_default();
+    let filters = &chain_state.filters;
+
+    match rpc_request.method.as_str() {
+        "eth_newFilter" if rpc_request.params[0].is_object() => {
+            let kind = FilterKind::Logs(rpc_request.params[0].clone());
+            result(json!(filters.install(kind, head)))
+        }
+        "eth_newBlockFilter" => result(json!(filters.install(FilterKind::Blocks, head))),
+        "eth_newPendingTransactionFilter" => error(DefinedError::UnsupportedMethod(Some(json!({
+            "reason": "pending transaction filters aren't supported",
+        })))),
+        "eth_uninstallFilter" => result(json!(filters.uninstall(filter_id))),
+        "eth_getFilterLogs" => match filters.kind(filter_id) {
+            Some(FilterKind::Logs(filter)) => {
+                let rpc_request = RpcRequest::new_uncachable(
+                    index,
+                    id,
+                    log_ranges::GET_LOGS.to_string(),
+                    json!([filter]),
+                );
+                log_ranges::get_logs(
+                    chain_state,
+                    rpc_request,
+                    cache_only,
+                    priority,
+                    forwarded_headers,
+                )
+                .await
+            }
+            Some(FilterKind::Blocks) => error(DefinedError::InvalidParams),
+            None => (index, filter_not_found(id)),
+        },
+        "eth_getFilterChanges" => {
+            let Some((kind, from_block, to_block)) = filters.poll(filter_id, head) else {
+                return (index, filter_not_found(id));
+            };
+
+            let (response, reported_block) = match kind {
+                FilterKind::Logs(filter) => {
+                    let response = log_changes(
+                        &chain_state,
+                        &rpc_request,
+                        &filter,
+                        (from_block, to_block),
+                        cache_only,
+                        priority,
+                        &forwarded_headers,
+                    )
+                    .await;
+                    (response, to_block)
+                }
+                FilterKind::Blocks => {
+                    block_changes(
+                        &chain_state,
+                        &rpc_request,
+                        (from_block, to_block),
+                        cache_only,
+                        priority,
+                        &forwarded_headers,
+                    )
+                    .await
+                }
+            };
+
+            let reported_block = match response.result {
+                ResultOrError::Error { .. } => from_block - 1,
+                ResultOrError::Result { .. } => reported_block,
+            };
+            if reported_block < to_block {
+                filters.rewind(filter_id, reported_block + 1, to_block);
+            }
+            (index, response)
+        }
+        _ => error(DefinedError::InvalidParams),
+    }
+}
+
+/// Nodes answer polls of unknown or expired filters this way.
+fn filter_not_found(id: RequestId) -> JsonRpcResponse {
+    JsonRpcResponse::from_custom_error(
+        Some(id),
+        json!({ "code": -32000, "message": "filter not found" }),
+    )
+}
+
+/// Logs matching `filter` in the range of blocks, within the range of the filter itself.
+async fn log_changes(
+    chain_state: &Arc<ChainState>,
+    rpc_request: &RpcRequest,
+    filter: &Value,
+    (from_block, to_block): (u64, u64),
+    cache_only: bool,
+    priority: Priority,
+    forwarded_headers: &HeaderMap,
+) -> JsonRpcResponse {
+    let bound = |bound: &Value| match bound {
+        Value::Null => None,
+        bound => common::parse_block_number(bound).ok().flatten(),
+    };
+    let from_block = bound(&filter["fromBlock"]).map_or(from_block, |bound| bound.max(from_block));
+    let to_block = bound(&filter["toBlock"]).map_or(to_block, |bound| bound.min(to_block));
+    if from_block > to_block {
+        return JsonRpcResponse::from_result(rpc_request.id.clone(), json!([]));
+    }
+
+    let rpc_request = RpcRequest::new_uncachable(
+        rpc_request.index,
+        rpc_request.id.clone(),
+        log_ranges::GET_LOGS.to_string(),
+        log_ranges::with_range(&json!([filter]), from_block, to_block),
+    );
+    let (_, response) = log_ranges::get_logs(
+        chain_state.clone(),
+        rpc_request,
+        cache_only,
+        priority,
+        forwarded_headers.clone(),
+    )
+    .await;
+    response
+}
+
+/// Hashes of the range of blocks, at most the `MAX_BLOCK_CHANGES` most recent ones, and the last block reported.
+/// Blocks are always fetched from the upstreams, so the poll fails if `cache_only`.
+async fn block_changes(
+    chain_state: &ChainState,
+    rpc_request: &RpcRequest,
+    (from_block, to_block): (u64, u64),
+    cache_only: bool,
+    priority: Priority,
+    forwarded_headers: &HeaderMap,
+) -> (JsonRpcResponse, u64) {
+    let from_block = from_block.max((to_block + 1).saturating_sub(MAX_BLOCK_CHANGES));
+    let id = rpc_request.id.clone();
+    if cache_only && from_block <= to_block {
+        let response = JsonRpcResponse::from_error(Some(id), DefinedError::NotCached(None));
+        return (response, from_block - 1);
+    }
+
+    let block_requests = (from_block..=to_block)
+        .enumerate()
+        .map(|(index, block)| {
+            RpcRequest::new_uncachable(
+                index,
+                // It's safe to unwrap here because a number is always a valid request id.
+                RequestId::try_from(json!(index)).unwrap(),
+                "eth_getBlockByNumber".to_string(),
+                json!([format!("{block:#x}"), false]),
+            )
+        })
+        .collect::<Vec<_>>();
+    let Some(first_request) = block_requests.first() else {
+        return (JsonRpcResponse::from_result(id, json!([])), to_block);
+    };
+
+    let upstreams = chain_state.route(&first_request.method, &first_request.params);
+    let mut responses = forward_uncached_requests(
+        chain_state,
+        upstreams,
+        block_requests,
+        priority,
+        forwarded_headers,
+    )
+    .await;
+    responses.sort_by_key(|(index, _)| *index);
+
+    let mut hashes = vec![];
+    for (_, response) in responses {
+        match response.result {
+            ResultOrError::Result { result } => match result["hash"].as_str() {
+                Some(hash) => hashes.push(json!(hash)),
+                // Not known to the upstream yet, reported on the next poll.
+                None => break,
+            },
+            ResultOrError::Error { error } => {
+                let response = JsonRpcResponse {
+                    id: Some(id),
+                    result: ResultOrError::Error { error },
+                    ..response
+                };
+                return (response, from_block - 1);
+            }
+        }
+    }
+
+    let reported_block = from_block + hashes.len() as u64 - 1;
+    (
+        JsonRpcResponse::from_result(id, Value::Array(hashes)),
+        reported_block,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_poll() {
+        let filters = Filters::default();
+        let id = filters.install(FilterKind::Blocks, 100);
+
+        assert!(matches!(filters.poll(&id, 100), Some((_, 101, 100))));
+        assert!(matches!(filters.poll(&id, 105), Some((_, 101, 105))));
+        assert!(matches!(filters.poll(&id, 107), Some((_, 106, 107))));
+
+        filters.rewind(&id, 106, 107);
+        assert!(matches!(filters.poll(&id, 108), Some((_, 106, 108))));
+
+        assert!(filters.uninstall(&id));
+        assert!(filters.poll(&id, 109).is_none());
+        assert!(!filters.uninstall(&id));
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use serde::Deserialize;
+
+use crate::ChainState;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GcConfig {
+    /// Seconds between garbage collection runs.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Entries keyed by a block number more than this many blocks behind the head are deleted. Only expired
+    /// entries are deleted if unset.
+    pub retention_blocks: Option<u64>,
+}
+
+fn default_interval_secs() -> u64 {
+    3600
+}
+
+/// Periodically deletes expired entries and entries of blocks past the retention horizon from the cache of
+/// the chain. The scan runs on the blocking thread pool since cache backends are synchronous.
+pub async fn run_gc(chain_state: Arc<ChainState>, config: GcConfig) {
+    let mut ticker = actix_web::rt::time::interval(Duration::from_secs(config.interval_secs));
+
+    loop {
+        ticker.tick().await;
+
+        let horizon = match (config.retention_blocks, chain_state.head.latest()) {
+            (Some(retention), Some(latest)) => Some(latest.saturating_sub(retention)),
+            _ => None,
+        };
+
+        let state = chain_state.clone();
+        let result = web::block(move || {
+            let mut cache_backend = state.cache_factory.get_instance()?;
+            cache_backend.collect_garbage(&|_, params_key| is_past_horizon(params_key, horizon))
+        })
+        .await;
+
+        match result {
+            Ok(Ok(reclaimed)) => {
+                let stats = &chain_state.stats;
+                stats
+                    .gc_reclaimed_entries
+                    .fetch_add(reclaimed.entries, Ordering::Relaxed);
+                stats
+                    .gc_reclaimed_bytes
+                    .fetch_add(reclaimed.bytes, Ordering::Relaxed);
+
+                tracing::info!(
+                    "cache gc reclaimed {} entries ({} bytes)",
+                    reclaimed.entries,
+                    reclaimed.bytes
+                );
+            }
+            Ok(Err(err)) => tracing::warn!("fail to collect cache garbage: {err:#}"),
+            Err(err) => tracing::warn!("fail to run cache gc: {err}"),
+        }
+    }
+}
+
+/// Seconds between checks of the byte quota of a chain.
+const QUOTA_INTERVAL_SECS: u64 = 60;
+
+/// Periodically evicts the oldest entries of the chain kept forever once they take more than `max_bytes`.
+pub async fn run_quota(chain_state: Arc<ChainState>, max_bytes: u64) {
+    let mut ticker = actix_web::rt::time::interval(Duration::from_secs(QUOTA_INTERVAL_SECS));
+
+    loop {
+        ticker.tick().await;
+
+        let state = chain_state.clone();
+        let result = web::block(move || {
+            let mut cache_backend = state.cache_factory.get_instance()?;
+            let evicted = cache_backend.evict_oldest(max_bytes)?;
+            anyhow::Ok((evicted, cache_backend.stored_bytes()?))
+        })
+        .await;
+
+        match result {
+            Ok(Ok((evicted, stored_bytes))) => {
+                let stats = &chain_state.stats;
+                stats
+                    .quota_evicted_entries
+                    .fetch_add(evicted.entries, Ordering::Relaxed);
+                stats
+                    .quota_evicted_bytes
+                    .fetch_add(evicted.bytes, Ordering::Relaxed);
+                if let Some(stored_bytes) = stored_bytes {
+                    stats.stored_bytes.store(stored_bytes, Ordering::Relaxed);
+                }
+
+                if evicted.entries > 0 {
+                    tracing::info!(
+                        "cache quota evicted {} entries ({} bytes)",
+                        evicted.entries,
+                        evicted.bytes
+                    );
+                }
+            }
+            Ok(Err(err)) => tracing::warn!("fail to enforce cache quota: {err:#}"),
+            Err(err) => tracing::warn!("fail to run cache quota: {err}"),
+        }
+    }
+}
+
+/// Whether a params key starts with a block number before `horizon`, e.g. `0x10-...` for a horizon of
+/// `0x11`. Keys starting with block hashes or anything else are kept.
+fn is_past_horizon(params_key: &str, horizon: Option<u64>) -> bool {
+    let Some(horizon) = horizon else {
+        return false;
+    };
+
+    let block_tag = params_key.split('-').next().unwrap_or_default();
+
+    match block_tag.strip_prefix("0x") {
+        Some(hex) if hex.len() <= 16 => {
+            u64::from_str_radix(hex, 16).is_ok_and(|block_number| block_number < horizon)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_past_horizon() {
+        assert!(is_past_horizon("0x10-true", Some(0x11)));
+        assert!(is_past_horizon("0x10", Some(0x11)));
+        assert!(!is_past_horizon("0x11-true", Some(0x11)));
+        assert!(!is_past_horizon("0x10-true", None));
+
+        let block_hash = format!("0x{}", "ab".repeat(32));
+        assert!(!is_past_horizon(&block_hash, Some(u64::MAX)));
+        assert!(!is_past_horizon("aa734bab822de3d5", Some(u64::MAX)));
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{utils, ChainState};
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Parameterless methods whose results only change with the head and are fetched on every new head.
+const PREWARMED_HEAD_METHODS: [&str; 2] = ["eth_gasPrice", "eth_maxPriorityFeePerGas"];
+
+/// Latest block number seen on a chain.
+#[derive(Default)]
+pub struct HeadTracker {
+    /// Zero until the first head has been observed.
+    latest: AtomicU64,
+    /// Zero until observed, and forever on chains without a `finalized` block tag.
+    finalized: AtomicU64,
+    /// Results of parameterless methods with the block they were fetched at. Entries go stale as soon as
+    /// the head moves past that block.
+    head_results: DashMap<String, (u64, Arc<Value>)>,
+}
+
+impl HeadTracker {
+    pub fn latest(&self) -> Option<u64> {
+        match self.latest.load(Ordering::Relaxed) {
+            0 => None,
+            block_number => Some(block_number),
+        }
+    }
+
+    /// Lagging upstreams never move the head backwards.
+    pub fn update_latest(&self, block_number: u64) {
+        self.latest.fetch_max(block_number, Ordering::Relaxed);
+    }
+
+    pub fn finalized(&self) -> Option<u64> {
+        match self.finalized.load(Ordering::Relaxed) {
+            0 => None,
+            block_number => Some(block_number),
+        }
+    }
+
+    pub fn update_finalized(&self, block_number: u64) {
+        self.finalized.fetch_max(block_number, Ordering::Relaxed);
+    }
+
+    /// Returns the result of `method` fetched at the current head, if any.
+    pub fn head_result(&self, method: &str) -> Option<Arc<Value>> {
+        let latest = self.latest()?;
+        let entry = self.head_results.get(method)?;
+        let (block_number, result) = entry.value();
+
+        (*block_number == latest).then(|| result.clone())
+    }
+
+    pub fn set_head_result(&self, block_number: u64, method: &str, result: Value) {
+        self.head_results
+            .insert(method.to_string(), (block_number, Arc::new(result)));
+    }
+}
+
+/// Keeps the head tracker of the chain up to date by polling `eth_blockNumber` and the finalized block on
+/// its default upstreams.
+pub async fn poll_latest_block(chain_state: Arc<ChainState>, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        let upstream = chain_state.upstreams.default_pool().pick();
+
+        match utils::get_block_number(upstream).await {
+            Ok(block_number) => chain_state.head.update_latest(block_number),
+            Err(err) => {
+                tracing::warn!("fail to poll latest block from {}: {err:#}", upstream.url);
+            }
+        }
+
+        // Chains without finality simply never report a finalized block.
+        match utils::get_finalized_block_number(upstream).await {
+            Ok(Some(block_number)) => chain_state.head.update_finalized(block_number),
+            Ok(None) => {}
+            Err(err) => {
+                tracing::debug!(
+                    "fail to poll finalized block from {}: {err:#}",
+                    upstream.url
+                );
+            }
+        }
+    }
+}
+
+/// Follows `newHeads` on a websocket upstream so that the head moves as soon as a block is produced,
+/// pre-warming the cache for every new block. Resubscribes whenever the subscription drops.
+pub async fn subscribe_new_heads(chain_state: Arc<ChainState>, url: Url) {
+    loop {
+        if let Err(err) = follow_new_heads(&chain_state, &url).await {
+            tracing::warn!("newHeads subscription on {url} dropped: {err:#}");
+        }
+
+        actix_web::rt::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn follow_new_heads(chain_state: &Arc<ChainState>, url: &Url) -> anyhow::Result<()> {
+    let (mut stream, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .context("fail to connect")?;
+
+    let subscribe = json!({
+        "jsonrpc": "2.0",
+        "method": "eth_subscribe",
+        "params": ["newHeads"],
+        "id": 1
+    });
+    stream.send(Message::Text(subscribe.to_string())).await?;
+
+    while let Some(message) = stream.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let message: Value = serde_json::from_str(&text)?;
+
+        if message["id"] == 1 {
+            if !message["error"].is_null() {
+                anyhow::bail!("fail to subscribe: {}", message["error"]);
+            }
+
+            tracing::info!("subscribed to newHeads on {url}");
+            continue;
+        }
+
+        let Some(number) = message["params"]["result"]["number"].as_str() else {
+            continue;
+        };
+        let block_number = u64::from_str_radix(number.trim_start_matches("0x"), 16)
+            .with_context(|| format!("invalid block number {number}"))?;
+
+        chain_state.head.update_latest(block_number);
+        chain_state.head.set_head_result(
+            block_number,
+            "eth_blockNumber",
+            Value::String(format!("0x{block_number:x}")),
+        );
+
+        actix_web::rt::spawn(prewarm_head(chain_state.clone(), block_number));
+    }
+
+    anyhow::bail!("connection closed")
+}
+
+/// Fetches the block that was just confirmed and the fee data of the head in one batch. The block goes through the
+/// regular cache path, which only caches blocks `confirmation_depth` behind the head, so that's the one fetched.
+/// Fee data is kept on the head tracker until the next head.
+async fn prewarm_head(chain_state: Arc<ChainState>, block_number: u64) {
+    let confirmed_block = block_number.saturating_sub(chain_state.confirmation_depth);
+    let block_params = json!([format!("0x{confirmed_block:x}"), false]);
+
+    // Request ids are indexes into `methods`.
+    let methods = std::iter::once("eth_getBlockByNumber")
+        .chain(PREWARMED_HEAD_METHODS)
+        .collect::<Vec<_>>();
+    let requests = methods
+        .iter()
+        .enumerate()
+        .map(|(id, method)| {
+            let params = match *method {
+                "eth_getBlockByNumber" => block_params.clone(),
+                _ => json!([]),
+            };
+
+            json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id })
+        })
+        .collect::<Vec<_>>();
+
+    let upstream = chain_state.upstreams.default_pool().pick();
+
+    let responses = match upstream.request(&requests).await {
+        Ok(Value::Array(responses)) => responses,
+        Ok(response) => {
+            tracing::warn!("invalid response when pre-warming block {block_number}: {response}");
+            return;
+        }
+        Err(err) => {
+            tracing::warn!("fail to pre-warm block {block_number}: {err:#}");
+            return;
+        }
+    };
+
+    for mut response in responses {
+        let Some(method) = response["id"]
+            .as_u64()
+            .and_then(|id| methods.get(id as usize))
+        else {
+            continue;
+        };
+
+        let result = response["result"].take();
+        if result.is_null() {
+            continue;
+        }
+
+        if *method != "eth_getBlockByNumber" {
+            chain_state
+                .head
+                .set_head_result(block_number, method, result);
+            continue;
+        }
+
+        if let Err(err) = chain_state.cache_result(method, &block_params, &result, upstream) {
+            tracing::warn!("fail to cache pre-warmed block {confirmed_block}: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update_latest() {
+        let head = HeadTracker::default();
+        assert_eq!(head.latest(), None);
+
+        head.update_latest(100);
+        head.update_latest(99);
+        assert_eq!(head.latest(), Some(100));
+    }
+
+    #[test]
+    fn test_head_result() {
+        let head = HeadTracker::default();
+        head.set_head_result(100, "eth_gasPrice", json!("0x1"));
+        assert_eq!(head.head_result("eth_gasPrice"), None);
+
+        head.update_latest(100);
+        assert_eq!(
+            head.head_result("eth_gasPrice").as_deref(),
+            Some(&json!("0x1"))
+        );
+        assert_eq!(head.head_result("eth_blockNumber"), None);
+
+        head.update_latest(101);
+        assert_eq!(head.head_result("eth_gasPrice"), None);
+    }
+
+    #[test]
+    fn test_update_finalized() {
+        let head = HeadTracker::default();
+        assert_eq!(head.finalized(), None);
+
+        head.update_finalized(90);
+        head.update_finalized(80);
+        assert_eq!(head.finalized(), Some(90));
+        assert_eq!(head.latest(), None);
+    }
+}
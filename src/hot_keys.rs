@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::json_rpc::{JsonRpcRequest, RequestId};
+use crate::ChainState;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HotKeyRefreshConfig {
+    /// Cache hits within the lifetime of an entry that make it hot.
+    #[serde(default = "default_min_hits")]
+    pub min_hits: u32,
+
+    /// Hot entries are refreshed when they expire within this many seconds.
+    #[serde(default = "default_refresh_ahead_secs")]
+    pub refresh_ahead_secs: u64,
+}
+
+fn default_min_hits() -> u32 {
+    10
+}
+
+fn default_refresh_ahead_secs() -> u64 {
+    2
+}
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Cache entries with a TTL, with the hits they got since they were written.
+#[derive(Default)]
+pub struct HotKeys {
+    entries: DashMap<String, HotKey>,
+}
+
+struct HotKey {
+    method: String,
+    params: Value,
+    expires_at: Instant,
+    hits: u32,
+}
+
+impl HotKeys {
+    /// Starts tracking an entry written with `ttl`, resetting its hits if it was refreshed.
+    pub fn track(&self, cache_key: &str, method: &str, params: &Value, ttl: Duration) {
+        let key = HotKey {
+            method: method.to_string(),
+            params: params.clone(),
+            expires_at: Instant::now() + ttl,
+            hits: 0,
+        };
+
+        self.entries.insert(cache_key.to_string(), key);
+    }
+
+    pub fn record_hit(&self, cache_key: &str) {
+        if let Some(mut key) = self.entries.get_mut(cache_key) {
+            key.hits = key.hits.saturating_add(1);
+        }
+    }
+
+    /// Forgets expired entries and returns the method and params of hot entries expiring before
+    /// `refresh_before`. Returned entries are forgotten until they are written again.
+    fn take_expiring(
+        &self,
+        min_hits: u32,
+        now: Instant,
+        refresh_before: Instant,
+    ) -> Vec<(String, Value)> {
+        let mut expiring = vec![];
+
+        self.entries.retain(|_, key| {
+            if key.expires_at <= now {
+                return false;
+            }
+
+            if key.hits >= min_hits && key.expires_at <= refresh_before {
+                expiring.push((std::mem::take(&mut key.method), key.params.take()));
+                return false;
+            }
+
+            true
+        });
+
+        expiring
+    }
+}
+
+/// Refreshes hot entries of the chain shortly before they expire, so that popular entries never miss.
+pub async fn run_refresh(chain_state: Arc<ChainState>, config: HotKeyRefreshConfig) {
+    let refresh_ahead = Duration::from_secs(config.refresh_ahead_secs);
+    let mut ticker = actix_web::rt::time::interval(SCAN_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        let Some(hot_keys) = &chain_state.hot_keys else {
+            return;
+        };
+
+        let now = Instant::now();
+        let expiring = hot_keys.take_expiring(config.min_hits, now, now + refresh_ahead);
+
+        for (method, params) in expiring {
+            if let Err(err) = refresh(&chain_state, &method, &params).await {
+                tracing::warn!(method, "fail to refresh hot cache entry: {err:#}");
+            }
+        }
+    }
+}
+
+async fn refresh(chain_state: &ChainState, method: &str, params: &Value) -> anyhow::Result<()> {
+    let upstream = chain_state.route(method, params).pick();
+
+    // It's safe to unwrap here because a number is always a valid request id.
+    let request_id = RequestId::try_from(json!(1)).unwrap();
+    let request = JsonRpcRequest::new(Some(&request_id), method, params);
+
+    let mut response = upstream.request(&request).await?;
+    if !response["error"].is_null() {
+        anyhow::bail!("upstream returned error: {}", response["error"]);
+    }
+
+    chain_state.cache_result(method, params, &response["result"].take(), upstream)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_expiring() {
+        let hot_keys = HotKeys::default();
+        let ttl = Duration::from_secs(10);
+        hot_keys.track("hot", "eth_gasPrice", &json!([]), ttl);
+        hot_keys.track("cold", "eth_gasPrice", &json!([]), ttl);
+        hot_keys.track("expired", "eth_gasPrice", &json!([]), Duration::ZERO);
+
+        for _ in 0..3 {
+            hot_keys.record_hit("hot");
+        }
+        hot_keys.record_hit("cold");
+
+        let now = Instant::now();
+        assert!(hot_keys.take_expiring(3, now, now).is_empty());
+        assert_eq!(hot_keys.entries.len(), 2);
+
+        let expiring = hot_keys.take_expiring(3, now, now + ttl);
+        assert_eq!(expiring, vec![("eth_gasPrice".to_string(), json!([]))]);
+        assert_eq!(hot_keys.entries.len(), 1);
+        assert!(hot_keys.entries.contains_key("cold"));
+    }
+}
@@ -0,0 +1,70 @@
+use actix_web::{error, web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cache::CacheStatus;
+use crate::{AppState, METADATA_KEY_SUFFIX};
+
+#[derive(Deserialize)]
+struct InspectQuery {
+    method: String,
+    /// JSON encoded params, none if unset.
+    params: Option<String>,
+}
+
+#[derive(Serialize)]
+struct InspectResult {
+    key: String,
+    value: Option<Value>,
+    /// Only stored for chains with `store_metadata` enabled.
+    metadata: Option<Value>,
+}
+
+/// Looks up the cache entry of a request, e.g. `/eth/inspect?method=eth_getBalance&params=["0x..","0x10"]`.
+#[actix_web::get("/{chain}/inspect")]
+pub async fn inspect(
+    path: web::Path<(String,)>,
+    query: web::Query<InspectQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (chain,) = path.into_inner();
+    let chain_state = data
+        .chain_slot(&chain)
+        .and_then(|slot| slot.get())
+        .ok_or_else(|| error::ErrorNotFound("endpoint not supported"))?;
+
+    let cache_entry = chain_state
+        .cache_entries
+        .get(query.method.as_str())
+        .ok_or_else(|| error::ErrorBadRequest("cache is not supported"))?;
+
+    let params = match &query.params {
+        Some(params) => serde_json::from_str(params).map_err(error::ErrorBadRequest)?,
+        None => Value::Array(vec![]),
+    };
+
+    let params_key = chain_state
+        .params_key(cache_entry, &params)
+        .map_err(|err| error::ErrorBadRequest(format!("{err:#}")))?
+        .ok_or_else(|| error::ErrorBadRequest("request is not cacheable"))?;
+
+    let mut cache_backend = chain_state
+        .cache_factory
+        .get_instance()
+        .map_err(|err| error::ErrorServiceUnavailable(format!("{err:#}")))?;
+
+    let mut read = |params_key: &str| match cache_backend.read(&query.method, params_key) {
+        Ok(CacheStatus::Cached { value, .. }) => Ok(Some(value)),
+        Ok(CacheStatus::Missed { .. }) => Ok(None),
+        Err(err) => Err(error::ErrorInternalServerError(format!("{err:#}"))),
+    };
+
+    let value = read(&params_key)?;
+    let metadata = read(&format!("{params_key}{METADATA_KEY_SUFFIX}"))?;
+
+    Ok(HttpResponse::Ok().json(InspectResult {
+        key: cache_backend.key(&query.method, &params_key),
+        value,
+        metadata,
+    }))
+}
@@ -0,0 +1,128 @@
+use std::net::IpAddr;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use futures::future::{self, Either, Ready};
+
+use crate::args::Args;
+use crate::client_ip::{self, Cidr};
+
+/// CIDR allow and deny lists of client addresses.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    pub fn new(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        Self { allow, deny }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Denied addresses are rejected even if they're also allowed, others are accepted unless there's an allow
+    /// list they're missing from. Clients without an address, i.e. on unix sockets, are accepted.
+    fn is_allowed(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return true;
+        };
+
+        !self.deny.iter().any(|cidr| cidr.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip)))
+    }
+}
+
+/// Filters of the RPC endpoints and of the management endpoints.
+#[derive(Debug, Clone)]
+pub struct IpFilters {
+    rpc: IpFilter,
+    admin: IpFilter,
+    trusted_proxies: Vec<Cidr>,
+}
+
+impl IpFilters {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            rpc: IpFilter::new(args.allow_ips.clone(), args.deny_ips.clone()),
+            admin: IpFilter::new(args.admin_allow_ips.clone(), args.admin_deny_ips.clone()),
+            trusted_proxies: args.trusted_proxies.clone(),
+        }
+    }
+
+    fn is_allowed(&self, req: &HttpRequest) -> bool {
+        let filter = match is_admin_path(req.path()) {
+            true => &self.admin,
+            false => &self.rpc,
+        };
+
+        filter.is_empty() || filter.is_allowed(client_ip::resolve(req, &self.trusted_proxies))
+    }
+}
+
+/// Whether `path` is one of the management endpoints.
+fn is_admin_path(path: &str) -> bool {
+    let segments = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+
+    matches!(
+        segments.as_slice(),
+        ["health"] | ["metrics"] | ["stats"] | ["stats", _] | [_, "inspect" | "maintenance"]
+    )
+}
+
+/// Middleware rejecting clients that aren't allowed with 403, before the request body is read.
+pub fn check<S>(
+    ip_filters: &IpFilters,
+    req: ServiceRequest,
+    srv: &S,
+) -> Either<S::Future, Ready<Result<ServiceResponse, Error>>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    if ip_filters.is_allowed(req.request()) {
+        return Either::Left(srv.call(req));
+    }
+
+    tracing::debug!("rejecting request from {:?}", req.peer_addr());
+    let response = HttpResponse::Forbidden().body("client address not allowed");
+    Either::Right(future::ok(req.into_response(response)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn cidrs(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs.iter().map(|cidr| cidr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let ip = |ip: &str| Some(IpAddr::from_str(ip).unwrap());
+
+        let filter = IpFilter::new(cidrs(&["10.0.0.0/8"]), cidrs(&["10.0.0.66"]));
+        assert!(filter.is_allowed(ip("10.1.2.3")));
+        assert!(!filter.is_allowed(ip("10.0.0.66")));
+        assert!(!filter.is_allowed(ip("1.2.3.4")));
+        assert!(filter.is_allowed(None));
+
+        let filter = IpFilter::new(vec![], cidrs(&["1.2.3.0/24"]));
+        assert!(filter.is_allowed(ip("1.2.4.1")));
+        assert!(!filter.is_allowed(ip("1.2.3.4")));
+    }
+
+    #[test]
+    fn test_is_admin_path() {
+        assert!(is_admin_path("/health"));
+        assert!(is_admin_path("/stats/upstream-usage"));
+        assert!(is_admin_path("/eth/inspect"));
+        assert!(is_admin_path("/eth/maintenance"));
+        assert!(!is_admin_path("/eth"));
+        assert!(!is_admin_path("/eth/eth_blockNumber"));
+        assert!(!is_admin_path("/"));
+    }
+}
@@ -1,11 +1,20 @@
 use std::hash::Hash;
+use std::sync::Arc;
 
+use actix_web::web::Bytes;
 use actix_web::HttpResponse;
+use futures::{future, stream, Stream, StreamExt};
 use serde::Serialize;
 use serde_json::{Number, Value};
 
 const DEFAULT_JSON_RPC_VERSION: &str = "2.0";
 
+/// Batches with more responses than this are serialized while being sent rather than upfront.
+pub const STREAMED_BATCH_LEN: usize = 1000;
+
+/// Responses serialized into one chunk of a streamed batch.
+const RESPONSES_PER_CHUNK: usize = 100;
+
 #[derive(PartialEq, Hash, Debug, Clone)]
 pub struct RequestId {
     id: StringOrNumber,
@@ -25,6 +34,9 @@ impl TryFrom<Value> for RequestId {
             Value::String(string) => Ok(Self {
                 id: StringOrNumber::String(string),
             }),
+            Value::Null => Ok(Self {
+                id: StringOrNumber::Null,
+            }),
             _ => Err(anyhow::anyhow!("invalid request id")),
         }
     }
@@ -35,6 +47,7 @@ impl Serialize for RequestId {
         match &self.id {
             StringOrNumber::String(string) => string.serialize(serializer),
             StringOrNumber::Number(number) => number.serialize(serializer),
+            StringOrNumber::Null => serializer.serialize_unit(),
         }
     }
 }
@@ -45,20 +58,24 @@ impl Eq for RequestId {}
 enum StringOrNumber {
     String(String),
     Number(u64),
+    /// Some clients send `"id": null` or omit the id. They still get a response, with a `null` id.
+    Null,
 }
 
-#[derive(Serialize, Clone)]
-pub struct JsonRpcRequest {
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: Value,
-    pub id: Option<RequestId>,
+/// Borrows the parts of the request so that forwarding it, possibly to several upstreams, never copies
+/// large params.
+#[derive(Serialize, Clone, Copy)]
+pub struct JsonRpcRequest<'a> {
+    pub jsonrpc: &'static str,
+    pub method: &'a str,
+    pub params: &'a Value,
+    pub id: Option<&'a RequestId>,
 }
 
-impl JsonRpcRequest {
-    pub fn new(id: Option<RequestId>, method: String, params: Value) -> Self {
+impl<'a> JsonRpcRequest<'a> {
+    pub fn new(id: Option<&'a RequestId>, method: &'a str, params: &'a Value) -> Self {
         Self {
-            jsonrpc: DEFAULT_JSON_RPC_VERSION.to_string(),
+            jsonrpc: DEFAULT_JSON_RPC_VERSION,
             method,
             params,
             id,
@@ -97,11 +114,13 @@ impl JsonRpcResponse {
         }
     }
 
-    pub fn from_result(id: RequestId, result: Value) -> Self {
+    pub fn from_result(id: RequestId, result: impl Into<Arc<Value>>) -> Self {
         Self {
             jsonrpc: DEFAULT_JSON_RPC_VERSION.to_string(),
             id: Some(id),
-            result: ResultOrError::Result { result },
+            result: ResultOrError::Result {
+                result: result.into(),
+            },
         }
     }
 }
@@ -118,6 +137,35 @@ impl From<JsonRpcResponse> for Result<HttpResponse, actix_web::Error> {
     }
 }
 
+/// Serializes a batch response chunk by chunk, so that the JSON of a huge batch is never held in memory
+/// at once. Missing responses are `null`, as when serializing the whole batch.
+pub fn stream_batch_response(
+    responses: Vec<Option<JsonRpcResponse>>,
+) -> impl Stream<Item = Result<Bytes, serde_json::Error>> {
+    let mut is_first = true;
+
+    let chunks = stream::iter(responses)
+        .chunks(RESPONSES_PER_CHUNK)
+        .map(move |responses| {
+            let mut buf = vec![];
+
+            for response in responses {
+                if !is_first {
+                    buf.push(b',');
+                }
+                is_first = false;
+
+                serde_json::to_writer(&mut buf, &response)?;
+            }
+
+            Ok::<_, serde_json::Error>(Bytes::from(buf))
+        });
+
+    stream::once(future::ready(Ok(Bytes::from_static(b"["))))
+        .chain(chunks)
+        .chain(stream::once(future::ready(Ok(Bytes::from_static(b"]")))))
+}
+
 #[derive(Serialize, Clone)]
 #[serde(untagged)]
 pub enum ResultOrError {
@@ -126,9 +174,10 @@ pub enum ResultOrError {
         error: DefinedOrCustomError,
     },
 
+    /// Shared so that e.g. duplicate requests of a batch don't copy large results.
     Result {
         #[serde(rename = "result")]
-        result: Value,
+        result: Arc<Value>,
     },
 }
 
@@ -140,7 +189,7 @@ pub enum DefinedOrCustomError {
 }
 
 /// Source: https://www.jsonrpc.org/specification
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum DefinedError {
     #[allow(dead_code)]
     InvalidJson,
@@ -153,6 +202,35 @@ pub enum DefinedError {
     InvalidParams,
 
     InternalError(Option<Value>),
+
+    /// No upstream of the pool could be reached. Codes from -32050 on are specific to this proxy.
+    UpstreamUnavailable(Option<Value>),
+
+    /// The upstream answered with something other than a response to the request.
+    InvalidUpstreamResponse(Option<Value>),
+
+    CacheUnavailable(Option<Value>),
+
+    /// Every connection to the cache backend is in use.
+    CachePoolExhausted(Option<Value>),
+
+    /// The chain is configured but hasn't been initialized yet.
+    ChainUnavailable(Option<Value>),
+
+    /// A daily or monthly quota of the API key is used up.
+    QuotaExceeded(Option<Value>),
+
+    /// The request asked to be served from the cache only, and its result isn't cached.
+    NotCached(Option<Value>),
+
+    /// The request references a block above the one the chain is pinned to.
+    BeyondPinnedBlock(Option<Value>),
+
+    /// The chain is under maintenance and its result isn't cached.
+    UnderMaintenance(Option<Value>),
+
+    /// The namespace of the method isn't supported by the upstreams, as probed at startup.
+    UnsupportedMethod(Option<Value>),
 }
 
 impl DefinedError {
@@ -165,6 +243,27 @@ impl DefinedError {
             DefinedError::MethodNotFound => (-32601, "Method does not exist".to_string()),
             DefinedError::InvalidParams => (-32602, "Invalid method parameters".to_string()),
             DefinedError::InternalError(_) => (-32603, "Internal JSON-RPC error".to_string()),
+            DefinedError::UpstreamUnavailable(_) => (-32050, "Upstream unavailable".to_string()),
+            DefinedError::InvalidUpstreamResponse(_) => {
+                (-32051, "Invalid upstream response".to_string())
+            }
+            DefinedError::CacheUnavailable(_) => (-32052, "Cache backend unavailable".to_string()),
+            DefinedError::ChainUnavailable(_) => (-32053, "Chain unavailable".to_string()),
+            DefinedError::CachePoolExhausted(_) => {
+                (-32054, "Cache connection pool exhausted".to_string())
+            }
+            DefinedError::QuotaExceeded(_) => (-32055, "Quota exceeded".to_string()),
+            DefinedError::NotCached(_) => (-32056, "Result not cached".to_string()),
+            DefinedError::BeyondPinnedBlock(_) => {
+                (-32057, "Block beyond the pinned block".to_string())
+            }
+            DefinedError::UnsupportedMethod(_) => {
+                (-32601, "Method not supported by the upstreams".to_string())
+            }
+            DefinedError::UnderMaintenance(_) => (
+                -32058,
+                "Temporarily unavailable, chain under maintenance".to_string(),
+            ),
         }
     }
 
@@ -175,6 +274,16 @@ impl DefinedError {
             DefinedError::MethodNotFound => &None,
             DefinedError::InvalidParams => &None,
             DefinedError::InternalError(err) => err,
+            DefinedError::UpstreamUnavailable(err) => err,
+            DefinedError::InvalidUpstreamResponse(err) => err,
+            DefinedError::CacheUnavailable(err) => err,
+            DefinedError::ChainUnavailable(err) => err,
+            DefinedError::CachePoolExhausted(err) => err,
+            DefinedError::QuotaExceeded(err) => err,
+            DefinedError::NotCached(err) => err,
+            DefinedError::BeyondPinnedBlock(err) => err,
+            DefinedError::UnderMaintenance(err) => err,
+            DefinedError::UnsupportedMethod(err) => err,
         }
     }
 }
@@ -195,3 +304,41 @@ impl Serialize for DefinedError {
         error.serialize(serializer)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stream_batch_response() {
+        let responses = (0..RESPONSES_PER_CHUNK as u64 * 2 + 1)
+            .map(|id| match id % 3 {
+                0 => None,
+                _ => Some(JsonRpcResponse::from_result(
+                    RequestId::try_from(json!(id)).unwrap(),
+                    json!(format!("0x{id:x}")),
+                )),
+            })
+            .collect::<Vec<_>>();
+
+        let streamed = futures::executor::block_on(
+            stream_batch_response(responses.clone())
+                .map(|chunk| chunk.unwrap().to_vec())
+                .concat(),
+        );
+
+        assert_eq!(streamed, serde_json::to_vec(&responses).unwrap());
+    }
+
+    #[test]
+    fn test_stream_empty_batch_response() {
+        let streamed = futures::executor::block_on(
+            stream_batch_response(vec![])
+                .map(|chunk| chunk.unwrap().to_vec())
+                .concat(),
+        );
+
+        assert_eq!(streamed, b"[]".as_slice());
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Seconds a token's `iat` may be off from the current time, as in geth's authenticated RPC.
+const IAT_WINDOW_SECS: u64 = 60;
+
+/// `{"alg":"HS256","typ":"JWT"}`
+const HEADER: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9";
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    iat: u64,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+/// Shared secret of HS256 tokens, as used by the Engine API of execution clients.
+pub struct JwtSecret {
+    key: Vec<u8>,
+}
+
+impl JwtSecret {
+    pub fn new(key: Vec<u8>) -> Self {
+        Self { key }
+    }
+
+    /// Reads a hex encoded secret, e.g. the `jwtsecret` file of geth.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("fail to read jwt secret {}", path.display()))?;
+        let content = content.trim();
+        let key = hex::decode(content.strip_prefix("0x").unwrap_or(content))
+            .context("fail to decode jwt secret")?;
+
+        anyhow::ensure!(!key.is_empty(), "jwt secret is empty");
+        Ok(Self::new(key))
+    }
+
+    /// A token issued now.
+    pub fn issue(&self) -> String {
+        self.issue_at(unix_secs())
+    }
+
+    fn issue_at(&self, iat: u64) -> String {
+        // It's safe to unwrap here because the claims are plain numbers.
+        let claims = serde_json::to_vec(&Claims { iat }).unwrap();
+        let signing_input = format!("{HEADER}.{}", URL_SAFE_NO_PAD.encode(claims));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&signing_input).finalize().into_bytes());
+
+        format!("{signing_input}.{signature}")
+    }
+
+    /// Checks that `token` is signed with the secret and was issued recently.
+    pub fn validate(&self, token: &str) -> anyhow::Result<()> {
+        let (signing_input, signature) = token.rsplit_once('.').context("malformed token")?;
+        let (header, claims) = signing_input.split_once('.').context("malformed token")?;
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .context("malformed signature")?;
+        self.mac(signing_input)
+            .verify_slice(&signature)
+            .ok()
+            .context("invalid signature")?;
+
+        let header: Header =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?).context("malformed header")?;
+        anyhow::ensure!(
+            header.alg == "HS256",
+            "unsupported algorithm {}",
+            header.alg
+        );
+
+        let claims: Claims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?).context("malformed claims")?;
+        anyhow::ensure!(
+            claims.iat.abs_diff(unix_secs()) <= IAT_WINDOW_SECS,
+            "stale token"
+        );
+
+        Ok(())
+    }
+
+    /// Checks the bearer token of `req`.
+    pub fn authorize(&self, req: &HttpRequest) -> anyhow::Result<()> {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .context("missing bearer token")?;
+
+        self.validate(token)
+    }
+
+    fn mac(&self, signing_input: &str) -> Hmac<Sha256> {
+        // It's safe to unwrap here because HMAC accepts keys of any length.
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let secret = JwtSecret::new(vec![7; 32]);
+
+        assert!(secret.validate(&secret.issue()).is_ok());
+        assert!(JwtSecret::new(vec![8; 32])
+            .validate(&secret.issue())
+            .is_err());
+
+        let stale = secret.issue_at(unix_secs() - 2 * IAT_WINDOW_SECS);
+        assert_eq!(
+            secret.validate(&stale).unwrap_err().to_string(),
+            "stale token"
+        );
+
+        assert!(secret.validate("not-a-token").is_err());
+    }
+
+    #[test]
+    fn test_authorize() {
+        let secret = JwtSecret::new(vec![7; 32]);
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((AUTHORIZATION, format!("Bearer {}", secret.issue())))
+            .to_http_request();
+        assert!(secret.authorize(&req).is_ok());
+
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        assert!(secret.authorize(&req).is_err());
+    }
+
+    #[test]
+    fn test_header() {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        assert_eq!(header, HEADER);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Per cache key locks held while a missed key is being fetched from the upstream, so that concurrent misses
+/// of the same key wait for the first one instead of stampeding the upstream.
+#[derive(Default)]
+pub struct KeyLocks {
+    locks: Arc<DashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl KeyLocks {
+    /// Locks `key` if nobody else holds it. Returns the lock to wait for otherwise.
+    pub fn try_lock(&self, key: &str) -> Result<KeyLockGuard, PendingKeyLock> {
+        let lock = self.lock_of(key);
+
+        match lock.clone().try_lock_owned() {
+            Ok(guard) => Ok(self.guard(key, guard)),
+            Err(_) => Err(PendingKeyLock {
+                key: key.to_string(),
+                lock,
+            }),
+        }
+    }
+
+    /// Waits until the holder of the key is done and locks it in turn.
+    pub async fn lock(&self, pending: PendingKeyLock) -> KeyLockGuard {
+        let guard = pending.lock.lock_owned().await;
+        self.guard(&pending.key, guard)
+    }
+
+    fn lock_of(&self, key: &str) -> Arc<Mutex<()>> {
+        // Avoid allocating the key for keys locked right now.
+        if let Some(lock) = self.locks.get(key) {
+            return lock.clone();
+        }
+
+        self.locks.entry(key.to_string()).or_default().clone()
+    }
+
+    fn guard(&self, key: &str, guard: OwnedMutexGuard<()>) -> KeyLockGuard {
+        KeyLockGuard {
+            key: key.to_string(),
+            locks: self.locks.clone(),
+            guard: Some(guard),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks.len()
+    }
+}
+
+/// A key locked by someone else.
+pub struct PendingKeyLock {
+    key: String,
+    lock: Arc<Mutex<()>>,
+}
+
+/// Unlocks the key when dropped, and forgets it unless others are waiting for it.
+pub struct KeyLockGuard {
+    key: String,
+    locks: Arc<DashMap<String, Arc<Mutex<()>>>>,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyLockGuard {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        // Waiters hold a reference of their own, so the lock is only shared with the map once unused.
+        self.locks
+            .remove_if(&self.key, |_, lock| Arc::strong_count(lock) == 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_lock() {
+        let locks = KeyLocks::default();
+
+        let guard = locks.try_lock("a").ok().unwrap();
+        let pending = locks.try_lock("a").err().unwrap();
+        assert!(locks.try_lock("b").is_ok());
+
+        drop(guard);
+        let guard = futures::executor::block_on(locks.lock(pending));
+        assert_eq!(locks.len(), 1);
+
+        drop(guard);
+        assert_eq!(locks.len(), 0);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeSet;
+
+use alloy_primitives::U64;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::rpc_cache_handler::common;
+
+/// Chunk queries a single request may plan, requests needing more are served the regular way.
+const MAX_CHUNK_QUERIES: usize = 256;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LogIndexConfig {
+    /// Blocks per chunk, chunks start at multiples of it.
+    #[serde(default = "default_chunk_blocks")]
+    pub chunk_blocks: u64,
+}
+
+fn default_chunk_blocks() -> u64 {
+    1000
+}
+
+/// An `eth_getLogs` filter over a block range.
+#[derive(Debug, PartialEq)]
+struct LogFilter {
+    from_block: u64,
+    to_block: u64,
+    /// Lowercase, since clients send checksummed addresses.
+    addresses: BTreeSet<String>,
+    /// Topics by position, `None` matching any topic.
+    topics: Vec<Option<BTreeSet<String>>>,
+}
+
+impl LogFilter {
+    fn parse(filter: &Value, latest: u64) -> Option<Self> {
+        let block = |bound: &Value| match bound {
+            Value::Null => Some(latest),
+            bound => match common::parse_block_number(bound) {
+                Ok(Some(block)) => Some(block),
+                Ok(None) if bound == "latest" => Some(latest),
+                _ => None,
+            },
+        };
+
+        let addresses = match &filter["address"] {
+            Value::String(address) => BTreeSet::from([address.to_lowercase()]),
+            Value::Array(addresses) => addresses
+                .iter()
+                .map(|address| address.as_str().map(str::to_lowercase))
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        let topics = match &filter["topics"] {
+            Value::Null => vec![],
+            Value::Array(topics) => topics
+                .iter()
+                .map(|topic| match topic {
+                    Value::Null => Some(None),
+                    Value::String(topic) => Some(Some(BTreeSet::from([topic.to_lowercase()]))),
+                    Value::Array(topics) => topics
+                        .iter()
+                        .map(|topic| topic.as_str().map(str::to_lowercase))
+                        .collect::<Option<_>>()
+                        .map(Some),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        Some(Self {
+            from_block: block(&filter["fromBlock"])?,
+            to_block: block(&filter["toBlock"])?,
+            addresses,
+            topics,
+        })
+    }
+
+    /// The first topic every matching log has, if there's a single one.
+    fn topic0(&self) -> Option<&String> {
+        match self.topics.first() {
+            Some(Some(topics)) if topics.len() == 1 => topics.first(),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, log: &Value) -> bool {
+        let Some(block) = serde_json::from_value::<U64>(log["blockNumber"].clone()).ok() else {
+            return false;
+        };
+        if !(self.from_block..=self.to_block).contains(&block.to::<u64>()) {
+            return false;
+        }
+
+        let address = log["address"].as_str().unwrap_or_default().to_lowercase();
+        if !self.addresses.contains(&address) {
+            return false;
+        }
+
+        let log_topics = log["topics"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        self.topics
+            .iter()
+            .enumerate()
+            .all(|(position, topics)| match topics {
+                None => true,
+                Some(topics) => log_topics
+                    .get(position)
+                    .and_then(Value::as_str)
+                    .is_some_and(|topic| topics.contains(&topic.to_lowercase())),
+            })
+    }
+}
+
+/// The logs of a single address, optionally with a given first topic, in a chunk. These are cached like any other
+/// `eth_getLogs` request, so that filters with different ranges or topics can share them.
+pub struct ChunkQuery {
+    address: String,
+    topic0: Option<String>,
+    from_block: u64,
+    to_block: u64,
+}
+
+impl ChunkQuery {
+    /// Params of the cached requests containing the logs of the query, broadest first. Only the last one is
+    /// fetched if none of them is cached.
+    pub fn candidates(&self) -> Vec<Value> {
+        let params = |topics: Option<Value>| {
+            let mut filter = json!({
+                "address": self.address,
+                "fromBlock": format!("{:#x}", self.from_block),
+                "toBlock": format!("{:#x}", self.to_block),
+            });
+            if let Some(topics) = topics {
+                filter["topics"] = topics;
+            }
+            json!([filter])
+        };
+
+        let mut candidates = vec![params(None)];
+        if let Some(topic0) = &self.topic0 {
+            candidates.push(params(Some(json!([topic0]))));
+        }
+        candidates
+    }
+}
+
+/// How a filter is answered from the index.
+pub struct IndexPlan {
+    filter: LogFilter,
+    /// Of the chunks that are confirmed.
+    queries: Vec<ChunkQuery>,
+    /// Blocks too close to the head to be indexed, fetched with the client filter.
+    live_range: Option<(u64, u64)>,
+}
+
+impl IndexPlan {
+    pub fn queries(&self) -> &[ChunkQuery] {
+        &self.queries
+    }
+
+    pub fn live_range(&self) -> Option<(u64, u64)> {
+        self.live_range
+    }
+
+    /// The logs matching the filter among the logs of the chunks and the live range, in chain order.
+    pub fn select(&self, logs: Vec<Value>) -> Vec<Value> {
+        let position = |log: &Value| {
+            let number =
+                |value: &Value| serde_json::from_value::<U64>(value.clone()).unwrap_or_default();
+            (number(&log["blockNumber"]), number(&log["logIndex"]))
+        };
+
+        let mut logs = logs
+            .into_iter()
+            .filter(|log| self.filter.matches(log))
+            .collect::<Vec<_>>();
+        logs.sort_by_key(position);
+        logs
+    }
+}
+
+/// How the `eth_getLogs` request with `params` is answered from the index, `None` if it can't be, e.g. because
+/// it isn't restricted to some addresses or none of its blocks are confirmed yet.
+pub fn plan(
+    params: &Value,
+    config: &LogIndexConfig,
+    latest: Option<u64>,
+    confirmation_depth: u64,
+) -> Option<IndexPlan> {
+    let filter = params
+        .as_array()
+        .filter(|params| params.len() == 1)?
+        .first()?;
+    if !filter["blockHash"].is_null() || config.chunk_blocks == 0 {
+        return None;
+    }
+
+    let latest = latest?;
+    let filter = LogFilter::parse(filter, latest)?;
+    if filter.from_block > filter.to_block {
+        return None;
+    }
+
+    let confirmed = latest.checked_sub(confirmation_depth)?;
+    let chunk_start = |block: u64| block - block % config.chunk_blocks;
+    let chunk_end = |start: u64| start.saturating_add(config.chunk_blocks - 1);
+
+    let mut chunks = vec![];
+    let mut start = chunk_start(filter.from_block);
+    while start <= filter.to_block && chunk_end(start) <= confirmed {
+        chunks.push((start, chunk_end(start)));
+        start = chunk_end(start) + 1;
+    }
+
+    if chunks.is_empty() || chunks.len() * filter.addresses.len() > MAX_CHUNK_QUERIES {
+        return None;
+    }
+
+    let live_range =
+        (start <= filter.to_block).then(|| (start.max(filter.from_block), filter.to_block));
+
+    let topic0 = filter.topic0().cloned();
+    let queries = filter
+        .addresses
+        .iter()
+        .flat_map(|address| {
+            chunks.iter().map(|&(from_block, to_block)| ChunkQuery {
+                address: address.clone(),
+                topic0: topic0.clone(),
+                from_block,
+                to_block,
+            })
+        })
+        .collect();
+
+    Some(IndexPlan {
+        filter,
+        queries,
+        live_range,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    #[test]
+    fn test_plan() {
+        let config = LogIndexConfig { chunk_blocks: 100 };
+        let params = json!([{
+            "address": "0xB59F67A8BFF5D8CD03F6AC17265C550ED8F33907",
+            "fromBlock": "0x96",
+            "toBlock": "latest",
+            "topics": [TRANSFER],
+        }]);
+
+        let plan = plan(&params, &config, Some(420), 10).unwrap();
+        let chunks = plan
+            .queries()
+            .iter()
+            .map(|query| (query.from_block, query.to_block))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, vec![(100, 199), (200, 299), (300, 399)]);
+        assert_eq!(plan.live_range(), Some((400, 420)));
+        assert_eq!(
+            plan.queries()[0].candidates(),
+            vec![
+                json!([{
+                    "address": "0xb59f67a8bff5d8cd03f6ac17265c550ed8f33907",
+                    "fromBlock": "0x64",
+                    "toBlock": "0xc7",
+                }]),
+                json!([{
+                    "address": "0xb59f67a8bff5d8cd03f6ac17265c550ed8f33907",
+                    "fromBlock": "0x64",
+                    "toBlock": "0xc7",
+                    "topics": [TRANSFER],
+                }]),
+            ]
+        );
+
+        // Unrestricted filters would need to index every log.
+        assert!(super::plan(
+            &json!([{ "fromBlock": "0x0", "toBlock": "0x200" }]),
+            &config,
+            Some(420),
+            10
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_select() {
+        let config = LogIndexConfig { chunk_blocks: 100 };
+        let params = json!([{
+            "address": ["0x01", "0x02"],
+            "fromBlock": "0x96",
+            "toBlock": "0x12c",
+            "topics": [null, ["0xaa", "0xbb"]],
+        }]);
+        let plan = plan(&params, &config, Some(1000), 0).unwrap();
+
+        let log = |address: &str, block: u64, log_index: u64, topics: Value| {
+            json!({
+                "address": address,
+                "blockNumber": format!("{block:#x}"),
+                "logIndex": format!("{log_index:#x}"),
+                "topics": topics,
+            })
+        };
+        let logs = vec![
+            log("0x01", 200, 3, json!(["0x00", "0xaa"])),
+            // Before the range of the filter.
+            log("0x01", 120, 0, json!(["0x00", "0xaa"])),
+            log("0x01", 200, 4, json!(["0x00", "0xcc"])),
+            log("0x02", 200, 1, json!(["0x00", "0xbb"])),
+            log("0x03", 200, 2, json!(["0x00", "0xbb"])),
+            log("0x02", 160, 7, json!(["0x00", "0xAA"])),
+        ];
+
+        assert_eq!(
+            plan.select(logs),
+            vec![
+                log("0x02", 160, 7, json!(["0x00", "0xAA"])),
+                log("0x02", 200, 1, json!(["0x00", "0xbb"])),
+                log("0x01", 200, 3, json!(["0x00", "0xaa"])),
+            ]
+        );
+    }
+}
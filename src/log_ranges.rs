@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+
+use crate::cache::{CacheBackend, CacheStatus};
+use crate::concurrency::Priority;
+use crate::json_rpc::{DefinedError, DefinedOrCustomError, JsonRpcResponse, ResultOrError};
+use crate::log_index::{self, IndexPlan};
+use crate::rpc_cache_handler::common;
+use crate::{forward_uncached_requests, ChainState, RpcRequest};
+
+pub const GET_LOGS: &str = "eth_getLogs";
+
+/// Pieces of a request fetched at once.
+const MAX_CONCURRENT_RANGES: usize = 8;
+
+/// Block ranges of at most `max_range` blocks covering the range of the `eth_getLogs` filter in `params`, `None`
+/// if the filter doesn't need splitting. Open ends of the range are resolved to the `latest` block.
+pub fn split(params: &Value, max_range: u64, latest: Option<u64>) -> Option<Vec<(u64, u64)>> {
+    let filter = params.get(0)?;
+    if !filter["blockHash"].is_null() || max_range == 0 {
+        return None;
+    }
+
+    let block = |bound: &Value| match bound {
+        Value::Null => latest,
+        bound => match common::parse_block_number(bound) {
+            Ok(Some(block)) => Some(block),
+            Ok(None) if bound == "latest" => latest,
+            _ => None,
+        },
+    };
+    let (from_block, to_block) = (block(&filter["fromBlock"])?, block(&filter["toBlock"])?);
+    if from_block > to_block || to_block - from_block < max_range {
+        return None;
+    }
+
+    let ranges = (from_block..=to_block)
+        .step_by(max_range as usize)
+        .map(|start| (start, to_block.min(start + (max_range - 1))))
+        .collect();
+    Some(ranges)
+}
+
+/// How an `eth_getLogs` request is answered piecewise instead of being forwarded as is.
+pub enum Plan {
+    /// Sub-ranges providers accept, see `ChainConfig::max_logs_range`.
+    Ranges(Vec<(u64, u64)>),
+    /// Chunks of the log index, see `ChainConfig::log_index`.
+    Indexed(IndexPlan),
+}
+
+/// How `params` of an `eth_getLogs` request are answered, `None` if they're forwarded as is.
+pub fn plan(chain_state: &ChainState, params: &Value) -> Option<Plan> {
+    let latest = chain_state.head.latest();
+
+    let indexed = chain_state
+        .log_index
+        .as_ref()
+        .and_then(|config| log_index::plan(params, config, latest, chain_state.confirmation_depth));
+    if let Some(plan) = indexed {
+        return Some(Plan::Indexed(plan));
+    }
+
+    let ranges = split(params, chain_state.max_logs_range?, latest)?;
+    Some(Plan::Ranges(ranges))
+}
+
+/// Answers an `eth_getLogs` request piecewise as planned, from the cache if possible, and combines the logs of the
+/// pieces. Pieces that are confirmed get cached by themselves.
+pub async fn fetch(
+    chain_state: Arc<ChainState>,
+    rpc_request: RpcRequest,
+    plan: Plan,
+    bypass_cache: bool,
+    cache_only: bool,
+    priority: Priority,
+    forwarded_headers: HeaderMap,
+) -> (usize, JsonRpcResponse) {
+    let parts = match pieces(&chain_state, &rpc_request, &plan, bypass_cache) {
+        Ok(parts) => parts,
+        Err(err) => {
+            return (
+                rpc_request.index,
+                JsonRpcResponse::from_error(Some(rpc_request.id), err),
+            );
+        }
+    };
+
+    let results = futures::stream::iter(parts)
+        .map(|part| {
+            let (chain_state, forwarded_headers) = (&chain_state, &forwarded_headers);
+            async move {
+                let sub_request = match part {
+                    Ok(result) => return Ok(result),
+                    Err(sub_request) => sub_request,
+                };
+
+                if cache_only {
+                    return Err(DefinedOrCustomError::Defined(DefinedError::NotCached(None)));
+                }
+
+                let upstreams = chain_state.route(&sub_request.method, &sub_request.params);
+                let response = forward_uncached_requests(
+                    chain_state,
+                    upstreams,
+                    vec![sub_request],
+                    priority,
+                    forwarded_headers,
+                )
+                .await
+                .pop()
+                .map(|(_, response)| response.result);
+
+                match response {
+                    Some(ResultOrError::Result { result }) => Ok(result),
+                    Some(ResultOrError::Error { error }) => Err(error),
+                    None => Err(DefinedOrCustomError::Defined(
+                        DefinedError::InvalidUpstreamResponse(None),
+                    )),
+                }
+            }
+        })
+        .buffered(MAX_CONCURRENT_RANGES)
+        .collect::<Vec<_>>()
+        .await;
+
+    let logs = merge(results).map(|logs| match &plan {
+        Plan::Ranges(_) => logs,
+        Plan::Indexed(plan) => plan.select(logs),
+    });
+
+    let id = rpc_request.id;
+    let response = match logs {
+        Ok(logs) => JsonRpcResponse::from_result(id, Value::Array(logs)),
+        Err(DefinedOrCustomError::Defined(error)) => JsonRpcResponse::from_error(Some(id), error),
+        Err(DefinedOrCustomError::Custom(error)) => {
+            JsonRpcResponse::from_custom_error(Some(id), error)
+        }
+    };
+
+    (rpc_request.index, response)
+}
+
+/// Answers an `eth_getLogs` request like the ones of clients, piecewise if planned. Only planned requests can be
+/// answered from the cache, the others fail if `cache_only`.
+pub async fn get_logs(
+    chain_state: Arc<ChainState>,
+    rpc_request: RpcRequest,
+    cache_only: bool,
+    priority: Priority,
+    forwarded_headers: HeaderMap,
+) -> (usize, JsonRpcResponse) {
+    if let Some(plan) = plan(&chain_state, &rpc_request.params) {
+        return fetch(
+            chain_state,
+            rpc_request,
+            plan,
+            false,
+            cache_only,
+            priority,
+            forwarded_headers,
+        )
+        .await;
+    }
+
+    let (index, id) = (rpc_request.index, rpc_request.id.clone());
+    if cache_only {
+        let response = JsonRpcResponse::from_error(Some(id), DefinedError::NotCached(None));
+        return (index, response);
+    }
+
+    let upstreams = chain_state.route(&rpc_request.method, &rpc_request.params);
+    forward_uncached_requests(
+        &chain_state,
+        upstreams,
+        vec![rpc_request],
+        priority,
+        &forwarded_headers,
+    )
+    .await
+    .pop()
+    .unwrap_or_else(|| {
+        let response =
+            JsonRpcResponse::from_error(Some(id), DefinedError::InvalidUpstreamResponse(None));
+        (index, response)
+    })
+}
+
+/// Cached logs of the pieces of the plan, or the requests fetching them.
+fn pieces(
+    chain_state: &ChainState,
+    rpc_request: &RpcRequest,
+    plan: &Plan,
+    bypass_cache: bool,
+) -> Result<Vec<Result<Arc<Value>, RpcRequest>>, DefinedError> {
+    let mut cache_backend = chain_state.cache_factory.get_instance().map_err(|err| {
+        DefinedError::CacheUnavailable(Some(json!({
+            "reason": format!("{err:#}"),
+        })))
+    })?;
+    let mut lookup = Lookup {
+        chain_state,
+        cache_backend: cache_backend.as_mut(),
+        rpc_request,
+        bypass_cache,
+    };
+
+    let pieces = match plan {
+        Plan::Ranges(ranges) => ranges
+            .iter()
+            .map(|&(from_block, to_block)| {
+                let is_confirmed = chain_state.head.latest().is_some_and(|latest| {
+                    to_block.saturating_add(chain_state.confirmation_depth) <= latest
+                });
+                let params = with_range(&rpc_request.params, from_block, to_block);
+                lookup.lookup(params, is_confirmed)
+            })
+            .collect(),
+        Plan::Indexed(plan) => {
+            let mut pieces = plan
+                .queries()
+                .iter()
+                .map(|query| {
+                    let mut candidates = query.candidates();
+                    // It's safe to unwrap here because a query always has at least one candidate.
+                    let narrowest = candidates.pop().unwrap();
+                    candidates
+                        .into_iter()
+                        .find_map(|candidate| lookup.cached(&candidate))
+                        .map_or_else(|| lookup.lookup(narrowest, true), Ok)
+                })
+                .collect::<Vec<_>>();
+
+            if let Some((from_block, to_block)) = plan.live_range() {
+                let params = with_range(&rpc_request.params, from_block, to_block);
+                pieces.push(lookup.lookup(params, false));
+            }
+            pieces
+        }
+    };
+
+    Ok(pieces)
+}
+
+/// `params` of an `eth_getLogs` request restricted to the blocks from `from_block` to `to_block`.
+pub fn with_range(params: &Value, from_block: u64, to_block: u64) -> Value {
+    let mut params = params.clone();
+    params[0]["fromBlock"] = json!(format!("{from_block:#x}"));
+    params[0]["toBlock"] = json!(format!("{to_block:#x}"));
+    params
+}
+
+struct Lookup<'a> {
+    chain_state: &'a ChainState,
+    cache_backend: &'a mut dyn CacheBackend,
+    rpc_request: &'a RpcRequest,
+    bypass_cache: bool,
+}
+
+impl Lookup<'_> {
+    fn params_key(&self, params: &Value) -> Option<String> {
+        let cache_entry = self.chain_state.cache_entries.get(GET_LOGS)?;
+        self.chain_state
+            .params_key(cache_entry, params)
+            .ok()
+            .flatten()
+    }
+
+    /// Cached logs of `params`.
+    fn cached(&mut self, params: &Value) -> Option<Arc<Value>> {
+        if self.bypass_cache {
+            return None;
+        }
+
+        match self.cache_backend.read(GET_LOGS, &self.params_key(params)?) {
+            Ok(CacheStatus::Cached { value, .. }) => {
+                self.chain_state.stats.record_cache_hit(GET_LOGS);
+                Some(Arc::new(value))
+            }
+            Ok(CacheStatus::Missed { .. }) => None,
+            Err(err) => {
+                tracing::error!("fail to read cache because: {err:#}");
+                None
+            }
+        }
+    }
+
+    /// Cached logs of `params` if they're `cacheable`, or else the request fetching them, which caches them if
+    /// they're `cacheable`.
+    fn lookup(&mut self, params: Value, cacheable: bool) -> Result<Arc<Value>, RpcRequest> {
+        let params_key = self.params_key(&params).filter(|_| cacheable);
+        let key = match params_key.map(|params_key| self.cache_backend.read(GET_LOGS, &params_key))
+        {
+            Some(Ok(CacheStatus::Cached { value, .. })) if !self.bypass_cache => {
+                self.chain_state.stats.record_cache_hit(GET_LOGS);
+                return Ok(Arc::new(value));
+            }
+            Some(Ok(CacheStatus::Cached { key, .. } | CacheStatus::Missed { key })) => Some(key),
+            Some(Err(err)) => {
+                tracing::error!("fail to read cache because: {err:#}");
+                None
+            }
+            None => None,
+        };
+
+        let sub_request = RpcRequest::new_uncachable(
+            self.rpc_request.index,
+            self.rpc_request.id.clone(),
+            GET_LOGS.to_string(),
+            params,
+        );
+        Err(RpcRequest {
+            cache_key: key,
+            ..sub_request
+        })
+    }
+}
+
+/// Logs of every piece in order, or the first error.
+fn merge(
+    results: Vec<Result<Arc<Value>, DefinedOrCustomError>>,
+) -> Result<Vec<Value>, DefinedOrCustomError> {
+    let mut logs = vec![];
+    for result in results {
+        match result?.as_ref() {
+            Value::Array(sub_logs) => logs.extend(sub_logs.iter().cloned()),
+            Value::Null => {}
+            result => {
+                return Err(DefinedOrCustomError::Defined(
+                    DefinedError::InvalidUpstreamResponse(Some(json!({
+                        "reason": "array is expected",
+                        "response": result,
+                    }))),
+                ))
+            }
+        }
+    }
+    Ok(logs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let filter = |from_block: Value, to_block: Value| json!([{ "fromBlock": from_block, "toBlock": to_block }]);
+
+        assert_eq!(split(&filter(json!("0x0"), json!("0x9")), 10, None), None);
+        assert_eq!(
+            split(&filter(json!("0x0"), json!("0xa")), 10, None),
+            Some(vec![(0, 9), (10, 10)])
+        );
+        assert_eq!(
+            split(&filter(json!("0x64"), json!("latest")), 50, Some(250)),
+            Some(vec![(100, 149), (150, 199), (200, 249), (250, 250)])
+        );
+        assert_eq!(
+            split(&filter(json!("0x64"), json!("latest")), 50, None),
+            None
+        );
+        assert_eq!(
+            split(&json!([{ "blockHash": "0x00" }]), 10, Some(1000)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let logs = |logs: Value| Ok(Arc::new(logs));
+
+        assert_eq!(
+            merge(vec![logs(json!([1, 2])), logs(json!([])), logs(json!([3]))]).ok(),
+            Some(vec![json!(1), json!(2), json!(3)])
+        );
+        assert!(matches!(
+            merge(vec![
+                logs(json!([1])),
+                Err(DefinedOrCustomError::Custom(json!({ "code": -32005 })))
+            ]),
+            Err(DefinedOrCustomError::Custom(error)) if error["code"] == -32005
+        ));
+    }
+}
@@ -1,47 +1,367 @@
-use std::collections::HashMap;
-
-use actix_web::{error, web, App, Error, HttpResponse, HttpServer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_cors::Cors;
+use actix_web::dev::{Server, Service};
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, ETag, EntityTag, IfNoneMatch,
+};
+use actix_web::http::KeepAlive;
+use actix_web::middleware::Condition;
+use actix_web::{error, web, App, Error, HttpMessage, HttpRequest, HttpResponse, HttpServer};
 use anyhow::Context;
 use cache::{memory_backend, CacheBackendFactory};
-use clap::Parser;
-use env_logger::Env;
+use futures::{future, FutureExt, StreamExt};
+use redis::IntoConnectionInfo;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Url;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use sha1::Digest;
+use tokio::sync::Semaphore;
 
-use crate::args::Args;
+use crate::access_log::RequestSummary;
+use crate::args::{AdminAddress, Args, Command};
+use crate::cache::key_filter::{KeyFilter, KeyFilterBackendFactory};
+use crate::cache::mode::{CacheMode, ModeBackendFactory};
 use crate::cache::redis_backend::RedisBackendFactory;
-use crate::cache::CacheStatus;
-use crate::json_rpc::{DefinedError, JsonRpcRequest, JsonRpcResponse, RequestId};
-use crate::rpc_cache_handler::RpcCacheHandler;
-
+use crate::cache::replication::{ReplicatedBackendFactory, WriteQueue};
+use crate::cache::{CacheBackend, CacheStatus, EntryMetadata, PoolExhausted};
+use crate::concurrency::{Priority, UpstreamLimits};
+use crate::config::{ChainConfig, Config, RedisConfig};
+use crate::event_stream::EventStream;
+use crate::failure_injection::FailureInjectionConfig;
+use crate::filters::Filters;
+use crate::head_tracker::HeadTracker;
+use crate::hot_keys::HotKeys;
+use crate::ip_filter::IpFilters;
+use crate::json_rpc::{DefinedError, JsonRpcRequest, JsonRpcResponse, RequestId, ResultOrError};
+use crate::jwt::JwtSecret;
+use crate::key_lock::KeyLocks;
+use crate::log_index::LogIndexConfig;
+use crate::method_aliases::MethodAliasConfig;
+use crate::params_rewrite::ParamsRewriteConfig;
+use crate::peers::Peers;
+use crate::quorum::{QuorumConfig, Verdict, Witnesses};
+use crate::quota::{ApiKeys, QuotaExceeded};
+use crate::response_matcher::ResponseMatcher;
+use crate::rpc_cache_handler::{common, HandlerRegistry, RpcCacheHandler};
+use crate::shadow_verify::SampledHit;
+use crate::static_responses::StaticResponseConfig;
+use crate::stats::ChainStats;
+use crate::transactions::{RecentTransactions, TransactionConfig};
+use crate::transport::UpstreamClient;
+use crate::upstream::{is_rate_limited, Upstream, UpstreamConfig, UpstreamPool, UpstreamRouter};
+
+mod access_log;
+mod alerting;
+mod analyze;
 mod args;
+mod backup;
+mod bench;
 mod cache;
+mod canary;
+mod capabilities;
+mod chain_registry;
+mod client_ip;
+mod concurrency;
+mod config;
+mod event_stream;
+mod failure_injection;
+mod filters;
+mod gc;
+mod head_tracker;
+mod hot_keys;
+mod inspect;
+mod ip_filter;
 mod json_rpc;
+mod jwt;
+mod key_lock;
+mod log_index;
+mod log_ranges;
+mod maintenance;
+mod method_aliases;
+mod mirror;
+mod params_rewrite;
+mod peers;
+mod pool_status;
+mod quorum;
+mod quota;
+mod response_matcher;
 mod rpc_cache_handler;
+mod shadow_verify;
+mod snapshot;
+mod static_responses;
+mod stats;
+mod tls;
+mod transactions;
+mod transport;
+mod upstream;
 mod utils;
 
 #[actix_web::post("/{chain}")]
 async fn rpc_call(
+    req: HttpRequest,
     path: web::Path<(String,)>,
     data: web::Data<AppState>,
     body: web::Json<Value>,
 ) -> Result<HttpResponse, Error> {
     let (chain,) = path.into_inner();
-    let chain_state = data
-        .chains
-        .get(&chain.to_uppercase())
+    if body.is_array() && event_stream::is_requested(&req) {
+        return Ok(serve_event_stream(req, chain, data, body));
+    }
+    if data.reorder_batches && is_big_batch(&body) {
+        return serve_reordered_batch(req, chain, data, body).await;
+    }
+
+    serve_rpc_call(req, &chain, data, body, None).await
+}
+
+/// Serves the chain given by `--default-chain` at `/`, so that the proxy can replace the URL of a single node.
+#[actix_web::post("/")]
+async fn default_rpc_call(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<Value>,
+) -> Result<HttpResponse, Error> {
+    let chain = data
+        .default_chain
+        .clone()
+        .ok_or_else(|| error::ErrorNotFound("endpoint not supported"))?;
+    if body.is_array() && event_stream::is_requested(&req) {
+        return Ok(serve_event_stream(req, chain, data, body));
+    }
+    if data.reorder_batches && is_big_batch(&body) {
+        return serve_reordered_batch(req, chain, data, body).await;
+    }
+
+    serve_rpc_call(req, &chain, data, body, None).await
+}
+
+#[derive(Deserialize)]
+struct GetRpcQuery {
+    /// JSON encoded params, none if unset.
+    params: Option<String>,
+}
+
+/// Serves single requests as `GET /{chain}/{method}?params=[..]`, e.g. for debugging with curl or for a CDN
+/// in front. Methods sending transactions or signing are only served over POST.
+#[actix_web::get("/{chain}/{method}")]
+async fn get_rpc_call(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<GetRpcQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (chain, method) = path.into_inner();
+    if method.starts_with("eth_send") || method.starts_with("eth_sign") {
+        return Err(error::ErrorMethodNotAllowed(
+            "method is only served over POST",
+        ));
+    }
+
+    let params = match &query.params {
+        Some(params) => serde_json::from_str(params).map_err(error::ErrorBadRequest)?,
+        None => Value::Array(vec![]),
+    };
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+
+    serve_rpc_call(req, &chain, data, web::Json(body), None).await
+}
+
+/// Answers a batch with server-sent events, one per response as soon as it's known, for clients of large batches
+/// that would rather not wait for the slowest request.
+fn serve_event_stream(
+    req: HttpRequest,
+    chain: String,
+    data: web::Data<AppState>,
+    body: web::Json<Value>,
+) -> HttpResponse {
+    let (mut events, event_body) = EventStream::new();
+
+    actix_web::rt::spawn(async move {
+        let response = serve_rpc_call(req, &chain, data, body, Some(&mut events)).await;
+
+        // Requests failing as a whole, e.g. for an unknown chain, get the usual body as their only event.
+        if !events.has_sent() {
+            let response = response.unwrap_or_else(|err| err.error_response());
+            if let Ok(body) = actix_web::body::to_bytes(response.into_body()).await {
+                events.send_data(&body);
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type(event_stream::CONTENT_TYPE)
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(event_body)
+}
+
+fn is_big_batch(body: &Value) -> bool {
+    body.as_array()
+        .is_some_and(|batch| batch.len() > json_rpc::STREAMED_BATCH_LEN)
+}
+
+/// Answers a big batch with its responses in the order they're known, so that cache hits are sent while misses
+/// are still being fetched. Batches failing as a whole are answered as usual.
+async fn serve_reordered_batch(
+    req: HttpRequest,
+    chain: String,
+    data: web::Data<AppState>,
+    body: web::Json<Value>,
+) -> Result<HttpResponse, Error> {
+    let (mut events, mut response_body) = EventStream::json_array();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+
+    actix_web::rt::spawn(async move {
+        let response = serve_rpc_call(req, &chain, data, body, Some(&mut events)).await;
+        match events.has_sent() {
+            true => events.finish(),
+            false => {
+                let _ = sender.send(response);
+            }
+        }
+    });
+
+    let dropped = || Err(error::ErrorInternalServerError("fail to serve batch"));
+
+    // The status is only committed to once the first responses are known.
+    match future::select(response_body.next(), receiver).await {
+        future::Either::Left((Some(first), _)) => Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .streaming(futures::stream::once(future::ready(first)).chain(response_body))),
+        future::Either::Left((None, receiver)) => receiver.await.unwrap_or_else(|_| dropped()),
+        future::Either::Right((response, _)) => response.unwrap_or_else(|_| dropped()),
+    }
+}
+
+/// Serves a single request or a batch. The responses of a batch are also sent to `events` as soon as they're
+/// known if set.
+async fn serve_rpc_call(
+    req: HttpRequest,
+    chain: &str,
+    data: web::Data<AppState>,
+    body: web::Json<Value>,
+    mut events: Option<&mut EventStream>,
+) -> Result<HttpResponse, Error> {
+    if let Some(jwt_secret) = &data.jwt_secret {
+        jwt_secret
+            .authorize(&req)
+            .map_err(|err| error::ErrorUnauthorized(format!("{err:#}")))?;
+    }
+
+    let slot = data
+        .chain_slot(chain)
         .ok_or_else(|| error::ErrorNotFound("endpoint not supported"))?;
 
+    // The chain is known but its upstreams couldn't be reached yet, it's retried in the background.
+    let Some(chain_state) = slot.get() else {
+        return Ok(
+            HttpResponse::ServiceUnavailable().json(JsonRpcResponse::from_error(
+                None,
+                DefinedError::ChainUnavailable(None),
+            )),
+        );
+    };
+
     let (requests, is_single_request) = match body {
         web::Json(Value::Array(requests)) => (requests, false),
         web::Json(Value::Object(obj)) => (vec![Value::Object(obj)], true),
         _ => return JsonRpcResponse::from_error(None, DefinedError::InvalidRequest).into(),
     };
 
-    let mut ordered_requests_result: Vec<Option<JsonRpcResponse>> = vec![None; requests.len()];
+    let batch_size = requests.len();
+
+    let api_key = match &data.api_keys {
+        Some(api_keys) => {
+            let api_key = api_keys
+                .key_of(&req)
+                .ok_or_else(|| error::ErrorUnauthorized("invalid api key"))?;
+
+            // Usage isn't enforced while it can't be counted, rather than failing every request.
+            if let Err(err) = api_keys.record_requests(&api_key, batch_size as u64) {
+                if err.is::<QuotaExceeded>() {
+                    let reason = Some(json!({
+                        "reason": format!("{err:#}"),
+                    }));
+                    return JsonRpcResponse::from_error(None, DefinedError::QuotaExceeded(reason))
+                        .into();
+                }
+
+                tracing::error!("fail to record usage of api key: {err:#}");
+            }
+
+            Some(api_key)
+        }
+        None => None,
+    };
+    let mut ordered_requests_result: Vec<Option<JsonRpcResponse>> = vec![None; batch_size];
+
+    macro_rules! send_new_events {
+        () => {
+            if let Some(events) = events.as_deref_mut() {
+                events.send_new(&ordered_requests_result);
+            }
+        };
+    }
+
+    let is_sampled = req.extensions().contains::<RequestSummary>();
+    let priority = Priority::of(&req);
+    // Fetches fresh results from the upstream and refreshes their cache entries, e.g. to rule out stale data.
+    let no_cache = req
+        .get_header::<CacheControl>()
+        .is_some_and(|cache_control| cache_control.0.contains(&CacheDirective::NoCache));
+    // Hits stored longer ago than this many seconds are fetched again, e.g. for clients sensitive to stale prices.
+    // Ignored without metadata, which the age of entries is taken from, rather than making every hit a miss.
+    let max_age = req
+        .get_header::<CacheControl>()
+        .and_then(|cache_control| {
+            cache_control
+                .0
+                .iter()
+                .find_map(|directive| match directive {
+                    CacheDirective::MaxAge(max_age) => Some(*max_age),
+                    _ => None,
+                })
+        })
+        .filter(|_| chain_state.store_metadata);
+    // Misses fail instead of reaching the upstream, e.g. for offline analysis against a pre-warmed cache.
+    let in_maintenance = chain_state.maintenance.load(Ordering::Relaxed);
+    let only_if_cached = data.offline
+        || in_maintenance
+        || req
+            .get_header::<CacheControl>()
+            .is_some_and(|cache_control| cache_control.0.contains(&CacheDirective::OnlyIfCached));
+    let forwarded_headers = forwarded_headers(&req, &chain_state.forward_headers);
+    let mut methods = BTreeSet::new();
+    let mut cache_hits = 0;
     let mut uncached_requests = vec![];
-    let mut request_id_index_map: HashMap<RequestId, usize> = HashMap::new();
+    let mut sampled_hits = vec![];
+    let mut missed_keys: HashMap<String, usize> = HashMap::new();
+    let mut duplicate_requests: Vec<(usize, RequestId, usize)> = vec![];
+    let mut waiting_requests = vec![];
+    // Sent to every upstream they're routed to, transactions and requests aggregated across the pool.
+    let mut broadcast_requests = vec![];
+    // Answered by the proxy from what it fetches itself, e.g. `eth_getLogs` requests split into sub-ranges providers
+    // accept and polls of emulated filters.
+    let mut local_requests = vec![];
+    // Transactions recorded for deduplication, forgotten again if sending them fails.
+    let mut sent_transactions = vec![];
+    // Of single requests hitting an entry, how long caches in front may keep the response.
+    let mut http_cache_control = None;
+    // Held until the fetched results have been cached.
+    let mut key_lock_guards = vec![];
+    // Locked across the instances sharing the cache, unlocked once the fetched results have been cached.
+    let mut remote_locked_keys = vec![];
+    // Misses of keys another instance sharing the cache is fetching.
+    let mut remote_waiting_requests = vec![];
 
     // Scope the redis connection
     {
@@ -49,44 +369,202 @@ async fn rpc_call(
             Ok(v) => v,
             Err(err) => {
                 tracing::error!("fail to get cache backend because: {err:#}");
-                return JsonRpcResponse::from_error(
-                    None,
-                    DefinedError::InternalError(Some(json!({
-                        "error": "fail to get cache backend",
-                        "reason": err.to_string(),
-                    }))),
-                )
-                .into();
+
+                let reason = Some(json!({
+                    "reason": format!("{err:#}"),
+                }));
+                let err = if err.is::<PoolExhausted>() {
+                    chain_state
+                        .stats
+                        .cache_pool_exhausted
+                        .fetch_add(1, Ordering::Relaxed);
+                    DefinedError::CachePoolExhausted(reason)
+                } else {
+                    DefinedError::CacheUnavailable(reason)
+                };
+
+                return JsonRpcResponse::from_error(None, err).into();
             }
         };
 
         for (index, request) in requests.into_iter().enumerate() {
+            let bypass_cache = no_cache || request.get("cache") == Some(&Value::Bool(false));
+            let cache_only = only_if_cached || request.get("cache") == Some(&json!("only"));
+
             let (id, method, params) = match extract_single_request_info(request) {
                 Ok(v) => v,
                 Err((request_id, err)) => {
-                    ordered_requests_result
-                        .push(Some(JsonRpcResponse::from_error(request_id, err)));
+                    ordered_requests_result[index] =
+                        Some(JsonRpcResponse::from_error(request_id, err));
                     continue;
                 }
             };
 
+            let method = method_aliases::rewrite(&chain_state.method_aliases, method);
+            let params = params_rewrite::rewrite(&chain_state.params_rewrites, &method, params);
+
+            if let Some(static_response) = chain_state
+                .static_responses
+                .iter()
+                .find(|static_response| static_response.matches(&method, &params))
+            {
+                ordered_requests_result[index] = Some(static_response.response(id));
+                continue;
+            }
+
+            if let Some(namespace) = chain_state.upstreams.unsupported_namespace(&method) {
+                ordered_requests_result[index] = Some(JsonRpcResponse::from_error(
+                    Some(id),
+                    DefinedError::UnsupportedMethod(Some(json!({
+                        "reason": format!("the upstreams don't support `{namespace}_*` methods"),
+                    }))),
+                ));
+                continue;
+            }
+
+            let mut params = match chain_state.pinned_block {
+                Some(pinned_block) => match snapshot::pin_params(&method, params, pinned_block) {
+                    Ok(params) => params,
+                    Err(err) => {
+                        ordered_requests_result[index] =
+                            Some(JsonRpcResponse::from_error(Some(id), err));
+                        continue;
+                    }
+                },
+                None => params,
+            };
+
+            if is_sampled {
+                methods.insert(method.clone());
+            }
+
+            if let Some(result) = chain_state.local_results.get(method.as_str()) {
+                chain_state.stats.record_cache_hit(&method);
+                cache_hits += 1;
+                ordered_requests_result[index] =
+                    Some(JsonRpcResponse::from_result(id, result.clone()));
+                continue;
+            }
+
+            if let Some(result) = chain_state
+                .head
+                .head_result(&method)
+                .filter(|_| !bypass_cache)
+            {
+                tracing::info!("head result hit for method {}", method);
+                chain_state.stats.record_cache_hit(&method);
+                cache_hits += 1;
+                ordered_requests_result[index] = Some(JsonRpcResponse::from_result(id, result));
+                continue;
+            }
+
+            macro_rules! fail_if_cache_only {
+                () => {
+                    if cache_only {
+                        let err = match in_maintenance {
+                            true => DefinedError::UnderMaintenance(None),
+                            false => DefinedError::NotCached(
+                                data.offline.then(|| json!({ "reason": "offline mode" })),
+                            ),
+                        };
+                        ordered_requests_result[index] =
+                            Some(JsonRpcResponse::from_error(Some(id), err));
+                        continue;
+                    }
+                };
+            }
+
             macro_rules! push_uncached_request_and_continue {
                 () => {{
+                    fail_if_cache_only!();
                     let rpc_request = RpcRequest::new_uncachable(index, id, method, params);
-                    request_id_index_map.insert(rpc_request.id.clone(), uncached_requests.len());
                     uncached_requests.push(rpc_request);
                     continue;
                 }};
 
                 ($key: expr) => {{
+                    fail_if_cache_only!();
                     let rpc_request = RpcRequest::new(index, id, method, params, $key);
-                    request_id_index_map.insert(rpc_request.id.clone(), uncached_requests.len());
                     uncached_requests.push(rpc_request);
                     continue;
                 }};
             }
 
-            let cache_entry = match chain_state.cache_entries.get(&method) {
+            if chain_state.emulate_filters && filters::is_filter_method(&method) {
+                let rpc_request = RpcRequest::new_uncachable(index, id, method, params);
+                let (chain_state, forwarded_headers) =
+                    (chain_state.clone(), forwarded_headers.clone());
+                local_requests.push(
+                    filters::serve(
+                        chain_state,
+                        rpc_request,
+                        cache_only,
+                        priority,
+                        forwarded_headers,
+                    )
+                    .boxed_local(),
+                );
+                continue;
+            }
+
+            if let Some(plan) = (method == log_ranges::GET_LOGS)
+                .then(|| log_ranges::plan(chain_state, &params))
+                .flatten()
+            {
+                let rpc_request = RpcRequest::new_uncachable(index, id, method, params);
+                let (chain_state, forwarded_headers) =
+                    (chain_state.clone(), forwarded_headers.clone());
+                local_requests.push(
+                    log_ranges::fetch(
+                        chain_state,
+                        rpc_request,
+                        plan,
+                        bypass_cache,
+                        cache_only,
+                        priority,
+                        forwarded_headers,
+                    )
+                    .boxed_local(),
+                );
+                continue;
+            }
+
+            if pool_status::is_aggregated(&method)
+                && chain_state.route(&method, &params).upstreams().len() > 1
+            {
+                fail_if_cache_only!();
+                broadcast_requests.push(RpcRequest::new_uncachable(index, id, method, params));
+                continue;
+            }
+
+            if method == transactions::SEND_RAW_TRANSACTION {
+                fail_if_cache_only!();
+
+                let window = Duration::from_secs(chain_state.transactions.dedupe_window_secs);
+                let tx_hash = transactions::transaction_hash(&params).filter(|_| !window.is_zero());
+                if let Some(tx_hash) = tx_hash {
+                    if !chain_state.recent_transactions.record(tx_hash, window) {
+                        tracing::info!(
+                            "transaction {tx_hash:#x} was sent recently, not sending it again"
+                        );
+                        ordered_requests_result[index] = Some(JsonRpcResponse::from_result(
+                            id,
+                            json!(format!("{tx_hash:#x}")),
+                        ));
+                        continue;
+                    }
+                    sent_transactions.push((index, tx_hash));
+                }
+
+                if chain_state.transactions.broadcast {
+                    broadcast_requests.push(RpcRequest::new_uncachable(index, id, method, params));
+                    continue;
+                }
+
+                push_uncached_request_and_continue!();
+            }
+
+            let cache_entry = match chain_state.cache_entries.get(method.as_str()) {
                 Some(cache_entry) => cache_entry,
                 None => {
                     tracing::warn!(method, "cache is not supported");
@@ -94,7 +572,33 @@ async fn rpc_call(
                 }
             };
 
-            let params_key = match cache_entry.handler.extract_cache_key(&params) {
+            if let Some(result) = cache_entry.handler.local_result(&params) {
+                let response = match result {
+                    Ok(result) => JsonRpcResponse::from_result(id, result),
+                    Err(err) => {
+                        tracing::warn!(method, "fail to compute local result: {err:#}");
+                        JsonRpcResponse::from_error(Some(id), DefinedError::InvalidParams)
+                    }
+                };
+                ordered_requests_result[index] = Some(response);
+                continue;
+            }
+
+            let resolved_params =
+                match (chain_state.resolve_latest_block, chain_state.head.latest()) {
+                    (true, Some(latest_block)) => {
+                        cache_entry.handler.resolve_block_tag(&params, latest_block)
+                    }
+                    _ => None,
+                };
+            // The result of e.g. `latest` changes with the head even though the resolved entry doesn't.
+            let is_block_tag_resolved = resolved_params.is_some();
+            // Assigned rather than shadowed, so that the macros above send the resolved params upstream.
+            if let Some(resolved_params) = resolved_params {
+                params = resolved_params;
+            }
+
+            let params_key = match chain_state.params_key(cache_entry, &params) {
                 Ok(Some(params_key)) => params_key,
                 Ok(None) => push_uncached_request_and_continue!(),
                 Err(err) => {
@@ -107,13 +611,117 @@ async fn rpc_call(
                 }
             };
 
-            match cache_backend.read(&method, &params_key) {
+            let cache_status = match bypass_cache {
+                true => Ok(CacheStatus::Missed {
+                    key: cache_backend.key(&method, &params_key),
+                }),
+                false => cache_backend.read(&method, &params_key),
+            };
+
+            let cache_status = match (cache_status, max_age) {
+                (Ok(CacheStatus::Cached { key, .. }), Some(max_age))
+                    if !is_fresh(cache_backend.as_mut(), &method, &params_key, max_age) =>
+                {
+                    Ok(CacheStatus::Missed { key })
+                }
+                (cache_status, _) => cache_status,
+            };
+
+            match cache_status {
                 Ok(CacheStatus::Cached { key, value }) => {
+                    let value = Arc::new(value);
                     tracing::info!("cache hit for method {} with key {}", method, key);
+                    chain_state.stats.record_cache_hit(&method);
+                    cache_hits += 1;
+                    if !is_block_tag_resolved && !cache_entry.handler.is_pending(&value) {
+                        let is_final =
+                            chain_state.is_final(cache_entry.handler.as_ref(), &params, &value);
+                        http_cache_control = cache_entry.http_cache_control(is_final);
+                    }
+                    if let Some(hot_keys) = &chain_state.hot_keys {
+                        hot_keys.record_hit(&key);
+                    }
+
+                    // Verifying hits reaches the upstreams, which offline mode never does.
+                    if !data.offline
+                        && data.verify_sample_rate > 0.0
+                        && rand::random::<f64>() < data.verify_sample_rate
+                    {
+                        sampled_hits.push(SampledHit {
+                            method: method.clone(),
+                            params: params.clone(),
+                            cache_key: key,
+                            cached_value: value.clone(),
+                        });
+                    }
+
                     ordered_requests_result[index] = Some(JsonRpcResponse::from_result(id, value));
                 }
                 Ok(CacheStatus::Missed { key }) => {
                     tracing::info!("cache missed for method {} with key {}", method, key);
+
+                    if cache_entry.error_cache_ttl.is_some() && !bypass_cache {
+                        let error_params_key = format!("{params_key}{ERROR_KEY_SUFFIX}");
+
+                        if let Ok(CacheStatus::Cached { value, .. }) =
+                            cache_backend.read(&method, &error_params_key)
+                        {
+                            tracing::info!(
+                                "cached error hit for method {} with key {}",
+                                method,
+                                key
+                            );
+                            chain_state.stats.record_cache_hit(&method);
+                            cache_hits += 1;
+                            ordered_requests_result[index] =
+                                Some(JsonRpcResponse::from_custom_error(Some(id), value));
+                            continue;
+                        }
+                    }
+
+                    fail_if_cache_only!();
+
+                    // Identical requests in the same batch share a single upstream request.
+                    if let Some(&primary_index) = missed_keys.get(&key) {
+                        duplicate_requests.push((index, id, primary_index));
+                        continue;
+                    }
+
+                    missed_keys.insert(key.clone(), index);
+
+                    match chain_state.key_locks.try_lock(&key) {
+                        Ok(guard) => key_lock_guards.push(guard),
+                        Err(pending) => {
+                            let waiting = WaitingRequest {
+                                index,
+                                id,
+                                method,
+                                params,
+                                params_key,
+                                key,
+                            };
+                            waiting_requests.push((waiting, pending));
+                            continue;
+                        }
+                    }
+
+                    // Another instance sharing the cache may be fetching the key already.
+                    match cache_backend.try_lock(&key, KEY_LOCK_TIMEOUT) {
+                        Ok(true) => remote_locked_keys.push(key.clone()),
+                        Ok(false) => {
+                            remote_waiting_requests.push(WaitingRequest {
+                                index,
+                                id,
+                                method,
+                                params,
+                                params_key,
+                                key,
+                            });
+                            continue;
+                        }
+                        Err(err) => tracing::error!("fail to lock key {key} because: {err:#}"),
+                    }
+
                     push_uncached_request_and_continue!(key);
                 }
                 Err(err) => {
@@ -124,64 +732,433 @@ async fn rpc_call(
         }
     }
 
+    send_new_events!();
+
+    // Misses of keys another request is fetching right now wait for it and are served from the cache it
+    // fills, so that an expiring popular key doesn't stampede the upstream.
+    let key_locks = &chain_state.key_locks;
+    let waited_requests = future::join_all(waiting_requests.into_iter().map(
+        |(waiting, pending)| async move {
+            let guard = actix_web::rt::time::timeout(KEY_LOCK_TIMEOUT, key_locks.lock(pending))
+                .await
+                .ok();
+            (waiting, guard)
+        },
+    ))
+    .await;
+
+    if !waited_requests.is_empty() {
+        let mut cache_backend = chain_state.cache_factory.get_instance().ok();
+
+        for (waiting, guard) in waited_requests {
+            let WaitingRequest {
+                index,
+                id,
+                method,
+                params,
+                params_key,
+                key,
+            } = waiting;
+
+            let cached = cache_backend
+                .as_mut()
+                .and_then(|cache_backend| cache_backend.read(&method, &params_key).ok());
+
+            if let Some(CacheStatus::Cached { value, .. }) = cached {
+                tracing::info!(
+                    "cache hit after waiting for method {} with key {}",
+                    method,
+                    key
+                );
+                chain_state.stats.record_cache_hit(&method);
+                cache_hits += 1;
+                ordered_requests_result[index] = Some(JsonRpcResponse::from_result(id, value));
+                continue;
+            }
+
+            // The concurrent fetch failed or took too long, fetch the key ourselves.
+            key_lock_guards.extend(guard);
+            uncached_requests.push(RpcRequest::new(index, id, method, params, key));
+        }
+
+        send_new_events!();
+    }
+
+    // Keys locked by other instances are polled until their result is cached or the lock is released, e.g.
+    // because the fetch failed, in which case the lock is taken over.
+    let deadline = Instant::now() + KEY_LOCK_TIMEOUT;
+    while !remote_waiting_requests.is_empty() && Instant::now() < deadline {
+        actix_web::rt::time::sleep(REMOTE_KEY_LOCK_POLL_INTERVAL).await;
+
+        let Ok(mut cache_backend) = chain_state.cache_factory.get_instance() else {
+            break;
+        };
+
+        for waiting in std::mem::take(&mut remote_waiting_requests) {
+            if let Ok(CacheStatus::Cached { value, .. }) =
+                cache_backend.read(&waiting.method, &waiting.params_key)
+            {
+                tracing::info!(
+                    "cache hit after waiting for another instance for method {} with key {}",
+                    waiting.method,
+                    waiting.key
+                );
+                chain_state.stats.record_cache_hit(&waiting.method);
+                cache_hits += 1;
+                ordered_requests_result[waiting.index] =
+                    Some(JsonRpcResponse::from_result(waiting.id, value));
+                continue;
+            }
+
+            match cache_backend.try_lock(&waiting.key, KEY_LOCK_TIMEOUT) {
+                Ok(false) => remote_waiting_requests.push(waiting),
+                locked => {
+                    if locked.is_ok() {
+                        remote_locked_keys.push(waiting.key.clone());
+                    }
+                    uncached_requests.push(waiting.into_uncached_request());
+                }
+            }
+        }
+
+        send_new_events!();
+    }
+
+    // The other instances took too long, fetch the keys ourselves.
+    uncached_requests.extend(
+        remote_waiting_requests
+            .into_iter()
+            .map(WaitingRequest::into_uncached_request),
+    );
+
+    if let Some(summary) = req.extensions_mut().get_mut::<RequestSummary>() {
+        *summary = RequestSummary {
+            methods,
+            batch_size,
+            cache_hits,
+        };
+    }
+
+    if !sampled_hits.is_empty() {
+        actix_web::rt::spawn(shadow_verify::verify_cache_hits(
+            chain_state.clone(),
+            sampled_hits,
+        ));
+    }
+
+    // Duplicates are answered on every return, their primary may have been served from the cache after all, e.g.
+    // once the lock it waited on was released.
     macro_rules! return_response {
-        () => {
+        () => {{
+            for (index, id, primary_index) in duplicate_requests {
+                ordered_requests_result[index] = ordered_requests_result[primary_index]
+                    .clone()
+                    .map(|mut response| {
+                        response.id = Some(id);
+                        response
+                    });
+            }
+
+            send_new_events!();
             return Ok(match is_single_request {
-                true => ordered_requests_result[0].clone().unwrap().into(),
+                true => single_response(
+                    &req,
+                    ordered_requests_result[0].take().unwrap(),
+                    http_cache_control,
+                ),
+                false if ordered_requests_result.len() > json_rpc::STREAMED_BATCH_LEN => {
+                    HttpResponse::Ok()
+                        .content_type(ContentType::json())
+                        .streaming(json_rpc::stream_batch_response(ordered_requests_result))
+                }
                 false => HttpResponse::Ok().json(ordered_requests_result),
-            })
-        };
+            });
+        }};
     }
 
-    if uncached_requests.is_empty() {
+    if uncached_requests.is_empty() && broadcast_requests.is_empty() && local_requests.is_empty() {
         return_response!();
     }
 
-    let rpc_result = utils::do_rpc_request(
-        &data.http_client,
-        chain_state.rpc_url.clone(),
-        &uncached_requests,
-    );
+    if let (Some(mirror), false) = (chain_state.upstreams.mirror(), uncached_requests.is_empty()) {
+        mirror.send(&uncached_requests);
+    }
 
-    let rpc_result = match rpc_result.await {
-        Ok(v) => v,
-        Err(err) => {
-            tracing::error!("fail to make rpc request because: {}", err);
+    // One upstream batch per upstream pool the requests are routed to.
+    let mut upstream_batches: Vec<(&UpstreamPool, Vec<RpcRequest>)> = vec![];
+
+    for rpc_request in uncached_requests {
+        let upstreams = chain_state.route(&rpc_request.method, &rpc_request.params);
+
+        match upstream_batches
+            .iter_mut()
+            .find(|(pool, _)| std::ptr::eq(*pool, upstreams))
+        {
+            Some((_, batch)) => batch.push(rpc_request),
+            None => upstream_batches.push((upstreams, vec![rpc_request])),
+        }
+    }
+
+    // Responses of each upstream batch are sent to the event stream as soon as the batch is answered.
+    let events_sender = events.as_deref();
+    let send_events = |responses: &Vec<(usize, JsonRpcResponse)>| {
+        if let Some(events) = events_sender {
+            for (_, response) in responses {
+                events.send(response);
+            }
+        }
+    };
+
+    let batch_responses =
+        future::join_all(upstream_batches.into_iter().map(|(upstreams, batch)| {
+            forward_uncached_requests(chain_state, upstreams, batch, priority, &forwarded_headers)
+                .inspect(send_events)
+        }));
+    let broadcast_responses =
+        future::join_all(broadcast_requests.into_iter().map(|rpc_request| {
+            let (chain_state, forwarded_headers) = (chain_state.clone(), forwarded_headers.clone());
+            match pool_status::is_aggregated(&rpc_request.method) {
+                true => pool_status::aggregate(chain_state, rpc_request, forwarded_headers)
+                    .left_future(),
+                false => transactions::broadcast(chain_state, rpc_request, forwarded_headers)
+                    .right_future(),
+            }
+        }))
+        .inspect(send_events);
+    let local_responses = future::join_all(local_requests).inspect(send_events);
+    let (mut batch_responses, broadcast_responses, local_responses) =
+        future::join3(batch_responses, broadcast_responses, local_responses).await;
+
+    if !remote_locked_keys.is_empty() {
+        match chain_state.cache_factory.get_instance() {
+            Ok(mut cache_backend) => {
+                for key in &remote_locked_keys {
+                    if let Err(err) = cache_backend.unlock(key) {
+                        tracing::error!("fail to unlock key {key} because: {err:#}");
+                    }
+                }
+            }
+            // The locks expire by themselves.
+            Err(err) => tracing::error!("fail to get cache backend because: {err:#}"),
+        }
+    }
+    batch_responses.push(broadcast_responses);
+    batch_responses.push(local_responses);
+
+    if let (Some(api_keys), Some(api_key)) = (&data.api_keys, &api_key) {
+        let upstream_bytes = batch_responses
+            .iter()
+            .flatten()
+            .map(|(_, response)| quota::serialized_len(response))
+            .sum();
+
+        if let Err(err) = api_keys.record_upstream_bytes(api_key, upstream_bytes) {
+            tracing::error!("fail to record usage of api key: {err:#}");
+        }
+    }
+
+    for (index, response) in batch_responses.into_iter().flatten() {
+        if let Some(events) = events.as_deref_mut() {
+            events.mark_sent(index);
+        }
+        ordered_requests_result[index] = Some(response);
+    }
+
+    for (index, tx_hash) in sent_transactions {
+        if let Some(JsonRpcResponse {
+            result: ResultOrError::Error { .. },
+            ..
+        }) = &ordered_requests_result[index]
+        {
+            chain_state.recent_transactions.forget(&tx_hash);
+        }
+    }
+
+    return_response!()
+}
+
+/// Sends a batch of uncached requests to one of `upstreams`, caches the cacheable results and returns the
+/// responses paired with the index of their request in the client batch.
+async fn forward_uncached_requests(
+    chain_state: &ChainState,
+    upstreams: &UpstreamPool,
+    mut uncached_requests: Vec<RpcRequest>,
+    priority: Priority,
+    forwarded_headers: &HeaderMap,
+) -> Vec<(usize, JsonRpcResponse)> {
+    if !chain_state.sequential_upstream_ids {
+        return forward_upstream_batch(
+            chain_state,
+            upstreams,
+            uncached_requests,
+            priority,
+            forwarded_headers,
+        )
+        .await;
+    }
+
+    // Client ids by the index of their request in the client batch, restored once the responses are matched.
+    let mut client_ids = HashMap::with_capacity(uncached_requests.len());
+    for (number, rpc_request) in uncached_requests.iter_mut().enumerate() {
+        // It's safe to unwrap here because a number is always a valid request id.
+        let upstream_id = RequestId::try_from(json!(number)).unwrap();
+        client_ids.insert(
+            rpc_request.index,
+            std::mem::replace(&mut rpc_request.id, upstream_id),
+        );
+    }
 
+    let mut responses = forward_upstream_batch(
+        chain_state,
+        upstreams,
+        uncached_requests,
+        priority,
+        forwarded_headers,
+    )
+    .await;
+
+    for (index, response) in &mut responses {
+        if let Some(id) = client_ids.remove(index) {
+            response.id = Some(id);
+        }
+    }
+
+    responses
+}
+
+async fn forward_upstream_batch(
+    chain_state: &ChainState,
+    upstreams: &UpstreamPool,
+    mut uncached_requests: Vec<RpcRequest>,
+    priority: Priority,
+    forwarded_headers: &HeaderMap,
+) -> Vec<(usize, JsonRpcResponse)> {
+    let mut responses = Vec::with_capacity(uncached_requests.len());
+
+    macro_rules! fail_all_and_return {
+        ($error: expr) => {{
             for rpc_request in uncached_requests {
-                ordered_requests_result[rpc_request.index] = Some(JsonRpcResponse::from_error(
-                    Some(rpc_request.id),
-                    DefinedError::InternalError(Some(json!({
-                        "error": "fail to make rpc request to backend",
-                        "reason": err.to_string(),
-                    }))),
+                responses.push((
+                    rpc_request.index,
+                    JsonRpcResponse::from_error(Some(rpc_request.id), $error),
                 ));
             }
 
-            return_response!();
+            return responses;
+        }};
+    }
+
+    let permit = match chain_state.upstream_limits.acquire(priority).await {
+        Ok(permit) => permit,
+        Err(err) => {
+            tracing::warn!("fail to forward rpc requests: {err:#}");
+
+            fail_all_and_return!(DefinedError::UpstreamUnavailable(Some(json!({
+                "reason": format!("{err:#}"),
+            }))));
+        }
+    };
+
+    let failover_order = upstreams.failover_order().collect::<Vec<_>>();
+
+    // With a quorum, cacheable requests also go to the next upstreams of the pool to cross-check their results.
+    let witness_requests = uncached_requests
+        .iter()
+        .filter(|rpc_request| rpc_request.cache_key.is_some())
+        .collect::<Vec<_>>();
+    let witness_upstreams = match &chain_state.quorum {
+        Some(quorum) if !witness_requests.is_empty() => failover_order
+            .iter()
+            .skip(1)
+            .take(quorum.upstreams.saturating_sub(1))
+            .collect(),
+        _ => vec![],
+    };
+    let witness_responses = future::join_all(
+        witness_upstreams
+            .iter()
+            .map(|upstream| upstream.request_with_headers(&witness_requests, forwarded_headers)),
+    );
+
+    let failover = request_with_failover(
+        chain_state,
+        failover_order.iter().copied(),
+        &uncached_requests,
+        forwarded_headers,
+    );
+    let ((mut rpc_result, mut last_error), witness_responses) =
+        future::join(failover, witness_responses).await;
+
+    // Fallback upstreams of unknown quality only step in when every upstream of the pool failed, and their
+    // results are never cached.
+    let mut is_fallback = false;
+    let fallback = match rpc_result {
+        Some(_) => None,
+        None => chain_state.upstreams.fallback_pool(),
+    };
+    if let Some(fallback) = fallback {
+        tracing::warn!("every upstream failed, trying the fallback upstreams");
+
+        (rpc_result, last_error) = request_with_failover(
+            chain_state,
+            fallback.failover_order(),
+            &uncached_requests,
+            forwarded_headers,
+        )
+        .await;
+        is_fallback = rpc_result.is_some();
+    }
+    drop(permit);
+
+    if is_fallback {
+        for rpc_request in &mut uncached_requests {
+            rpc_request.cache_key = None;
+        }
+    }
+
+    let witnesses = chain_state
+        .quorum
+        .as_ref()
+        .filter(|_| !is_fallback)
+        .map(|quorum| Witnesses::new(witness_responses, quorum.threshold));
+
+    let (rpc_result, source) = match (rpc_result, last_error) {
+        (Some(rpc_result), _) => rpc_result,
+        (None, err) => {
+            let reason = err.map(|err| err.to_string()).unwrap_or_default();
+
+            fail_all_and_return!(DefinedError::UpstreamUnavailable(Some(json!({
+                "reason": reason,
+            }))));
         }
     };
 
     let result_values = match rpc_result {
         Value::Array(v) => v,
+        // Upstreams answer batches they reject as a whole, e.g. when rate limiting, with a single error.
+        Value::Object(ref response) if response.contains_key("error") => {
+            for rpc_request in uncached_requests {
+                responses.push((
+                    rpc_request.index,
+                    JsonRpcResponse::from_custom_error(
+                        Some(rpc_request.id),
+                        response["error"].clone(),
+                    ),
+                ));
+            }
+
+            return responses;
+        }
         _ => {
             tracing::error!(
                 "array is expected but we got invalid rpc response: {},",
                 rpc_result.to_string()
             );
 
-            for rpc_request in uncached_requests {
-                ordered_requests_result[rpc_request.index] = Some(JsonRpcResponse::from_error(
-                    Some(rpc_request.id),
-                    DefinedError::InternalError(Some(json!({
-                        "error": "invalid rpc response from backend",
-                        "reason": "array is expected",
-                        "response": rpc_result.to_string(),
-                    }))),
-                ));
-            }
-
-            return_response!();
+            fail_all_and_return!(DefinedError::InvalidUpstreamResponse(Some(json!({
+                "reason": "array is expected",
+                "response": rpc_result.to_string(),
+            }))));
         }
     };
 
@@ -198,84 +1175,315 @@ async fn rpc_call(
         Err(err) => {
             tracing::error!("fail to get cache backend because: {}", err);
 
-            for rpc_request in uncached_requests {
-                ordered_requests_result[rpc_request.index] = Some(JsonRpcResponse::from_error(
-                    Some(rpc_request.id),
-                    DefinedError::InternalError(Some(json!({
-                        "error": "fail to get cache backend",
-                        "reason": err.to_string(),
-                    }))),
-                ));
-            }
-
-            return_response!();
+            fail_all_and_return!(DefinedError::CacheUnavailable(Some(json!({
+                "reason": err.to_string(),
+            }))));
         }
     };
 
-    for (index, mut response) in result_values.into_iter().enumerate() {
-        let rpc_request = match RequestId::try_from(response["id"].clone()) {
-            Ok(id) if request_id_index_map.get(&id).is_some() => {
-                &uncached_requests[*request_id_index_map.get(&id).unwrap()]
-            }
-            _ => {
-                if index >= uncached_requests.len() {
-                    tracing::warn!("rpc response has invalid id and fail to map to original request. response is ignored, response: {response}");
-                    continue;
-                }
+    let mut matcher = ResponseMatcher::new(
+        uncached_requests.iter().map(|rpc_request| &rpc_request.id),
+        chain_state.lenient_id_matching,
+    );
+    let mut unmatched_responses = vec![];
+    let canary = chain_state.upstreams.canary();
+    let mut canary_samples = vec![];
+    // Only results that can be cached are compared, others may legitimately differ between nodes.
+    let mut sample_for_canary = |rpc_request: &RpcRequest, response: &Value| {
+        if let (Some(canary), Some(_)) = (canary, &rpc_request.cache_key) {
+            canary_samples.extend(canary.sample(
+                &rpc_request.method,
+                &rpc_request.params,
+                response,
+            ));
+        }
+    };
 
-                tracing::warn!(
-                    "rpc response has invalid id. find a potential match from original request"
+    for (position, response) in result_values.into_iter().enumerate() {
+        match matcher.match_id(&response["id"]) {
+            Some(index) => {
+                let rpc_request = &uncached_requests[index];
+                sample_for_canary(rpc_request, &response);
+                let response = process_upstream_response(
+                    chain_state,
+                    cache_backend.as_mut(),
+                    rpc_request,
+                    source,
+                    witnesses.as_ref(),
+                    response,
                 );
-                &uncached_requests[index]
+                responses.push((rpc_request.index, response));
             }
+            None => unmatched_responses.push((position, response)),
+        }
+    }
+
+    if !unmatched_responses.is_empty() {
+        let count = unmatched_responses.len() as u64;
+        let total = chain_state
+            .id_mismatches
+            .fetch_add(count, Ordering::Relaxed)
+            + count;
+        tracing::warn!("{count} rpc responses have ids matching no request, {total} in total");
+    }
+
+    for (position, response) in unmatched_responses {
+        let index = match matcher.is_lenient() {
+            true => matcher.match_position(position),
+            false => None,
         };
 
-        match response["error"].take() {
-            Value::Null => {}
-            error => {
-                let response =
-                    JsonRpcResponse::from_custom_error(Some(rpc_request.id.clone()), error);
-                ordered_requests_result[rpc_request.index] = Some(response);
-                continue;
+        match index {
+            Some(index) => {
+                let rpc_request = &uncached_requests[index];
+                sample_for_canary(rpc_request, &response);
+                let response = process_upstream_response(
+                    chain_state,
+                    cache_backend.as_mut(),
+                    rpc_request,
+                    source,
+                    witnesses.as_ref(),
+                    response,
+                );
+                responses.push((rpc_request.index, response));
+            }
+            None => {
+                tracing::warn!("rpc response fail to map to original request. response is ignored, response: {response}");
             }
         }
+    }
 
-        let result = response["result"].take();
-        let response = JsonRpcResponse::from_result(rpc_request.id.clone(), result.clone());
-        ordered_requests_result[rpc_request.index] = Some(response);
+    if let (Some(canary), false) = (canary, canary_samples.is_empty()) {
+        actix_web::rt::spawn(canary.clone().compare(canary_samples));
+    }
 
-        let cache_key = match rpc_request.cache_key.clone() {
-            Some(cache_key) => cache_key.clone(),
-            None => continue,
-        };
+    for index in matcher.unanswered() {
+        let rpc_request = &uncached_requests[index];
+
+        responses.push((
+            rpc_request.index,
+            JsonRpcResponse::from_error(
+                Some(rpc_request.id.clone()),
+                DefinedError::InvalidUpstreamResponse(Some(json!({
+                    "reason": "missing response",
+                }))),
+            ),
+        ));
+    }
 
-        // It's safe to unwrap here because if the cache system doesn't support this method, we have already
-        // made the early return.
-        let cache_entry = chain_state.cache_entries.get(&rpc_request.method).unwrap();
+    responses
+}
 
-        let (can_cache, extracted_value) = match cache_entry.handler.extract_cache_value(&result) {
-            Ok(v) => v,
-            Err(err) => {
-                tracing::error!("fail to extract cache value because: {}", err);
+/// Sends `requests` to `upstreams` in turn until one of them answers. Transport errors fail over to the next
+/// upstream, and so do rate-limited responses if configured. The last rate-limited response is returned if no
+/// upstream answers properly, the last transport error if none answers at all.
+async fn request_with_failover<'a>(
+    chain_state: &ChainState,
+    upstreams: impl IntoIterator<Item = &'a Upstream>,
+    requests: &[RpcRequest],
+    forwarded_headers: &HeaderMap,
+) -> (Option<(Value, &'a Upstream)>, Option<anyhow::Error>) {
+    let mut rpc_result = None;
+    let mut last_error = None;
+
+    for upstream in upstreams {
+        let started_at = Instant::now();
+
+        let request = upstream.request_with_headers(requests, forwarded_headers);
+
+        match failure_injection::inject(chain_state.failure_injection.as_ref(), request).await {
+            Ok(response) => {
+                let latency = started_at.elapsed();
+                upstream.record_latency(latency);
+
+                // A batch takes as long as its slowest request, which says little about the others.
+                if let [rpc_request] = requests {
+                    chain_state
+                        .stats
+                        .record_upstream_latency(&rpc_request.method, latency);
+                }
 
-                ordered_requests_result[rpc_request.index] = Some(JsonRpcResponse::from_error(
-                    Some(rpc_request.id.clone()),
-                    DefinedError::InternalError(Some(json!({
-                        "error": "fail to extract cache value",
-                        "reason": err.to_string(),
-                    }))),
-                ));
+                let retry = chain_state.retry_rate_limited && is_rate_limited(&response);
+                rpc_result = Some((response, upstream));
 
-                continue;
+                if !retry {
+                    break;
+                }
+
+                tracing::warn!(
+                    "upstream {} is rate limited, trying the next one",
+                    upstream.url
+                );
+            }
+            Err(err) => {
+                let failures = upstream.record_failure(started_at.elapsed());
+                tracing::warn!(
+                    "fail to make rpc request to {} because: {err:#}",
+                    upstream.url
+                );
+
+                // Reported once per outage rather than for every failed request.
+                if failures == chain_state.upstream_failure_alert_threshold {
+                    tracing::error!(
+                        "upstream {} failed {failures} requests in a row, last error: {err:#}",
+                        upstream.url
+                    );
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+
+    (rpc_result, last_error)
+}
+
+/// Turns an upstream response into the response for `rpc_request`, caching the result if possible.
+fn process_upstream_response(
+    chain_state: &ChainState,
+    cache_backend: &mut dyn CacheBackend,
+    rpc_request: &RpcRequest,
+    source: &Upstream,
+    witnesses: Option<&Witnesses>,
+    mut response: Value,
+) -> JsonRpcResponse {
+    match response["error"].take() {
+        Value::Null => {}
+        error => {
+            chain_state.write_error_cache(cache_backend, rpc_request, &error);
+            return JsonRpcResponse::from_custom_error(Some(rpc_request.id.clone()), error);
+        }
+    }
+
+    let result = response["result"].take();
+
+    let (result, cache_key) = match (witnesses, &rpc_request.cache_key) {
+        (Some(witnesses), Some(cache_key)) => match witnesses.settle(&rpc_request.id, result) {
+            Verdict::Agreed(result) => (result, Some(cache_key)),
+            Verdict::Disputed(result) => {
+                tracing::warn!(
+                    method = rpc_request.method,
+                    "upstreams disagree on the result, which is not cached"
+                );
+                (result, None)
             }
+        },
+        (_, cache_key) => (result, cache_key.as_ref()),
+    };
+
+    if let Some(cache_key) = cache_key {
+        if let Err(err) = chain_state.write_cache(
+            cache_backend,
+            &rpc_request.method,
+            &rpc_request.params,
+            cache_key,
+            &result,
+            source,
+        ) {
+            tracing::error!("fail to extract cache value because: {}", err);
+
+            return JsonRpcResponse::from_error(
+                Some(rpc_request.id.clone()),
+                DefinedError::InternalError(Some(json!({
+                    "error": "fail to extract cache value",
+                    "reason": err.to_string(),
+                }))),
+            );
+        }
+    }
+
+    JsonRpcResponse::from_result(rpc_request.id.clone(), result)
+}
+
+/// Whether the entry of `params_key` was stored at most `max_age` seconds ago. Entries stored without metadata, see
+/// `ChainConfig::store_metadata`, e.g. before it was enabled, are never fresh enough.
+fn is_fresh(
+    cache_backend: &mut dyn CacheBackend,
+    method: &str,
+    params_key: &str,
+    max_age: u32,
+) -> bool {
+    let metadata_key = format!("{params_key}{METADATA_KEY_SUFFIX}");
+    let Ok(CacheStatus::Cached { value, .. }) = cache_backend.read(method, &metadata_key) else {
+        return false;
+    };
+    let Ok(metadata) = serde_json::from_value::<EntryMetadata>(value) else {
+        return false;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now.saturating_sub(metadata.stored_at) <= u64::from(max_age)
+}
+
+/// Headers of `req` listed in `names`, to be sent along to the upstream. The address of the client is
+/// appended to `x-forwarded-for`.
+fn forwarded_headers(req: &HttpRequest, names: &[HeaderName]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for name in names {
+        let value = req
+            .headers()
+            .get(name.as_str())
+            .map(|value| value.as_bytes());
+        let value = match (name.as_str() == "x-forwarded-for", req.peer_addr()) {
+            (true, Some(peer_addr)) => {
+                let peer_ip = peer_addr.ip().to_string();
+                match value {
+                    Some(value) => [value, b", ", peer_ip.as_bytes()].concat(),
+                    None => peer_ip.into_bytes(),
+                }
+            }
+            _ => match value {
+                Some(value) => value.to_vec(),
+                None => continue,
+            },
         };
 
-        if can_cache {
-            let _ = cache_backend.write(&cache_key, &extracted_value.to_string());
+        if let Ok(value) = HeaderValue::from_bytes(&value) {
+            headers.insert(name.clone(), value);
         }
     }
 
-    return_response!()
+    headers
+}
+
+/// Responds with an `ETag` derived from the response body, or with 304 if the client already has it, so that
+/// clients polling identical requests don't download the same result again. Caches in front may keep the
+/// response as `cache_control` allows if set.
+fn single_response(
+    req: &HttpRequest,
+    response: JsonRpcResponse,
+    cache_control: Option<CacheControl>,
+) -> HttpResponse {
+    let body = match serde_json::to_vec(&response) {
+        Ok(body) => body,
+        Err(_) => return response.into(),
+    };
+
+    let etag = EntityTag::new_strong(hex::encode(sha1::Sha1::digest(&body)));
+
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+
+    let mut response = match not_modified {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+    response.insert_header(ETag(etag));
+
+    if let Some(cache_control) = cache_control {
+        response.insert_header(cache_control);
+    }
+
+    match not_modified {
+        true => response.finish(),
+        false => response.content_type(ContentType::json()).body(body),
+    }
 }
 
 fn extract_single_request_info(
@@ -296,54 +1504,353 @@ fn extract_single_request_info(
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    alerting::init_logger();
 
-    let args = Args::parse();
+    let args = Arc::new(Args::load());
+
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        if let Err(err) = bench::run(bench_args).await {
+            tracing::error!("benchmark failed because: {err:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Analyze(analyze_args)) = &args.command {
+        if let Err(err) = analyze::run(analyze_args) {
+            tracing::error!("analysis failed because: {err:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let _sentry_guard = alerting::init_sentry(&args);
+
+    let config = match &args.config {
+        Some(path) => Config::load(path).expect("fail to load config file"),
+        None => Config::default(),
+    };
+
+    let api_keys = args.api_keys_file.as_ref().map(|path| {
+        let keys = ApiKeys::load_keys(path).expect("fail to load keys file");
+        // Usage is counted in the global redis, if any, so that instances sharing it share the quotas.
+        let usage_redis = RedisConfig {
+            key_prefix: Some("usage".to_string()),
+            ..Default::default()
+        };
+        let usage = new_cache_backend_factory(&args, 0, &usage_redis)
+            .expect("fail to create usage backend");
+
+        ApiKeys::new(keys, usage)
+    });
 
     let mut app_state = AppState {
         chains: Default::default(),
-        http_client: reqwest::Client::new(),
+        aliases: Default::default(),
+        verify_sample_rate: args.verify_sample_rate,
+        api_keys,
+        jwt_secret: args
+            .jwt_secret_file
+            .as_deref()
+            .map(|path| JwtSecret::load(path).expect("fail to load jwt secret")),
+        offline: args.offline,
+        reorder_batches: args.reorder_batches,
+        default_chain: args.default_chain.clone(),
+        compute_units: config.compute_units.clone(),
     };
 
-    let handler_factories = rpc_cache_handler::factories();
+    let mut handler_registry = rpc_cache_handler::HandlerRegistry::load();
+    for handler_config in &config.handlers {
+        handler_registry
+            .register_factory(rpc_cache_handler::declarative::new_factory(handler_config));
+    }
+    for handler_config in &config.wasm_handlers {
+        let factory = rpc_cache_handler::wasm::load_factory(handler_config)
+            .expect("fail to load wasm handler");
+        handler_registry.register_factory(factory);
+    }
+
+    // Repeated `--endpoint` names and `upstreams` from the config file all add to the same chain.
+    let mut chain_upstreams: BTreeMap<String, Vec<UpstreamConfig>> = BTreeMap::new();
 
     for (name, rpc_url) in args.endpoints.iter() {
-        tracing::info!("Linked `{name}` to endpoint {rpc_url}");
+        if name == chain_registry::AUTO_ENDPOINT_NAME {
+            continue;
+        }
 
-        let chain_id = utils::get_chain_id(&reqwest::Client::new(), rpc_url.as_str())
-            .await
-            .expect("fail to get chain id");
+        chain_upstreams
+            .entry(name.clone())
+            .or_default()
+            .push(rpc_url.clone().into());
+    }
 
-        let cache_factory = new_cache_backend_factory(&args, chain_id)
-            .expect("fail to create cache backend factory");
+    for (name, chain_config) in config.chains.iter() {
+        if !chain_config.upstreams.is_empty() {
+            chain_upstreams
+                .entry(name.clone())
+                .or_default()
+                .extend(chain_config.upstreams.iter().cloned());
+        }
+    }
 
-        let mut chain_state = ChainState {
-            rpc_url: rpc_url.clone(),
-            cache_entries: Default::default(),
-            cache_factory,
+    // `auto` endpoints are named after the chain they serve. Their name must not be taken by a chain
+    // configured by name, which may well serve another chain.
+    let named_chains = chain_upstreams.keys().cloned().collect::<BTreeSet<_>>();
+    // Their chain can't be detected without reaching them, so offline mode leaves them out.
+    let auto_endpoints = args
+        .endpoints
+        .iter()
+        .filter(|(name, _)| name == chain_registry::AUTO_ENDPOINT_NAME)
+        .filter(|(_, rpc_url)| match args.offline {
+            true => {
+                tracing::warn!("auto endpoint {rpc_url} is skipped in offline mode");
+                false
+            }
+            false => true,
+        })
+        .map(|(_, rpc_url)| rpc_url)
+        .collect::<Vec<_>>();
+
+    // Endpoints whose chain can't be detected are left out instead of failing the startup.
+    let chain_ids = future::join_all(auto_endpoints.iter().map(|rpc_url| async move {
+        let upstream = Upstream::new(Url::clone(rpc_url).into(), &UpstreamClient::default())?;
+        utils::get_chain_id(&upstream).await
+    }))
+    .await;
+
+    for (rpc_url, chain_id) in auto_endpoints.into_iter().zip(chain_ids) {
+        let chain_id = match chain_id {
+            Ok(chain_id) => chain_id,
+            Err(err) => {
+                tracing::error!("fail to detect chain id of auto endpoint {rpc_url}: {err:#}");
+                continue;
+            }
         };
+        let name = chain_registry::chain_name(chain_id);
 
-        for factory in &handler_factories {
-            let handler = factory();
-            chain_state
-                .cache_entries
-                .insert(handler.method_name().to_string(), CacheEntry { handler });
+        if named_chains.contains(&name) {
+            panic!(
+                "auto endpoint {rpc_url} serves chain `{name}`, which is also configured by name"
+            );
         }
 
-        app_state.chains.insert(name.to_string(), chain_state);
+        tracing::info!("Auto endpoint {rpc_url} serves chain `{name}` ({chain_id})");
+        chain_upstreams
+            .entry(name)
+            .or_default()
+            .push(rpc_url.clone().into());
     }
 
-    let app_state = web::Data::new(app_state);
+    let handler_registry = Arc::new(handler_registry);
+    let global_upstream_limit = args
+        .max_concurrent_upstream_requests
+        .map(|limit| Arc::new(Semaphore::new(limit)));
+    let mut pending_chains = vec![];
+
+    for (name, upstreams) in chain_upstreams {
+        let chain_config = config.chain(&name);
+        if args.offline && chain_config.chain_id.is_none() {
+            panic!("chain `{name}` needs a `chain_id` in offline mode");
+        }
+
+        let http_client = chain_config
+            .http_client
+            .build_client()
+            .expect("fail to create http client");
+
+        let upstreams = UpstreamPool::new(upstreams, chain_config.load_balance, &http_client)
+            .and_then(|default_pool| {
+                UpstreamRouter::new(
+                    default_pool,
+                    chain_config.routes.clone(),
+                    chain_config.historical_route.clone(),
+                    chain_config.fallback_upstreams.clone(),
+                    chain_config.canary.clone(),
+                    chain_config.mirror.clone(),
+                    &http_client,
+                )
+            })
+            .expect("fail to create upstreams");
+
+        for upstream in upstreams.pools().flat_map(|pool| pool.upstreams()) {
+            tracing::info!("Linked `{name}` to endpoint {}", upstream.url);
+        }
+        if let Some(canary) = upstreams.canary() {
+            tracing::info!("Mirroring `{name}` to canary {}", canary.url());
+        }
+        if let Some(mirror) = upstreams.mirror() {
+            tracing::info!("Mirroring uncached `{name}` traffic to {}", mirror.url());
+        }
 
-    tracing::info!("Server listening on {}:{}", args.bind, args.port);
+        let slot = ChainSlot::default();
+        app_state.chains.insert(name.clone(), slot.clone());
+
+        for alias in &chain_config.aliases {
+            if let Some(other) = app_state.aliases.insert(alias.to_uppercase(), name.clone()) {
+                panic!("alias `{alias}` is given to both `{other}` and `{name}`");
+            }
+        }
+
+        let upstream_limits = UpstreamLimits::new(
+            chain_config.max_concurrent_upstream_requests,
+            global_upstream_limit.clone(),
+            Duration::from_millis(args.upstream_queue_timeout_ms),
+        );
+
+        pending_chains.push(Box::new(PendingChain {
+            name,
+            config: chain_config,
+            upstreams,
+            upstream_limits,
+            slot,
+        }));
+    }
+
+    if let Some(alias) = app_state
+        .aliases
+        .keys()
+        .find(|alias| app_state.chains.contains_key(*alias))
+    {
+        panic!("alias `{alias}` is also the name of a chain");
+    }
+
+    if let Some(default_chain) = &app_state.default_chain {
+        if app_state.chain_slot(default_chain).is_none() {
+            panic!("default chain `{default_chain}` is not configured");
+        }
+    }
+
+    // Chains are initialized concurrently so that a slow upstream doesn't hold up the others. Chains whose
+    // upstreams can't be reached keep retrying in the background and are served once they come up.
+    let init_results = future::join_all(
+        pending_chains
+            .into_iter()
+            .map(|pending| try_init_chain(pending, &args, &handler_registry)),
+    )
+    .await;
+
+    for pending in init_results.into_iter().filter_map(Result::err) {
+        actix_web::rt::spawn(retry_init_chain(
+            pending,
+            args.clone(),
+            handler_registry.clone(),
+        ));
+    }
+
+    let app_state = web::Data::new(app_state);
 
     {
         let app_state = app_state.clone();
 
-        HttpServer::new(move || App::new().service(rpc_call).app_data(app_state.clone()))
-            .bind((args.bind, args.port))?
-            .run()
-            .await?;
+        let cors_origins = args.cors_origins.clone();
+        let sentry_enabled = args.sentry_dsn.is_some();
+        let access_log_sample_rate = args.access_log_sample_rate;
+        let trusted_proxies = args.trusted_proxies.clone();
+        let serve_admin = args.admin_bind.is_empty();
+        let ip_filters = IpFilters::from_args(&args);
+        // The workers take `app_state` along.
+        let admin_app_state = app_state.clone();
+
+        let mut server = HttpServer::new(move || {
+            let ip_filters = ip_filters.clone();
+            let trusted_proxies = trusted_proxies.clone();
+
+            App::new()
+                .wrap_fn(move |req, srv| ip_filter::check(&ip_filters, req, srv))
+                .wrap(Condition::new(
+                    !cors_origins.is_empty(),
+                    new_cors(&cors_origins),
+                ))
+                .wrap(Condition::new(sentry_enabled, sentry_actix::Sentry::new()))
+                .wrap_fn(move |req, srv| {
+                    let is_sampled = access_log::is_sampled(access_log_sample_rate);
+                    let client_ip = match is_sampled {
+                        true => client_ip::resolve(req.request(), &trusted_proxies),
+                        false => None,
+                    };
+                    if is_sampled {
+                        req.extensions_mut().insert(RequestSummary::default());
+                    }
+
+                    let started_at = Instant::now();
+                    let response = srv.call(req);
+
+                    async move {
+                        let response = response.await?;
+                        if is_sampled {
+                            access_log::log(
+                                response.request(),
+                                client_ip,
+                                response.status(),
+                                started_at.elapsed(),
+                            );
+                        }
+                        Ok(response)
+                    }
+                })
+                .configure(|cfg| {
+                    if serve_admin {
+                        admin_services(cfg);
+                    }
+                })
+                .service(rpc_call)
+                .service(default_rpc_call)
+                .service(get_rpc_call)
+                .app_data(app_state.clone())
+        });
+
+        // Connection handling settings not given keep the actix defaults.
+        if let Some(workers) = args.workers {
+            server = server.workers(workers);
+        }
+        if let Some(max_connections) = args.max_connections {
+            server = server.max_connections(max_connections);
+        }
+        if let Some(backlog) = args.backlog {
+            server = server.backlog(backlog);
+        }
+        if let Some(timeout) = args.client_request_timeout_ms {
+            server = server.client_request_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(keep_alive) = args.keep_alive_secs {
+            server = server.keep_alive(match keep_alive {
+                0 => KeepAlive::Disabled,
+                secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+            });
+        }
+
+        let tls_config = match (&args.tls_cert, &args.tls_key) {
+            (Some(cert_path), Some(key_path)) => {
+                Some(tls::load_server_config(cert_path, key_path).expect("fail to load tls config"))
+            }
+            _ => None,
+        };
+
+        for (host, port) in &args.bind {
+            let port = port.unwrap_or(args.port);
+            let address = match host.contains(':') {
+                true => format!("[{host}]:{port}"),
+                false => format!("{host}:{port}"),
+            };
+
+            server = match &tls_config {
+                Some(tls_config) => {
+                    tracing::info!("Server listening on https://{address}");
+                    server.bind_rustls_021((host.as_str(), port), tls_config.clone())?
+                }
+                None => {
+                    tracing::info!("Server listening on {address}");
+                    server.bind((host.as_str(), port))?
+                }
+            };
+        }
+
+        if args.admin_bind.is_empty() {
+            server.run().await?;
+        } else {
+            let admin_server = admin_server(&args, admin_app_state)?;
+            future::try_join(server.run(), admin_server).await?;
+        }
     }
 
     tracing::info!("Server stopped");
@@ -351,23 +1858,463 @@ async fn main() -> std::io::Result<()> {
     Ok(())
 }
 
+/// A chain whose upstreams are set up but whose chain id hasn't been detected yet.
+struct PendingChain {
+    name: String,
+    config: ChainConfig,
+    upstreams: UpstreamRouter,
+    upstream_limits: UpstreamLimits,
+    slot: ChainSlot,
+}
+
+const CHAIN_INIT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_CHAIN_INIT_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Detects the chain id of a pending chain and starts serving it. The chain is handed back if its upstreams
+/// can't be reached.
+async fn try_init_chain(
+    mut pending: Box<PendingChain>,
+    args: &Args,
+    handler_registry: &HandlerRegistry,
+) -> Result<(), Box<PendingChain>> {
+    let chain_id = match pending.config.chain_id {
+        Some(chain_id) => chain_id,
+        None => match detect_chain_id(&pending.upstreams).await {
+            Ok(chain_id) => chain_id,
+            Err(err) => {
+                tracing::error!("fail to get chain id of `{}`: {err:#}", pending.name);
+                return Err(pending);
+            }
+        },
+    };
+
+    if pending.config.probe_capabilities && !args.offline {
+        let unsupported =
+            capabilities::unsupported_namespaces(pending.upstreams.unrouted_upstreams()).await;
+        pending.upstreams.set_unsupported_namespaces(unsupported);
+    }
+
+    let PendingChain {
+        name,
+        config,
+        upstreams,
+        upstream_limits,
+        slot,
+    } = *pending;
+
+    let chain_state = start_chain(
+        &name,
+        config,
+        upstreams,
+        upstream_limits,
+        chain_id,
+        args,
+        handler_registry,
+    );
+
+    match chain_state {
+        Ok(chain_state) => {
+            let _ = slot.set(chain_state);
+            tracing::info!("Chain `{name}` initialized with chain id {chain_id}");
+        }
+        Err(err) => tracing::error!("fail to initialize chain `{name}`: {err:#}"),
+    }
+
+    Ok(())
+}
+
+async fn retry_init_chain(
+    mut pending: Box<PendingChain>,
+    args: Arc<Args>,
+    handler_registry: Arc<HandlerRegistry>,
+) {
+    let mut delay = CHAIN_INIT_RETRY_DELAY;
+
+    loop {
+        tracing::info!(
+            "retrying to initialize chain `{}` in {delay:?}",
+            pending.name
+        );
+        actix_web::rt::time::sleep(delay).await;
+
+        pending = match try_init_chain(pending, &args, &handler_registry).await {
+            Ok(()) => return,
+            Err(pending) => pending,
+        };
+
+        delay = (delay * 2).min(MAX_CHAIN_INIT_RETRY_DELAY);
+    }
+}
+
+/// Sets up the state of a chain whose chain id is known and spawns its background tasks.
+fn start_chain(
+    name: &str,
+    chain_config: ChainConfig,
+    upstreams: UpstreamRouter,
+    upstream_limits: UpstreamLimits,
+    chain_id: u64,
+    args: &Args,
+    handler_registry: &HandlerRegistry,
+) -> anyhow::Result<Arc<ChainState>> {
+    let mut cache_factory = new_cache_backend_factory(args, chain_id, &chain_config.redis)
+        .context("fail to create cache backend factory")?;
+
+    let key_filter = match &chain_config.key_filter {
+        Some(config) => {
+            // Read-only instances never write, so the filter would never learn the keys written by others.
+            anyhow::ensure!(
+                args.cache_mode != CacheMode::ReadOnly,
+                "key_filter doesn't work with --cache-mode=read-only"
+            );
+            anyhow::ensure!(
+                config.false_positive_rate > 0.0 && config.false_positive_rate < 1.0,
+                "key_filter.false_positive_rate must be between 0 and 1"
+            );
+
+            let key_filter = Arc::new(KeyFilter::new(config));
+            cache_factory = Box::new(KeyFilterBackendFactory::new(
+                cache_factory,
+                key_filter.clone(),
+            ));
+            Some(key_filter)
+        }
+        None => None,
+    };
+
+    if let Some(dir) = &args.restore_from {
+        let path = backup::snapshot_path(dir, name);
+        if path.exists() {
+            let mut cache_backend = cache_factory.get_instance()?;
+            let entries = backup::restore(&mut *cache_backend, &path)
+                .with_context(|| format!("fail to restore {}", path.display()))?;
+            tracing::info!("restored {entries} cache entries of {name}");
+        }
+    }
+
+    // Writes restored or received from peers aren't sent to the peers, only writes through the replicated factory.
+    let peers = match &args.peer_token {
+        Some(token) => {
+            let local_cache: Arc<dyn CacheBackendFactory> = Arc::from(cache_factory);
+            let queue = Arc::new(WriteQueue::default());
+            cache_factory = match args.peers.is_empty() {
+                true => Box::new(local_cache.clone()),
+                false => Box::new(ReplicatedBackendFactory::new(
+                    local_cache.clone(),
+                    queue.clone(),
+                )),
+            };
+            let peers = Peers::new(args.peers.clone(), token.clone(), queue, local_cache)?;
+            Some(Arc::new(peers))
+        }
+        None => None,
+    };
+
+    for method_alias in &chain_config.method_aliases {
+        method_alias.validate()?;
+    }
+
+    let confirmation_depth = args
+        .confirmation_depths
+        .iter()
+        .rev()
+        .find(|(chain, _)| chain == name)
+        .map(|(_, depth)| *depth)
+        .unwrap_or(chain_config.confirmation_depth);
+
+    let pinned_block = args
+        .pinned_blocks
+        .iter()
+        .rev()
+        .find(|(chain, _)| chain == name)
+        .map(|(_, block)| *block)
+        .or(chain_config.pinned_block);
+
+    if let Some(quorum) = &chain_config.quorum {
+        anyhow::ensure!(
+            quorum.upstreams >= 2 && (1..=quorum.upstreams).contains(&quorum.threshold),
+            "quorum needs at least 2 upstreams and a threshold between 1 and the number of upstreams"
+        );
+    }
+
+    let failure_injection = match (&chain_config.failure_injection, args.failure_injection) {
+        (Some(config), true) => {
+            config.validate()?;
+            tracing::warn!("Injecting upstream failures into `{name}`");
+            Some(config.clone())
+        }
+        (Some(_), false) => {
+            anyhow::bail!("failure_injection is only honored with --failure-injection")
+        }
+        (None, _) => None,
+    };
+
+    let forward_headers = chain_config
+        .forward_headers
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid forwarded header `{name}`"))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let mut chain_state = ChainState {
+        upstreams,
+        head: Default::default(),
+        resolve_latest_block: chain_config.resolve_latest_block,
+        lenient_id_matching: chain_config.lenient_id_matching,
+        sequential_upstream_ids: chain_config.sequential_upstream_ids,
+        max_logs_range: chain_config.max_logs_range,
+        log_index: chain_config.log_index.clone(),
+        emulate_filters: chain_config.emulate_filters,
+        filters: Default::default(),
+        retry_rate_limited: chain_config.retry_rate_limited,
+        store_metadata: chain_config.store_metadata,
+        upstream_failure_alert_threshold: args.upstream_failure_alert_threshold,
+        confirmation_depth,
+        pinned_block,
+        maintenance: Default::default(),
+        id_mismatches: Default::default(),
+        stats: Default::default(),
+        key_locks: Default::default(),
+        upstream_limits,
+        forward_headers,
+        local_results: local_results(chain_id, args),
+        quorum: chain_config.quorum.clone(),
+        method_aliases: chain_config.method_aliases.clone(),
+        params_rewrites: chain_config.params_rewrites.clone(),
+        static_responses: chain_config.static_responses.clone(),
+        transactions: chain_config.transactions.clone(),
+        pending_cache_ttl: chain_config.pending_cache_ttl_secs.map(Duration::from_secs),
+        max_params_key_len: chain_config.max_params_key_len,
+        failure_injection,
+        recent_transactions: Default::default(),
+        hot_keys: chain_config
+            .hot_key_refresh
+            .is_some()
+            .then(HotKeys::default),
+        cache_entries: Default::default(),
+        cache_factory,
+        peers,
+    };
+
+    for factory in handler_registry.factories() {
+        let handler = factory();
+        // Requests for them fail before reaching the cache.
+        if chain_state
+            .upstreams
+            .unsupported_namespace(handler.method_name())
+            .is_some()
+        {
+            continue;
+        }
+
+        let error_cache_ttl = chain_config
+            .error_cache_ttl_secs
+            .get(handler.method_name())
+            .map(|ttl| Duration::from_secs(*ttl));
+        let http_max_age = chain_config
+            .http_max_age_secs
+            .get(handler.method_name())
+            .copied()
+            .unwrap_or(match handler.cache_ttl() {
+                Some(ttl) => ttl.as_secs().try_into().unwrap_or(u32::MAX),
+                None => DEFAULT_HTTP_MAX_AGE_SECS,
+            });
+        let http_max_age = Some(http_max_age).filter(|max_age| *max_age > 0);
+
+        chain_state.cache_entries.insert(
+            handler.method_name(),
+            CacheEntry {
+                handler,
+                error_cache_ttl,
+                http_max_age,
+            },
+        );
+    }
+
+    if let Some(pinned_block) = pinned_block {
+        chain_state.head.update_latest(pinned_block);
+        chain_state.head.set_head_result(
+            pinned_block,
+            "eth_blockNumber",
+            Value::String(format!("0x{pinned_block:x}")),
+        );
+    }
+
+    let chain_state = Arc::new(chain_state);
+
+    if let Some(gc_config) = chain_config.gc {
+        actix_web::rt::spawn(gc::run_gc(chain_state.clone(), gc_config));
+    }
+
+    if !args.peers.is_empty() {
+        actix_web::rt::spawn(peers::run_replication(
+            chain_state.clone(),
+            name.to_string(),
+        ));
+    }
+
+    if let Some(dir) = &args.snapshot_dir {
+        actix_web::rt::spawn(backup::run_backups(
+            chain_state.clone(),
+            backup::snapshot_path(dir, name),
+            Duration::from_secs(args.snapshot_interval_secs),
+        ));
+    }
+
+    if let Some(max_bytes) = chain_config.redis.max_bytes {
+        actix_web::rt::spawn(gc::run_quota(chain_state.clone(), max_bytes));
+    }
+
+    if let Some(key_filter) = key_filter {
+        let state = chain_state.clone();
+        let name = name.to_string();
+
+        // Reads are filtered once the keys already cached are known.
+        actix_web::rt::spawn(async move {
+            let result = web::block(move || {
+                let mut cache_backend = state.cache_factory.get_instance()?;
+                key_filter.warm_up(&mut *cache_backend)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(keys)) => tracing::info!("key filter of {name} warmed up with {keys} keys"),
+                Ok(Err(err)) => tracing::error!("fail to warm up key filter of {name}: {err:#}"),
+                Err(err) => tracing::error!("fail to run key filter warm up of {name}: {err}"),
+            }
+        });
+    }
+
+    // Everything below keeps in touch with the upstreams.
+    if args.offline {
+        return Ok(chain_state);
+    }
+
+    if let Some(refresh_config) = chain_config.hot_key_refresh {
+        actix_web::rt::spawn(hot_keys::run_refresh(chain_state.clone(), refresh_config));
+    }
+
+    // The head of a pinned chain never moves.
+    if pinned_block.is_some() {
+        return Ok(chain_state);
+    }
+
+    let head_poll_interval = chain_config
+        .head_poll_interval_secs
+        .map(Duration::from_secs)
+        .unwrap_or(head_tracker::DEFAULT_POLL_INTERVAL);
+    actix_web::rt::spawn(head_tracker::poll_latest_block(
+        chain_state.clone(),
+        head_poll_interval,
+    ));
+
+    let ws_upstream = chain_state
+        .upstreams
+        .default_pool()
+        .upstreams()
+        .iter()
+        .find(|upstream| matches!(upstream.url.scheme(), "ws" | "wss"));
+    if let Some(upstream) = ws_upstream {
+        actix_web::rt::spawn(head_tracker::subscribe_new_heads(
+            chain_state.clone(),
+            upstream.url.clone(),
+        ));
+    }
+
+    Ok(chain_state)
+}
+
+/// Results of trivial methods answered without reaching the cache or the upstreams.
+fn local_results(chain_id: u64, args: &Args) -> HashMap<&'static str, Arc<Value>> {
+    let mut results = HashMap::from([
+        ("eth_chainId", Arc::new(json!(format!("0x{chain_id:x}")))),
+        ("net_version", Arc::new(json!(chain_id.to_string()))),
+    ]);
+
+    if args.local_client_version {
+        let client_version = format!("cached-eth-rpc/v{}", env!("CARGO_PKG_VERSION"));
+        results.insert("web3_clientVersion", Arc::new(json!(client_version)));
+    }
+
+    results
+}
+
+/// Queries the chain id from every upstream of the chain and makes sure the ones that answer serve the same chain.
+/// Upstreams that fail are left out, so that one being down doesn't keep the chain from starting.
+async fn detect_chain_id(upstreams: &UpstreamRouter) -> anyhow::Result<u64> {
+    let upstreams = upstreams
+        .pools()
+        .flat_map(|pool| pool.upstreams())
+        .collect::<Vec<_>>();
+    let chain_ids = future::join_all(upstreams.iter().copied().map(utils::get_chain_id)).await;
+
+    let mut detected: Option<(u64, &Url)> = None;
+    for (upstream, chain_id) in upstreams.iter().zip(chain_ids) {
+        let chain_id = match chain_id {
+            Ok(chain_id) => chain_id,
+            Err(err) => {
+                tracing::warn!("fail to get chain id from {}: {err:#}", upstream.url);
+                continue;
+            }
+        };
+
+        match detected {
+            Some((expected, first_url)) if expected != chain_id => {
+                anyhow::bail!(
+                    "upstream {} serves chain {chain_id} while {first_url} serves chain {expected}",
+                    upstream.url
+                );
+            }
+            Some(_) => {}
+            None => detected = Some((chain_id, &upstream.url)),
+        }
+    }
+
+    detected
+        .map(|(chain_id, _)| chain_id)
+        .context("no upstream answered the chain id")
+}
+
 fn new_cache_backend_factory(
     args: &Args,
     chain_id: u64,
+    redis_config: &RedisConfig,
 ) -> anyhow::Result<Box<dyn CacheBackendFactory>> {
-    let factory: Box<dyn CacheBackendFactory> = match &args.redis_url {
+    let redis_url = redis_config.url.as_ref().or(args.redis_url.as_ref());
+
+    let factory: Box<dyn CacheBackendFactory> = match redis_url {
         Some(redis_url) => {
             tracing::info!("Using redis cache backend");
 
+            let mut connection_info = redis_url
+                .as_str()
+                .into_connection_info()
+                .context("fail to parse redis url")?;
+            if let Some(db) = redis_config.db {
+                connection_info.redis.db = db;
+            }
+
             let client =
-                redis::Client::open(redis_url.as_ref()).context("fail to create redis client")?;
+                redis::Client::open(connection_info).context("fail to create redis client")?;
 
             let conn_pool = r2d2::Pool::builder()
-                .max_size(300)
-                .test_on_check_out(false)
+                .max_size(args.redis_pool_max_size)
+                .min_idle(args.redis_pool_min_idle)
+                .connection_timeout(Duration::from_secs(args.redis_pool_connection_timeout_secs))
+                .test_on_check_out(args.redis_pool_test_on_check_out)
                 .build(client)
                 .context("fail to create redis connection pool")?;
-            let factory = RedisBackendFactory::new(chain_id, conn_pool);
+            let key_prefix = redis_config
+                .key_prefix
+                .clone()
+                .unwrap_or_else(|| chain_id.to_string());
+            let factory = RedisBackendFactory::new(
+                key_prefix,
+                conn_pool,
+                args.redis_fetch_locks,
+                redis_config.max_bytes.is_some(),
+            );
 
             Box::new(factory)
         }
@@ -377,22 +2324,445 @@ fn new_cache_backend_factory(
         }
     };
 
+    if args.cache_mode != CacheMode::ReadWrite {
+        tracing::info!("Using cache in {:?} mode", args.cache_mode);
+        return Ok(Box::new(ModeBackendFactory::new(factory, args.cache_mode)));
+    }
+
     Ok(factory)
 }
 
+fn new_cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+        .allow_any_header()
+        .max_age(3600);
+
+    if origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+
+    origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
+
+/// Management endpoints, served on the public addresses unless `--admin-bind` is given.
+fn admin_services(cfg: &mut web::ServiceConfig) {
+    cfg.service(stats::health)
+        .service(stats::chain_stats)
+        .service(stats::upstream_usage)
+        .service(stats::metrics)
+        .service(inspect::inspect)
+        .service(maintenance::status)
+        .service(maintenance::enable)
+        .service(maintenance::disable)
+        .service(peers::replicate);
+}
+
+/// Server of the management endpoints on the `--admin-bind` addresses, which can be firewalled separately from
+/// the public ones.
+fn admin_server(args: &Args, app_state: web::Data<AppState>) -> std::io::Result<Server> {
+    let ip_filters = IpFilters::from_args(args);
+
+    let mut server = HttpServer::new(move || {
+        let ip_filters = ip_filters.clone();
+
+        App::new()
+            .wrap_fn(move |req, srv| ip_filter::check(&ip_filters, req, srv))
+            .configure(admin_services)
+            .app_data(app_state.clone())
+    })
+    .workers(1);
+
+    for address in &args.admin_bind {
+        server = match address {
+            AdminAddress::Tcp(host, port) => {
+                let port = port.unwrap_or(args.admin_port);
+                match host.contains(':') {
+                    true => tracing::info!("Admin server listening on [{host}]:{port}"),
+                    false => tracing::info!("Admin server listening on {host}:{port}"),
+                }
+                server.bind((host.as_str(), port))?
+            }
+            #[cfg(unix)]
+            AdminAddress::Unix(path) => {
+                tracing::info!("Admin server listening on unix:{}", path.display());
+                server.bind_uds(path)?
+            }
+            #[cfg(not(unix))]
+            AdminAddress::Unix(path) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("unix sockets are unsupported: {}", path.display()),
+                ));
+            }
+        };
+    }
+
+    Ok(server.run())
+}
+
+/// How long a cache miss waits for a concurrent request fetching the same key before fetching it itself. Also
+/// bounds the wait of batches missing the same keys in a different order.
+const KEY_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a cache miss checks whether another instance fetching the same key cached its result.
+const REMOTE_KEY_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Seconds caches in front may keep hits of single requests whose results are final, unless configured.
+const DEFAULT_HTTP_MAX_AGE_SECS: u32 = 365 * 24 * 60 * 60;
+
+/// A cache miss waiting for a concurrent request fetching the same key.
+struct WaitingRequest {
+    index: usize,
+    id: RequestId,
+    method: String,
+    params: Value,
+    params_key: String,
+    key: String,
+}
+
+impl WaitingRequest {
+    /// Gives up waiting and fetches the key.
+    fn into_uncached_request(self) -> RpcRequest {
+        RpcRequest::new(self.index, self.id, self.method, self.params, self.key)
+    }
+}
+
+/// Appended to the params key of a request to store its cached error, next to where its result would be.
+const ERROR_KEY_SUFFIX: &str = ":error";
+
+/// Appended to the params key of a request to store the metadata of its cached result.
+const METADATA_KEY_SUFFIX: &str = ":meta";
+
 struct ChainState {
-    rpc_url: Url,
+    upstreams: UpstreamRouter,
+    head: HeadTracker,
+    resolve_latest_block: bool,
+    lenient_id_matching: bool,
+    /// Upstreams are sent sequential numbers as ids, see `ChainConfig::sequential_upstream_ids`.
+    sequential_upstream_ids: bool,
+    /// Blocks per `eth_getLogs` request the upstreams accept, see `ChainConfig::max_logs_range`.
+    max_logs_range: Option<u64>,
+    log_index: Option<LogIndexConfig>,
+    emulate_filters: bool,
+    filters: Filters,
+    retry_rate_limited: bool,
+    /// Consecutive failures of an upstream that are reported as an error.
+    upstream_failure_alert_threshold: u32,
+    /// Whether cached results are stored along with when and where they were fetched.
+    store_metadata: bool,
+    confirmation_depth: u64,
+    /// Block tags resolve to this block and later blocks are rejected if set.
+    pinned_block: Option<u64>,
+    /// Misses fail instead of reaching the upstreams while set, see `/{chain}/maintenance`.
+    maintenance: AtomicBool,
+    /// Upstream responses whose id matched no request of the batch.
+    id_mismatches: AtomicU64,
+    stats: ChainStats,
+    key_locks: KeyLocks,
+    upstream_limits: UpstreamLimits,
+    forward_headers: Vec<HeaderName>,
+    /// Results of methods that never change for the chain, e.g. `eth_chainId`.
+    local_results: HashMap<&'static str, Arc<Value>>,
+    quorum: Option<QuorumConfig>,
+    /// Applied to every request first, the first match renames its method.
+    method_aliases: Vec<MethodAliasConfig>,
+    /// Applied in order after the method aliases, filling in params clients leave out.
+    params_rewrites: Vec<ParamsRewriteConfig>,
+    /// Checked in order once aliases and params rewrites are applied, before anything else. The first match
+    /// answers the request.
+    static_responses: Vec<StaticResponseConfig>,
+    transactions: TransactionConfig,
+    pending_cache_ttl: Option<Duration>,
+    max_params_key_len: Option<usize>,
+    /// Breaks upstream responses of client requests on purpose if set, see `--failure-injection`.
+    failure_injection: Option<FailureInjectionConfig>,
+    recent_transactions: RecentTransactions,
+    /// Tracked only if hot entries are refreshed.
+    hot_keys: Option<HotKeys>,
     cache_factory: Box<dyn CacheBackendFactory>,
-    cache_entries: HashMap<String, CacheEntry>,
+    /// Set if writes are accepted from peers, with `--peer-token`.
+    peers: Option<Arc<Peers>>,
+    /// Looked up once per request, keyed by the static method names of the handlers to keep hashing cheap.
+    cache_entries: HashMap<&'static str, CacheEntry, ahash::RandomState>,
+}
+
+impl ChainState {
+    /// Params key of a request, hashed if it's longer than `max_params_key_len`.
+    fn params_key(
+        &self,
+        cache_entry: &CacheEntry,
+        params: &Value,
+    ) -> anyhow::Result<Option<String>> {
+        let params_key = cache_entry.handler.extract_cache_key(params)?;
+        Ok(params_key.map(|params_key| self.shorten_params_key(params_key)))
+    }
+
+    fn shorten_params_key(&self, params_key: String) -> String {
+        match self.max_params_key_len {
+            Some(max_len) => common::shorten_params_key(params_key, max_len),
+            None => params_key,
+        }
+    }
+
+    fn route(&self, method: &str, params: &Value) -> &UpstreamPool {
+        let referenced_block = self
+            .cache_entries
+            .get(method)
+            .and_then(|entry| entry.handler.referenced_block(params).ok())
+            .flatten();
+
+        self.upstreams
+            .route(method, referenced_block, self.head.latest())
+    }
+
+    /// Whether the newest block `params` depend on is at least `confirmation_depth` blocks behind the head, or
+    /// finalized for handlers requiring finality. Requests not referencing a block number are always
+    /// considered confirmed.
+    fn is_confirmed(&self, handler: &dyn RpcCacheHandler, params: &Value) -> bool {
+        let Ok(Some(block)) = handler.newest_block(params) else {
+            return true;
+        };
+
+        if handler.requires_finality() {
+            if let Some(finalized) = self.head.finalized() {
+                return block <= finalized;
+            }
+        }
+
+        if self.confirmation_depth == 0 {
+            return true;
+        }
+
+        matches!(
+            self.head.latest(),
+            Some(latest) if block.saturating_add(self.confirmation_depth) <= latest
+        )
+    }
+
+    /// Whether the result of a request can't be reorged away anymore: its block, referenced by `params` or else
+    /// by the `blockNumber` or `number` of `result`, is finalized, or at least `confirmation_depth` blocks behind
+    /// the head on chains not reporting a finalized block. Never with a confirmation depth of zero.
+    fn is_final(&self, handler: &dyn RpcCacheHandler, params: &Value, result: &Value) -> bool {
+        let block = match handler.newest_block(params) {
+            Ok(Some(block)) => Some(block),
+            _ => [&result["blockNumber"], &result["number"]]
+                .into_iter()
+                .find_map(|block| common::parse_block_number(block).ok().flatten()),
+        };
+        let Some(block) = block else {
+            return false;
+        };
+
+        match (self.head.finalized(), self.head.latest()) {
+            (Some(finalized), _) => block <= finalized,
+            (None, Some(latest)) if self.confirmation_depth > 0 => {
+                block.saturating_add(self.confirmation_depth) <= latest
+            }
+            _ => false,
+        }
+    }
+
+    /// Caches upstream errors the handler considers permanent, for methods with error caching enabled.
+    fn write_error_cache(
+        &self,
+        cache_backend: &mut dyn CacheBackend,
+        rpc_request: &RpcRequest,
+        error: &Value,
+    ) {
+        let Some(cache_key) = &rpc_request.cache_key else {
+            return;
+        };
+
+        // It's safe to unwrap here because requests of methods without a cache entry never get a cache key.
+        let cache_entry = self.cache_entries.get(rpc_request.method.as_str()).unwrap();
+        let (handler, Some(ttl)) = (&cache_entry.handler, cache_entry.error_cache_ttl) else {
+            return;
+        };
+
+        if !handler.is_permanent_error(&rpc_request.params, error)
+            || !self.is_confirmed(handler.as_ref(), &rpc_request.params)
+        {
+            return;
+        }
+
+        let key = format!("{cache_key}{ERROR_KEY_SUFFIX}");
+        let _ = cache_backend.write(&key, &error.to_string(), Some(ttl));
+    }
+
+    /// Caches a result fetched from `source` outside of a client request, e.g. while pre-warming.
+    fn cache_result(
+        &self,
+        method: &str,
+        params: &Value,
+        result: &Value,
+        source: &Upstream,
+    ) -> anyhow::Result<()> {
+        let cache_entry = self
+            .cache_entries
+            .get(method)
+            .context("cache is not supported")?;
+
+        let Some(params_key) = self.params_key(cache_entry, params)? else {
+            return Ok(());
+        };
+
+        let mut cache_backend = self.cache_factory.get_instance()?;
+        let cache_key = cache_backend.key(method, &params_key);
+
+        self.write_cache(
+            cache_backend.as_mut(),
+            method,
+            params,
+            &cache_key,
+            result,
+            source,
+        )
+    }
+
+    /// Writes `result` and the secondary entries derived from it if the handler of `method` considers it
+    /// cacheable. Fails only if the cache value can't be extracted.
+    fn write_cache(
+        &self,
+        cache_backend: &mut dyn CacheBackend,
+        method: &str,
+        params: &Value,
+        cache_key: &str,
+        result: &Value,
+        source: &Upstream,
+    ) -> anyhow::Result<()> {
+        // It's safe to unwrap here because requests of methods without a cache entry never get a cache key.
+        let cache_entry = self.cache_entries.get(method).unwrap();
+
+        if !result.is_null() {
+            if let Err(err) = cache_entry.handler.validate_result(params, result) {
+                tracing::warn!(
+                    method,
+                    "invalid result from {} is not cached: {err:#}",
+                    source.url
+                );
+                self.stats.invalid_results.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+
+        let (can_cache, extracted_value) = cache_entry.handler.extract_cache_value(result)?;
+        if !self.is_confirmed(cache_entry.handler.as_ref(), params) {
+            return Ok(());
+        }
+
+        // Pending results, e.g. of transactions not mined yet, are cached briefly if at all, so that the entry
+        // is replaced by the final result soon.
+        let ttl = match (can_cache, self.pending_cache_ttl) {
+            (true, _) => cache_entry.handler.cache_ttl(),
+            (false, Some(ttl)) if cache_entry.handler.is_pending(result) => {
+                let value = serde_json::to_string(result)?;
+                let _ = cache_backend.write(cache_key, &value, Some(ttl));
+                return Ok(());
+            }
+            (false, _) => return Ok(()),
+        };
+        let _ = cache_backend.write(cache_key, &extracted_value, ttl);
+
+        if self.store_metadata {
+            let metadata = EntryMetadata::new(source, self.head.latest());
+            let key = format!("{cache_key}{METADATA_KEY_SUFFIX}");
+            let _ = cache_backend.write(&key, &serde_json::to_string(&metadata)?, ttl);
+        }
+
+        if let (Some(hot_keys), Some(ttl)) = (&self.hot_keys, ttl) {
+            hot_keys.track(cache_key, method, params, ttl);
+        }
+
+        match cache_entry
+            .handler
+            .extract_secondary_cache_entries(params, result)
+        {
+            Ok(entries) => {
+                for entry in entries {
+                    let params_key = self.shorten_params_key(entry.params_key);
+                    let key = cache_backend.key(entry.method, &params_key);
+                    let _ = cache_backend.write(&key, &entry.value, None);
+                }
+            }
+            Err(err) => {
+                tracing::warn!(method, "fail to extract secondary cache entries: {err:#}");
+            }
+        }
+
+        Ok(())
+    }
 }
 
 struct CacheEntry {
     handler: Box<dyn RpcCacheHandler>,
+    /// TTL of cached permanent upstream errors, `None` if errors of the method aren't cached.
+    error_cache_ttl: Option<Duration>,
+    /// Seconds caches in front may keep hits of single requests, `None` if disabled. The TTL of the entries
+    /// unless configured for methods cached with a TTL.
+    http_max_age: Option<u32>,
 }
 
+impl CacheEntry {
+    /// `Cache-Control` of hits of single requests. Results cached with a TTL may be kept as long as their
+    /// entry, others only once they are final, but then as immutable.
+    fn http_cache_control(&self, is_final: bool) -> Option<CacheControl> {
+        let max_age = self.http_max_age?;
+
+        match self.handler.cache_ttl() {
+            Some(_) => Some(CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(max_age),
+            ])),
+            None if is_final => Some(CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(max_age),
+                CacheDirective::Extension("immutable".to_string(), None),
+            ])),
+            None => None,
+        }
+    }
+}
+
+/// Holds the state of a chain once its initialization succeeded.
+type ChainSlot = Arc<OnceLock<Arc<ChainState>>>;
+
 struct AppState {
-    chains: HashMap<String, ChainState>,
-    http_client: reqwest::Client,
+    /// Keyed by uppercase chain name.
+    chains: HashMap<String, ChainSlot>,
+    /// Uppercase aliases to the name of their chain.
+    aliases: HashMap<String, String>,
+    verify_sample_rate: f64,
+    /// Clients must send one of these keys if set.
+    api_keys: Option<ApiKeys>,
+    /// Clients must send a token signed with this secret if set.
+    jwt_secret: Option<JwtSecret>,
+    /// Upstreams are never contacted, every request is served from the cache only.
+    offline: bool,
+    /// Big batches are answered in the order their responses are known.
+    reorder_batches: bool,
+    /// Chain served at `/`, by name or alias.
+    default_chain: Option<String>,
+    compute_units: stats::ComputeUnitsConfig,
+}
+
+impl AppState {
+    /// Slot of the chain named or aliased `name`, case-insensitively.
+    fn chain_slot(&self, name: &str) -> Option<&ChainSlot> {
+        let name = name.to_uppercase();
+        let name = self.aliases.get(&name).unwrap_or(&name);
+
+        self.chains.get(name)
+    }
+
+    /// Chains that have been initialized.
+    fn ready_chains(&self) -> impl Iterator<Item = (&String, &Arc<ChainState>)> {
+        self.chains
+            .iter()
+            .filter_map(|(name, slot)| Some((name, slot.get()?)))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -428,11 +2798,181 @@ impl RpcRequest {
 
 impl Serialize for RpcRequest {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        JsonRpcRequest::new(
-            Some(self.id.clone()),
-            self.method.clone(),
-            self.params.clone(),
+        JsonRpcRequest::new(Some(&self.id), &self.method, &self.params).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+
+    #[test]
+    fn test_is_fresh() {
+        let mut backend = MemoryBackendFactory::new().get_instance().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut write_metadata = |params_key: &str, stored_at: u64| {
+            let metadata = EntryMetadata {
+                stored_at,
+                upstream: None,
+                head_block: None,
+            };
+            let key = backend.key(
+                "eth_getBalance",
+                &format!("{params_key}{METADATA_KEY_SUFFIX}"),
+            );
+            let value = serde_json::to_string(&metadata).unwrap();
+            backend.write(&key, &value, None).unwrap();
+        };
+        write_metadata("0x10-0xab", now - 5);
+        write_metadata("0x11-0xab", now - 60);
+
+        assert!(is_fresh(
+            backend.as_mut(),
+            "eth_getBalance",
+            "0x10-0xab",
+            10
+        ));
+        assert!(!is_fresh(
+            backend.as_mut(),
+            "eth_getBalance",
+            "0x11-0xab",
+            10
+        ));
+        assert!(is_fresh(
+            backend.as_mut(),
+            "eth_getBalance",
+            "0x11-0xab",
+            60
+        ));
+        // Stored without metadata.
+        assert!(!is_fresh(
+            backend.as_mut(),
+            "eth_getBalance",
+            "0x12-0xab",
+            3600
+        ));
+    }
+
+    #[test]
+    fn test_http_cache_control() {
+        let entry = |ttl_secs: &str| {
+            let config = format!("method: custom_method\nkey: ['$[0]']\nttl_secs: {ttl_secs}");
+            let config = serde_yaml::from_str(&config).unwrap();
+            CacheEntry {
+                handler: rpc_cache_handler::declarative::new_factory(&config)(),
+                error_cache_ttl: None,
+                http_max_age: Some(30),
+            }
+        };
+        let directives = |cache_control: Option<CacheControl>| cache_control.map(|header| header.0);
+
+        let permanent = entry("null");
+        assert_eq!(directives(permanent.http_cache_control(false)), None);
+        assert_eq!(
+            directives(permanent.http_cache_control(true)),
+            Some(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(30),
+                CacheDirective::Extension("immutable".to_string(), None),
+            ])
+        );
+
+        let expected = Some(vec![CacheDirective::Public, CacheDirective::MaxAge(30)]);
+        let with_ttl = entry("30");
+        assert_eq!(directives(with_ttl.http_cache_control(false)), expected);
+        assert_eq!(directives(with_ttl.http_cache_control(true)), expected);
+    }
+
+    #[actix_web::test]
+    async fn test_duplicates_of_waited_request() {
+        let mut args = <Args as clap::Parser>::parse_from(["cached-eth-rpc"]);
+        args.redis_url = None;
+        let chain_config = ChainConfig {
+            // Keeps the head tracker from polling the unreachable upstream.
+            pinned_block: Some(0x100),
+            ..Default::default()
+        };
+        let upstream = Url::parse("http://127.0.0.1:1").unwrap().into();
+        let upstreams = UpstreamPool::new(
+            vec![upstream],
+            chain_config.load_balance,
+            &UpstreamClient::default(),
+        )
+        .and_then(|pool| {
+            UpstreamRouter::new(pool, vec![], None, vec![], None, None, &Default::default())
+        })
+        .unwrap();
+        let upstream_limits = UpstreamLimits::new(None, None, Duration::from_secs(1));
+        let chain_state = start_chain(
+            "ethereum",
+            chain_config,
+            upstreams,
+            upstream_limits,
+            1,
+            &args,
+            &HandlerRegistry::load(),
         )
-        .serialize(serializer)
+        .unwrap();
+
+        let params = json!(["0x1", false]);
+        let mut cache_backend = chain_state.cache_factory.get_instance().unwrap();
+        let cache_entry = chain_state
+            .cache_entries
+            .get("eth_getBlockByNumber")
+            .unwrap();
+        let params_key = chain_state
+            .params_key(cache_entry, &params)
+            .unwrap()
+            .unwrap();
+        let key = cache_backend.key("eth_getBlockByNumber", &params_key);
+        // Another request is fetching the block right now.
+        let guard = chain_state.key_locks.try_lock(&key).ok().unwrap();
+
+        let slot = ChainSlot::default();
+        let _ = slot.set(chain_state.clone());
+        let data = web::Data::new(AppState {
+            chains: HashMap::from([("ETHEREUM".to_string(), slot)]),
+            aliases: Default::default(),
+            verify_sample_rate: 0.0,
+            api_keys: None,
+            jwt_secret: None,
+            offline: false,
+            reorder_batches: false,
+            default_chain: None,
+            compute_units: Default::default(),
+        });
+        let request = |id: u64| json!({ "jsonrpc": "2.0", "id": id, "method": "eth_getBlockByNumber", "params": params });
+        let body = web::Json(json!([request(1), request(2)]));
+        let response = serve_rpc_call(
+            actix_web::test::TestRequest::default().to_http_request(),
+            "ethereum",
+            data,
+            body,
+            None,
+        );
+
+        let block = json!({ "number": "0x1" });
+        let release = async move {
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+            cache_backend.write(&key, &block.to_string(), None).unwrap();
+            drop(guard);
+        };
+        let (response, ()) = future::join(response, release).await;
+
+        let body = actix_web::body::to_bytes(response.unwrap().into_body())
+            .await
+            .unwrap();
+        let responses: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            responses,
+            json!([
+                { "jsonrpc": "2.0", "id": 1, "result": { "number": "0x1" } },
+                { "jsonrpc": "2.0", "id": 2, "result": { "number": "0x1" } },
+            ])
+        );
     }
 }
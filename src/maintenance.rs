@@ -0,0 +1,60 @@
+use std::sync::atomic::Ordering;
+
+use actix_web::{error, web, Error, HttpResponse};
+use serde::Serialize;
+
+use crate::{AppState, ChainState};
+
+#[derive(Serialize)]
+struct MaintenanceStatus {
+    maintenance: bool,
+}
+
+fn chain_state<'a>(data: &'a AppState, chain: &str) -> Result<&'a ChainState, Error> {
+    data.chain_slot(chain)
+        .and_then(|slot| slot.get())
+        .map(|chain_state| chain_state.as_ref())
+        .ok_or_else(|| error::ErrorNotFound("endpoint not supported"))
+}
+
+fn respond(chain_state: &ChainState) -> HttpResponse {
+    HttpResponse::Ok().json(MaintenanceStatus {
+        maintenance: chain_state.maintenance.load(Ordering::Relaxed),
+    })
+}
+
+#[actix_web::get("/{chain}/maintenance")]
+pub async fn status(
+    path: web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    Ok(respond(chain_state(&data, &path.0)?))
+}
+
+/// Puts the chain into maintenance, e.g. while its nodes resync. Cache hits are still served, misses fail with
+/// an error instead of reaching the upstreams.
+#[actix_web::put("/{chain}/maintenance")]
+pub async fn enable(
+    path: web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let chain_state = chain_state(&data, &path.0)?;
+    if !chain_state.maintenance.swap(true, Ordering::Relaxed) {
+        tracing::warn!("chain `{}` is under maintenance", path.0);
+    }
+
+    Ok(respond(chain_state))
+}
+
+#[actix_web::delete("/{chain}/maintenance")]
+pub async fn disable(
+    path: web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let chain_state = chain_state(&data, &path.0)?;
+    if chain_state.maintenance.swap(false, Ordering::Relaxed) {
+        tracing::info!("chain `{}` is out of maintenance", path.0);
+    }
+
+    Ok(respond(chain_state))
+}
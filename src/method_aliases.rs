@@ -0,0 +1,68 @@
+use serde::Deserialize;
+
+/// Renames requests of `from` to `to` before they're looked up in the cache or forwarded, e.g. for upstreams
+/// serving `parity_*` methods as `trace_*`. A trailing `*` on both sides renames by prefix.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MethodAliasConfig {
+    pub from: String,
+    pub to: String,
+}
+
+impl MethodAliasConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.from.ends_with('*') == self.to.ends_with('*'),
+            "method alias `{}` -> `{}` must either rename a prefix on both sides or neither",
+            self.from,
+            self.to
+        );
+        Ok(())
+    }
+
+    fn rewrite(&self, method: &str) -> Option<String> {
+        match (self.from.strip_suffix('*'), self.to.strip_suffix('*')) {
+            (Some(from), Some(to)) => method.strip_prefix(from).map(|rest| format!("{to}{rest}")),
+            _ => (method == self.from).then(|| self.to.clone()),
+        }
+    }
+}
+
+/// `method` renamed by the first matching alias. Aliases aren't chained.
+pub fn rewrite(aliases: &[MethodAliasConfig], method: String) -> String {
+    aliases
+        .iter()
+        .find_map(|alias| alias.rewrite(&method))
+        .unwrap_or(method)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let alias = |from: &str, to: &str| MethodAliasConfig {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        let aliases = [
+            alias("parity_*", "trace_*"),
+            alias("eth_getTransactionReceipt", "alchemy_getTransactionReceipt"),
+            alias("trace_*", "debug_*"),
+        ];
+
+        assert_eq!(rewrite(&aliases, "parity_block".to_string()), "trace_block");
+        assert_eq!(
+            rewrite(&aliases, "eth_getTransactionReceipt".to_string()),
+            "alchemy_getTransactionReceipt"
+        );
+        assert_eq!(
+            rewrite(&aliases, "eth_getTransactionReceipts".to_string()),
+            "eth_getTransactionReceipts"
+        );
+        assert_eq!(rewrite(&aliases, "eth_call".to_string()), "eth_call");
+
+        assert!(alias("parity_*", "trace_block").validate().is_err());
+    }
+}
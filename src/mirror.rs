@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::transport::UpstreamClient;
+use crate::upstream::Upstream;
+
+/// Mirrored batches in flight at once, further batches are dropped until some complete.
+const MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    pub url: Url,
+
+    /// Fraction of uncached batches that are mirrored.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+/// Copies uncached batches to another url without waiting for or looking at its responses, e.g. to feed a
+/// staging environment or an analytics pipeline with real traffic.
+pub struct Mirror {
+    upstream: Upstream,
+    sample_rate: f64,
+    in_flight: AtomicUsize,
+    /// Batches mirrored, whether or not they were answered.
+    pub requests: AtomicU64,
+    /// Batches not mirrored because too many were in flight.
+    pub dropped: AtomicU64,
+}
+
+impl Mirror {
+    pub fn new(config: &MirrorConfig, http_client: &UpstreamClient) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&config.sample_rate),
+            "mirror.sample_rate must be between 0 and 1"
+        );
+
+        Ok(Self {
+            upstream: Upstream::new(config.url.clone().into(), http_client)?,
+            sample_rate: config.sample_rate,
+            in_flight: Default::default(),
+            requests: Default::default(),
+            dropped: Default::default(),
+        })
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.upstream.url
+    }
+
+    /// Sends `batch` to the mirror in the background if it's sampled.
+    pub fn send<T: Serialize + ?Sized>(self: &Arc<Self>, batch: &T) {
+        if rand::random::<f64>() >= self.sample_rate {
+            return;
+        }
+
+        if self.in_flight.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        // Serialized upfront so that the batch doesn't need to outlive the request.
+        let body = match serde_json::to_value(batch) {
+            Ok(body) => body,
+            Err(err) => {
+                self.in_flight.fetch_sub(1, Ordering::Relaxed);
+                tracing::warn!("fail to serialize mirrored batch: {err:#}");
+                return;
+            }
+        };
+
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mirror = self.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = mirror.upstream.request(&body).await {
+                tracing::debug!("fail to mirror batch to {}: {err:#}", mirror.upstream.url);
+            }
+            mirror.in_flight.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
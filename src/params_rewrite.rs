@@ -0,0 +1,90 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Fills in params of `method` clients leave out but an upstream requires, e.g. the trace types of
+/// `trace_replayBlockTransactions`, before the request is looked up in the cache or forwarded.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ParamsRewriteConfig {
+    pub method: String,
+
+    /// Params by position, used where the client sent none or `null`. `null` entries leave their position alone.
+    pub defaults: Vec<Value>,
+}
+
+impl ParamsRewriteConfig {
+    fn apply(&self, params: Value) -> Value {
+        let mut params = match params {
+            Value::Array(params) => params,
+            Value::Null => vec![],
+            // Named params are left to the upstream.
+            params => return params,
+        };
+
+        // Trailing params can only be filled in once every param before them is present.
+        let len = self
+            .defaults
+            .iter()
+            .rposition(|default| !default.is_null())
+            .map_or(0, |position| position + 1);
+        if params.len() < len {
+            params.resize(len, Value::Null);
+        }
+
+        for (param, default) in params.iter_mut().zip(&self.defaults) {
+            if param.is_null() {
+                *param = default.clone();
+            }
+        }
+
+        Value::Array(params)
+    }
+}
+
+/// `params` of `method` rewritten by every matching entry of `rewrites` in order.
+pub fn rewrite(rewrites: &[ParamsRewriteConfig], method: &str, params: Value) -> Value {
+    rewrites
+        .iter()
+        .filter(|rewrite| rewrite.method == method)
+        .fold(params, |params, rewrite| rewrite.apply(params))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let rewrites = [ParamsRewriteConfig {
+            method: "trace_replayBlockTransactions".to_string(),
+            defaults: vec![json!(null), json!(["trace"])],
+        }];
+
+        assert_eq!(
+            rewrite(&rewrites, "trace_replayBlockTransactions", json!(["0x10"])),
+            json!(["0x10", ["trace"]])
+        );
+        assert_eq!(
+            rewrite(
+                &rewrites,
+                "trace_replayBlockTransactions",
+                json!(["0x10", null])
+            ),
+            json!(["0x10", ["trace"]])
+        );
+        assert_eq!(
+            rewrite(
+                &rewrites,
+                "trace_replayBlockTransactions",
+                json!(["0x10", ["vmTrace"]])
+            ),
+            json!(["0x10", ["vmTrace"]])
+        );
+        assert_eq!(
+            rewrite(&rewrites, "trace_block", json!(["0x10"])),
+            json!(["0x10"])
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{error, web, Error, HttpRequest, HttpResponse};
+use anyhow::Context;
+use futures::future;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::cache::replication::{ReplicatedWrite, WriteQueue};
+use crate::cache::CacheBackendFactory;
+use crate::{AppState, ChainState};
+
+/// How often queued writes are sent to the peers.
+const REPLICATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Approximate size of the batches sent to the peers, well below the 2 MiB body limit of the endpoint. Larger
+/// writes, e.g. some traces, aren't replicated.
+const MAX_BATCH_BYTES: usize = 1 << 20;
+
+/// Seconds a peer has to accept a batch of writes.
+const REPLICATION_TIMEOUT_SECS: u64 = 10;
+
+/// Other instances of the proxy the cache writes of a chain are sent to, and accepted from, on a best-effort basis.
+/// Writes lost while a peer is down or the queue is full are never resent.
+pub struct Peers {
+    /// Admin addresses of the peers.
+    urls: Vec<Url>,
+    /// Shared by the peers to authenticate their writes.
+    token: String,
+    queue: Arc<WriteQueue>,
+    /// Cache of the chain without replication, so that writes from peers aren't sent back.
+    local_cache: Arc<dyn CacheBackendFactory>,
+    client: reqwest::Client,
+    /// Batches a peer failed to accept.
+    pub errors: AtomicU64,
+}
+
+impl Peers {
+    pub fn new(
+        urls: Vec<Url>,
+        token: String,
+        queue: Arc<WriteQueue>,
+        local_cache: Arc<dyn CacheBackendFactory>,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REPLICATION_TIMEOUT_SECS))
+            .build()
+            .context("fail to create peer http client")?;
+
+        Ok(Self {
+            urls,
+            token,
+            queue,
+            local_cache,
+            client,
+            errors: Default::default(),
+        })
+    }
+
+    /// Whether `token` is the shared token. Both are compared through their HMAC, in constant time, so that the
+    /// time taken doesn't tell how much of a guess is right.
+    fn is_valid_token(&self, token: &str) -> bool {
+        let mac = |message: &str| {
+            // It's safe to unwrap here because HMAC accepts keys of any length.
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.token.as_bytes()).unwrap();
+            mac.update(message.as_bytes());
+            mac
+        };
+
+        mac(token)
+            .verify_slice(&mac(&self.token).finalize().into_bytes())
+            .is_ok()
+    }
+
+    async fn send(&self, url: &Url, writes: &[ReplicatedWrite]) -> anyhow::Result<()> {
+        self.client
+            .post(url.clone())
+            .bearer_auth(&self.token)
+            .json(writes)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Periodically sends the writes queued since the last run to every peer of the chain `name`.
+pub async fn run_replication(chain_state: Arc<ChainState>, name: String) {
+    let Some(peers) = chain_state.peers.clone() else {
+        return;
+    };
+
+    let urls = peers
+        .urls
+        .iter()
+        .filter_map(|url| match url.join(&format!("{name}/replicate")) {
+            Ok(url) => Some(url),
+            Err(err) => {
+                tracing::error!("invalid peer url {url}: {err}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut ticker = actix_web::rt::time::interval(REPLICATION_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        let writes = peers.queue.take();
+        if writes.is_empty() {
+            continue;
+        }
+
+        for batch in batches(writes) {
+            let results = future::join_all(urls.iter().map(|url| peers.send(url, &batch))).await;
+            for (url, result) in urls.iter().zip(results) {
+                if let Err(err) = result {
+                    peers.errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!("fail to replicate {} writes to {url}: {err:#}", batch.len());
+                }
+            }
+        }
+    }
+}
+
+/// Splits `writes` into batches of about `MAX_BATCH_BYTES`, leaving out writes too large for a batch of their own.
+fn batches(writes: Vec<ReplicatedWrite>) -> Vec<Vec<ReplicatedWrite>> {
+    let mut batches = vec![];
+    let mut batch = vec![];
+    let mut batch_bytes = 0;
+
+    for write in writes {
+        // Fields, quotes and escapes take a few more bytes once serialized.
+        let bytes = write.key.len() + write.value.len() * 5 / 4 + 64;
+        if bytes > MAX_BATCH_BYTES {
+            continue;
+        }
+
+        if batch_bytes + bytes > MAX_BATCH_BYTES {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch.push(write);
+        batch_bytes += bytes;
+    }
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+#[derive(Serialize)]
+struct Replicated {
+    written: usize,
+}
+
+/// Writes the cache writes of a peer to the cache of the chain.
+#[actix_web::post("/{chain}/replicate")]
+pub async fn replicate(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    writes: web::Json<Vec<ReplicatedWrite>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (chain,) = path.into_inner();
+    let chain_state = data
+        .chain_slot(&chain)
+        .and_then(|slot| slot.get())
+        .ok_or_else(|| error::ErrorNotFound("endpoint not supported"))?;
+    let peers = chain_state
+        .peers
+        .clone()
+        .ok_or_else(|| error::ErrorNotFound("replication not enabled"))?;
+
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token.is_some_and(|token| peers.is_valid_token(token)) {
+        return Err(error::ErrorUnauthorized("invalid peer token"));
+    }
+
+    let writes = writes.into_inner();
+    let written = writes.len();
+    web::block(move || {
+        let mut cache_backend = peers.local_cache.get_instance()?;
+        for write in writes {
+            let ttl = write.ttl_ms.map(Duration::from_millis);
+            cache_backend.write(&write.key, &write.value, ttl)?;
+        }
+        anyhow::Ok(())
+    })
+    .await?
+    .map_err(|err| error::ErrorInternalServerError(format!("{err:#}")))?;
+
+    Ok(HttpResponse::Ok().json(Replicated { written }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_token() {
+        let peers = Peers::new(
+            vec![],
+            "secret".to_string(),
+            Default::default(),
+            Arc::new(crate::cache::memory_backend::MemoryBackendFactory::new()),
+        )
+        .unwrap();
+
+        assert!(peers.is_valid_token("secret"));
+        assert!(!peers.is_valid_token("secreT"));
+        assert!(!peers.is_valid_token("secret2"));
+        assert!(!peers.is_valid_token(""));
+    }
+
+    #[test]
+    fn test_batches() {
+        let write = |value_len: usize| ReplicatedWrite {
+            key: "eth_getBlockByNumber:0x1-true".to_string(),
+            value: "0".repeat(value_len),
+            ttl_ms: None,
+        };
+
+        let batches = batches(vec![
+            write(400_000),
+            write(400_000),
+            // Too large to be sent.
+            write(MAX_BATCH_BYTES),
+            write(400_000),
+            write(10),
+        ]);
+        let sizes = batches.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2, 2]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use alloy_primitives::U64;
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+
+use crate::json_rpc::{DefinedError, JsonRpcResponse};
+use crate::{ChainState, RpcRequest};
+
+/// Methods describing a single node, answered for the whole pool instead so that dashboards see its state
+/// rather than the state of whichever upstream the request landed on.
+const AGGREGATED_METHODS: [&str; 2] = ["eth_syncing", "net_peerCount"];
+
+pub fn is_aggregated(method: &str) -> bool {
+    AGGREGATED_METHODS.contains(&method)
+}
+
+/// Sends the request to every upstream it's routed to at once and combines their results: the pool is syncing if
+/// any upstream is, and has as many peers as its best connected upstream. Upstreams that fail are left out.
+pub async fn aggregate(
+    chain_state: Arc<ChainState>,
+    rpc_request: RpcRequest,
+    forwarded_headers: HeaderMap,
+) -> (usize, JsonRpcResponse) {
+    let upstreams = chain_state
+        .route(&rpc_request.method, &rpc_request.params)
+        .upstreams();
+
+    let responses = futures::future::join_all(
+        upstreams
+            .iter()
+            .map(|upstream| upstream.request_with_headers(&rpc_request, &forwarded_headers)),
+    )
+    .await;
+
+    let mut results = vec![];
+    let mut last_response = None;
+    for (upstream, response) in upstreams.iter().zip(responses) {
+        match response {
+            Ok(mut response) if response["error"].is_null() => {
+                results.push(response["result"].take())
+            }
+            Ok(response) => last_response = Some(response),
+            Err(err) => tracing::warn!(
+                method = rpc_request.method,
+                "fail to query {} because: {err:#}",
+                upstream.url
+            ),
+        }
+    }
+
+    let id = rpc_request.id;
+    let response = match combine(&rpc_request.method, results) {
+        Some(result) => JsonRpcResponse::from_result(id, result),
+        None => match last_response {
+            // Every upstream answered with an error, the last one is forwarded.
+            Some(mut response) => {
+                JsonRpcResponse::from_custom_error(Some(id), response["error"].take())
+            }
+            None => JsonRpcResponse::from_error(
+                Some(id),
+                DefinedError::UpstreamUnavailable(Some(json!({
+                    "reason": "every upstream failed",
+                }))),
+            ),
+        },
+    };
+
+    (rpc_request.index, response)
+}
+
+/// Result for the pool, `None` without results.
+fn combine(method: &str, results: Vec<Value>) -> Option<Value> {
+    if results.is_empty() {
+        return None;
+    }
+
+    match method {
+        // The syncing upstream furthest behind tells how far the pool is from being in sync.
+        "eth_syncing" => {
+            let current_block = |result: &Value| {
+                serde_json::from_value::<U64>(result["currentBlock"].clone()).unwrap_or_default()
+            };
+
+            let furthest_behind = results
+                .iter()
+                .filter(|result| result.is_object())
+                .min_by_key(|result| current_block(result))
+                .cloned();
+            Some(furthest_behind.unwrap_or(Value::Bool(false)))
+        }
+        "net_peerCount" => results
+            .iter()
+            .filter_map(|result| serde_json::from_value::<U64>(result.clone()).ok())
+            .max()
+            .map(|peers| json!(peers)),
+        _ => results.into_iter().next(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(
+            combine("eth_syncing", vec![json!(false), json!(false)]),
+            Some(json!(false))
+        );
+
+        let syncing = |current_block: &str| {
+            json!({
+                "startingBlock": "0x0",
+                "currentBlock": current_block,
+                "highestBlock": "0x100",
+            })
+        };
+        assert_eq!(
+            combine(
+                "eth_syncing",
+                vec![json!(false), syncing("0x80"), syncing("0x20")]
+            ),
+            Some(syncing("0x20"))
+        );
+
+        assert_eq!(
+            combine(
+                "net_peerCount",
+                vec![json!("0x19"), json!("0x32"), json!("0x7")]
+            ),
+            Some(json!("0x32"))
+        );
+
+        assert_eq!(combine("net_peerCount", vec![]), None);
+    }
+}
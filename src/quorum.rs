@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::json_rpc::RequestId;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuorumConfig {
+    /// Upstreams every uncached batch is sent to, including the one whose response is forwarded.
+    pub upstreams: usize,
+
+    /// Upstreams that must return the same result for it to be cached.
+    pub threshold: usize,
+}
+
+/// What the upstreams queried for a quorum agree on.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    /// At least `threshold` upstreams returned this result, which may be cached.
+    Agreed(Value),
+    /// No result reached the threshold. The result of the answering upstream is forwarded but not cached.
+    Disputed(Value),
+}
+
+/// Results of the same batch from the other upstreams of a quorum, keyed by request id.
+pub struct Witnesses {
+    results: Vec<HashMap<RequestId, Value>>,
+    threshold: usize,
+}
+
+impl Witnesses {
+    /// Takes the batch responses of the other upstreams. Failed requests and error responses don't vote.
+    pub fn new(responses: Vec<anyhow::Result<Value>>, threshold: usize) -> Self {
+        let results = responses
+            .into_iter()
+            .filter_map(|response| match response {
+                Ok(Value::Array(responses)) => Some(responses),
+                _ => None,
+            })
+            .map(|responses| {
+                responses
+                    .into_iter()
+                    .filter_map(|mut response| {
+                        if !response["error"].is_null() {
+                            return None;
+                        }
+
+                        let id = RequestId::try_from(response.get_mut("id")?.take()).ok()?;
+                        Some((id, response.get_mut("result")?.take()))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { results, threshold }
+    }
+
+    /// Settles on the result returned most often for request `id`, counting `result` of the answering upstream,
+    /// which wins ties.
+    pub fn settle(&self, id: &RequestId, result: Value) -> Verdict {
+        let mut votes: Vec<(&Value, usize)> = vec![(&result, 1)];
+
+        for witness in self.results.iter().filter_map(|results| results.get(id)) {
+            match votes.iter_mut().find(|(value, _)| *value == witness) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((witness, 1)),
+            }
+        }
+
+        // `max_by_key` returns the last maximum, so iterating in reverse lets the answering upstream win ties.
+        let (majority, count) = votes
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .copied()
+            .unwrap_or((&result, 1));
+
+        if count < self.threshold {
+            return Verdict::Disputed(result);
+        }
+
+        match majority == &result {
+            true => Verdict::Agreed(result),
+            false => Verdict::Agreed(majority.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn id(id: u64) -> RequestId {
+        RequestId::try_from(json!(id)).unwrap()
+    }
+
+    #[test]
+    fn test_settle() {
+        let witnesses = Witnesses::new(
+            vec![
+                Ok(json!([
+                    { "jsonrpc": "2.0", "id": 1, "result": "0x1" },
+                    { "jsonrpc": "2.0", "id": 2, "result": "0x2" },
+                    { "jsonrpc": "2.0", "id": 3, "error": { "code": -32000, "message": "busy" } },
+                ])),
+                Ok(json!([{ "jsonrpc": "2.0", "id": 2, "result": "0x2" }])),
+                Err(anyhow::anyhow!("timed out")),
+            ],
+            2,
+        );
+
+        assert_eq!(
+            witnesses.settle(&id(1), json!("0x1")),
+            Verdict::Agreed(json!("0x1"))
+        );
+        assert_eq!(
+            witnesses.settle(&id(2), json!("0xbad")),
+            Verdict::Agreed(json!("0x2"))
+        );
+        assert_eq!(
+            witnesses.settle(&id(3), json!("0x3")),
+            Verdict::Disputed(json!("0x3"))
+        );
+    }
+
+    #[test]
+    fn test_tie() {
+        let witnesses = Witnesses::new(vec![Ok(json!([{ "id": 1, "result": "0x2" }]))], 1);
+
+        assert_eq!(
+            witnesses.settle(&id(1), json!("0x1")),
+            Verdict::Agreed(json!("0x1"))
+        );
+    }
+}
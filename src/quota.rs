@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{web, HttpRequest};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheBackendFactory;
+
+/// Header carrying the API key of a request. The `api_key` query parameter is accepted as well, for clients
+/// that can't set headers.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub daily: Quota,
+    pub monthly: Quota,
+}
+
+/// Usage allowed per period, unlimited if unset.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    /// Each request of a batch counts as one.
+    pub requests: Option<u64>,
+    /// Size of the responses fetched from upstreams, cache hits are free.
+    pub upstream_bytes: Option<u64>,
+}
+
+/// A quota of the API key is used up.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    period: Period,
+    resource: &'static str,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} quota exceeded", self.period.name(), self.resource)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[derive(Debug, Clone, Copy)]
+enum Period {
+    Daily,
+    Monthly,
+}
+
+impl Period {
+    fn name(self) -> &'static str {
+        match self {
+            Period::Daily => "daily",
+            Period::Monthly => "monthly",
+        }
+    }
+
+    /// Identifies the UTC day or month of `unix_secs`, e.g. `d19783` or `m2024-03`.
+    fn id(self, unix_secs: u64) -> String {
+        let days = unix_secs / DAY_SECS;
+
+        match self {
+            Period::Daily => format!("d{days}"),
+            Period::Monthly => {
+                let (year, month) = year_month(days);
+                format!("m{year}-{month:02}")
+            }
+        }
+    }
+
+    /// How long the counters of a period are kept, a bit longer than the period itself.
+    fn ttl(self) -> Duration {
+        match self {
+            Period::Daily => Duration::from_secs(2 * DAY_SECS),
+            Period::Monthly => Duration::from_secs(32 * DAY_SECS),
+        }
+    }
+}
+
+/// Year and month of the civil date `days` after the unix epoch.
+/// Source: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn year_month(days: u64) -> (u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month)
+}
+
+/// API keys of the clients, with their usage counted in a cache backend so that it's shared by instances
+/// using the same redis.
+pub struct ApiKeys {
+    keys: HashMap<String, ApiKeyConfig>,
+    usage: Box<dyn CacheBackendFactory>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: HashMap<String, ApiKeyConfig>, usage: Box<dyn CacheBackendFactory>) -> Self {
+        Self { keys, usage }
+    }
+
+    /// Reads the keys file, a map of API keys to their quotas.
+    pub fn load_keys(path: &Path) -> anyhow::Result<HashMap<String, ApiKeyConfig>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("fail to read keys file {}", path.display()))?;
+
+        serde_yaml::from_str(&content).context("fail to parse keys file")
+    }
+
+    /// The known API key the request was sent with.
+    pub fn key_of(&self, req: &HttpRequest) -> Option<String> {
+        let api_key = match req.headers().get(API_KEY_HEADER) {
+            Some(value) => value.to_str().ok()?.to_string(),
+            None => {
+                web::Query::<ApiKeyQuery>::from_query(req.query_string())
+                    .ok()?
+                    .into_inner()
+                    .api_key
+            }
+        };
+
+        self.keys.contains_key(&api_key).then_some(api_key)
+    }
+
+    /// Counts `requests` of `api_key`. Fails with [`QuotaExceeded`] if a quota of the key is used up, in
+    /// which case the requests must be rejected.
+    pub fn record_requests(&self, api_key: &str, requests: u64) -> anyhow::Result<()> {
+        let config = self.keys.get(api_key).context("unknown api key")?;
+        let mut usage = self.usage.get_instance()?;
+        let now = unix_secs();
+
+        for (period, quota) in [
+            (Period::Daily, &config.daily),
+            (Period::Monthly, &config.monthly),
+        ] {
+            let params_key = format!("{}:{api_key}", period.id(now));
+
+            let key = usage.key("requests", &params_key);
+            let request_count = usage.incr(&key, requests, period.ttl())?;
+            if quota.requests.is_some_and(|limit| request_count > limit) {
+                anyhow::bail!(QuotaExceeded {
+                    period,
+                    resource: "requests",
+                });
+            }
+
+            let key = usage.key("upstream_bytes", &params_key);
+            let upstream_bytes = usage.incr(&key, 0, period.ttl())?;
+            if quota
+                .upstream_bytes
+                .is_some_and(|limit| upstream_bytes >= limit)
+            {
+                anyhow::bail!(QuotaExceeded {
+                    period,
+                    resource: "upstream bytes",
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn record_upstream_bytes(&self, api_key: &str, bytes: u64) -> anyhow::Result<()> {
+        let mut usage = self.usage.get_instance()?;
+        let now = unix_secs();
+
+        for period in [Period::Daily, Period::Monthly] {
+            let params_key = format!("{}:{api_key}", period.id(now));
+            let key = usage.key("upstream_bytes", &params_key);
+            usage.incr(&key, bytes, period.ttl())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiKeyQuery {
+    api_key: String,
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Size of the JSON of `value`, without holding it in memory.
+pub fn serialized_len(value: &impl Serialize) -> u64 {
+    struct ByteCounter(u64);
+
+    impl io::Write for ByteCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = ByteCounter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::cache::memory_backend::MemoryBackendFactory;
+
+    #[test]
+    fn test_period_id() {
+        // 2024-02-29 and 2024-03-01
+        assert_eq!(Period::Daily.id(19782 * DAY_SECS), "d19782");
+        assert_eq!(Period::Monthly.id(19782 * DAY_SECS), "m2024-02");
+        assert_eq!(Period::Monthly.id(19783 * DAY_SECS), "m2024-03");
+        assert_eq!(Period::Monthly.id(0), "m1970-01");
+    }
+
+    fn new_api_keys() -> ApiKeys {
+        let config = ApiKeyConfig {
+            daily: Quota {
+                requests: Some(3),
+                upstream_bytes: Some(100),
+            },
+            monthly: Default::default(),
+        };
+        let keys = HashMap::from([("alice".to_string(), config)]);
+
+        ApiKeys::new(keys, Box::new(MemoryBackendFactory::new()))
+    }
+
+    #[test]
+    fn test_record_requests() {
+        let api_keys = new_api_keys();
+        api_keys.record_requests("alice", 2).unwrap();
+        let err = api_keys.record_requests("alice", 2).unwrap_err();
+        assert_eq!(err.to_string(), "daily requests quota exceeded");
+        assert!(api_keys.record_requests("bob", 1).is_err());
+
+        let api_keys = new_api_keys();
+        api_keys.record_upstream_bytes("alice", 100).unwrap();
+        let err = api_keys.record_requests("alice", 1).unwrap_err();
+        assert!(err.is::<QuotaExceeded>());
+    }
+
+    #[test]
+    fn test_key_of() {
+        let api_keys = new_api_keys();
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((API_KEY_HEADER, "alice"))
+            .to_http_request();
+        assert_eq!(api_keys.key_of(&req).as_deref(), Some("alice"));
+
+        let req = actix_web::test::TestRequest::with_uri("/eth?api_key=alice").to_http_request();
+        assert_eq!(api_keys.key_of(&req).as_deref(), Some("alice"));
+
+        let req = actix_web::test::TestRequest::with_uri("/eth?api_key=bob").to_http_request();
+        assert_eq!(api_keys.key_of(&req), None);
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        assert_eq!(serialized_len(&json!({"result": "0x1"})), 16);
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde_json::Value;
+
+use crate::json_rpc::RequestId;
+
+/// Maps the responses of an upstream batch back to the requests of the batch.
+///
+/// Requests sharing an id are answered in order. In lenient mode ids are compared loosely (`1` matches
+/// `"1"`) and responses whose id matches no pending request are handed to the remaining requests by
+/// position.
+pub struct ResponseMatcher {
+    lenient: bool,
+    pending: HashMap<String, VecDeque<usize>>,
+    answered: Vec<bool>,
+}
+
+impl ResponseMatcher {
+    pub fn new<'a>(ids: impl Iterator<Item = &'a RequestId>, lenient: bool) -> Self {
+        let mut pending: HashMap<String, VecDeque<usize>> = HashMap::new();
+        let mut answered = vec![];
+
+        for (index, id) in ids.enumerate() {
+            let key = id_key(&serde_json::to_value(id).unwrap_or_default(), lenient);
+            pending.entry(key).or_default().push_back(index);
+            answered.push(false);
+        }
+
+        Self {
+            lenient,
+            pending,
+            answered,
+        }
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Index of the first unanswered request with the given response id.
+    pub fn match_id(&mut self, id: &Value) -> Option<usize> {
+        let index = self
+            .pending
+            .get_mut(&id_key(id, self.lenient))?
+            .pop_front()?;
+        self.answered[index] = true;
+
+        Some(index)
+    }
+
+    /// Index of the request at `position` if it's unanswered, otherwise of the first unanswered request.
+    pub fn match_position(&mut self, position: usize) -> Option<usize> {
+        let index = match self.answered.get(position) {
+            Some(false) => position,
+            _ => self.answered.iter().position(|answered| !answered)?,
+        };
+
+        self.answered[index] = true;
+        for indexes in self.pending.values_mut() {
+            indexes.retain(|i| *i != index);
+        }
+
+        Some(index)
+    }
+
+    pub fn unanswered(&self) -> impl Iterator<Item = usize> + '_ {
+        self.answered
+            .iter()
+            .enumerate()
+            .filter(|(_, answered)| !**answered)
+            .map(|(index, _)| index)
+    }
+}
+
+fn id_key(id: &Value, lenient: bool) -> String {
+    match id {
+        Value::String(s) if lenient => s.clone(),
+        Value::Number(n) if lenient => n.to_string(),
+        _ => id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn ids(values: &[Value]) -> Vec<RequestId> {
+        values
+            .iter()
+            .map(|v| RequestId::try_from(v.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let ids = ids(&[json!(1), json!(2), json!(1)]);
+        let mut matcher = ResponseMatcher::new(ids.iter(), false);
+
+        assert_eq!(matcher.match_id(&json!(1)), Some(0));
+        assert_eq!(matcher.match_id(&json!(1)), Some(2));
+        assert_eq!(matcher.match_id(&json!(1)), None);
+        assert_eq!(matcher.unanswered().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_strict_id_type() {
+        let ids = ids(&[json!(1)]);
+        let mut matcher = ResponseMatcher::new(ids.iter(), false);
+
+        assert_eq!(matcher.match_id(&json!("1")), None);
+        assert_eq!(matcher.match_id(&json!(1)), Some(0));
+    }
+
+    #[test]
+    fn test_lenient_id_type() {
+        let ids = ids(&[json!(1), json!("2")]);
+        let mut matcher = ResponseMatcher::new(ids.iter(), true);
+
+        assert_eq!(matcher.match_id(&json!("1")), Some(0));
+        assert_eq!(matcher.match_id(&json!(2)), Some(1));
+    }
+
+    #[test]
+    fn test_match_position() {
+        let ids = ids(&[json!(1), json!(2), json!(3)]);
+        let mut matcher = ResponseMatcher::new(ids.iter(), true);
+
+        assert_eq!(matcher.match_id(&json!(2)), Some(1));
+        assert_eq!(matcher.match_position(1), Some(0));
+        assert_eq!(matcher.match_position(2), Some(2));
+        assert_eq!(matcher.match_position(0), None);
+        assert_eq!(matcher.match_id(&json!(1)), None);
+    }
+}
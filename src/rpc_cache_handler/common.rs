@@ -2,9 +2,14 @@ use std::str::FromStr;
 
 use alloy_primitives::{Address, B256, U64};
 use anyhow::{bail, Context};
-use serde_json::Value;
+use serde_json::{json, Value};
 use sha1::Digest;
 
+use crate::rpc_cache_handler::{
+    eth_get_transaction_by_block_hash_and_index, eth_get_transaction_by_block_number_and_index,
+    eth_get_transaction_by_hash, eth_get_transaction_receipt, RpcCacheHandler, SecondaryCacheEntry,
+};
+
 pub enum ParamsSpec {
     Exact(usize),
     AtLeast(usize),
@@ -36,13 +41,18 @@ pub fn extract_address_cache_key(params: &Value) -> anyhow::Result<Option<String
     let account: Address =
         serde_json::from_value(params[0].clone()).context("params[0] not a valid address")?;
 
-    let block_tag = match extract_and_format_block_tag(&params[1])
-        .context("params[1] not a valid block tag")?
-    {
+    // An omitted block tag means `latest`.
+    let block_tag = match params.get(1) {
         Some(block_tag) => block_tag,
         None => return Ok(None),
     };
 
+    let block_tag =
+        match extract_and_format_block_tag(block_tag).context("params[1] not a valid block tag")? {
+            Some(block_tag) => block_tag,
+            None => return Ok(None),
+        };
+
     let lowercase_address = account.to_string().to_lowercase();
 
     Ok(Some(format!("{block_tag}-{lowercase_address}")))
@@ -54,22 +64,158 @@ pub fn extract_transaction_cache_value(result: &Value) -> anyhow::Result<(bool,
     Ok((can_cache, serde_json::to_string(result)?))
 }
 
-pub fn extract_and_format_block_number(value: &Value) -> anyhow::Result<Option<String>> {
+pub fn is_invalid_params_error(error: &Value) -> bool {
+    error["code"].as_i64() == Some(-32602)
+}
+
+/// Reverts of calls at a fixed block are deterministic, unlike e.g. timeouts or missing state.
+pub fn is_execution_reverted_error(error: &Value) -> bool {
+    let message = error["message"].as_str().unwrap_or_default();
+
+    error["code"].as_i64() == Some(3) || message.starts_with("execution reverted")
+}
+
+pub fn parse_block_number(value: &Value) -> anyhow::Result<Option<u64>> {
     let value = value.as_str().context("block tag not a string")?;
 
-    let block_tag = match value {
+    let block_number = match value {
         "earliest" | "latest" | "pending" | "finalized" | "safe" => None,
         _ => {
             let v = U64::from_str(value)
                 .context("block tag not a valid block number")?
                 .as_limbs()[0];
-            Some(format!("0x{:x}", v))
+            Some(v)
         }
     };
 
+    Ok(block_number)
+}
+
+/// Entry `handler` would cache for a request with `params` answered by `result`, if it's cacheable.
+pub fn extract_secondary_cache_entry(
+    handler: &dyn RpcCacheHandler,
+    params: &Value,
+    result: &Value,
+) -> anyhow::Result<Option<SecondaryCacheEntry>> {
+    let params_key = match handler.extract_cache_key(params)? {
+        Some(params_key) => params_key,
+        None => return Ok(None),
+    };
+
+    if handler.validate_result(params, result).is_err() {
+        return Ok(None);
+    }
+
+    let (can_cache, value) = handler.extract_cache_value(result)?;
+    if !can_cache {
+        return Ok(None);
+    }
+
+    Ok(Some(SecondaryCacheEntry {
+        method: handler.method_name(),
+        params_key,
+        value,
+    }))
+}
+
+/// `eth_getTransactionByHash` entries of a block fetched with full transaction objects.
+pub fn extract_block_transaction_entries(
+    block: &Value,
+) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+    let transactions = match block["transactions"].as_array() {
+        Some(transactions) => transactions,
+        None => return Ok(vec![]),
+    };
+
+    let handler = eth_get_transaction_by_hash::Handler::default();
+    let mut entries = vec![];
+
+    // Blocks fetched without transaction details only list hashes, which are skipped here.
+    for tx in transactions.iter().filter(|tx| tx.is_object()) {
+        if let Some(entry) = extract_secondary_cache_entry(&handler, &json!([tx["hash"]]), tx)? {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// `eth_getTransactionByBlockHashAndIndex` entries of a block fetched with full transaction objects, and the
+/// `eth_getTransactionByBlockNumberAndIndex` ones too if `by_number`, which is only safe for confirmed blocks.
+pub fn extract_block_transaction_index_entries(
+    block: &Value,
+    by_number: bool,
+) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+    let transactions = match block["transactions"].as_array() {
+        Some(transactions) => transactions,
+        None => return Ok(vec![]),
+    };
+
+    let mut block_tags: Vec<(&dyn RpcCacheHandler, &Value)> = vec![(
+        &eth_get_transaction_by_block_hash_and_index::Handler,
+        &block["hash"],
+    )];
+    if by_number {
+        block_tags.push((
+            &eth_get_transaction_by_block_number_and_index::Handler,
+            &block["number"],
+        ));
+    }
+
+    let mut entries = vec![];
+
+    for (index, tx) in transactions
+        .iter()
+        .enumerate()
+        .filter(|(_, tx)| tx.is_object())
+    {
+        for (handler, block_tag) in &block_tags {
+            let params = json!([block_tag, format!("{index:#x}")]);
+            if let Some(entry) = extract_secondary_cache_entry(*handler, &params, tx)? {
+                entries.push(entry);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// `eth_getTransactionReceipt` entries of an `eth_getBlockReceipts` result.
+pub fn extract_block_receipt_entries(receipts: &Value) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+    let receipts = match receipts.as_array() {
+        Some(receipts) => receipts,
+        None => return Ok(vec![]),
+    };
+
+    let handler = eth_get_transaction_receipt::Handler;
+    let mut entries = vec![];
+
+    for receipt in receipts {
+        let params = json!([receipt["transactionHash"]]);
+
+        if let Some(entry) = extract_secondary_cache_entry(&handler, &params, receipt)? {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn extract_and_format_block_number(value: &Value) -> anyhow::Result<Option<String>> {
+    let block_tag = parse_block_number(value)?.map(|v| format!("0x{:x}", v));
+
     Ok(block_tag)
 }
 
+/// Block number in `params[index]`, which may also be a block tag, a block hash or missing entirely.
+pub fn extract_referenced_block(params: &Value, index: usize) -> anyhow::Result<Option<u64>> {
+    match params.get(index) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) if s.len() == 66 => Ok(None),
+        Some(value) => parse_block_number(value),
+    }
+}
+
 pub fn extract_and_format_block_hash(value: &Value) -> anyhow::Result<String> {
     let value_str = value.as_str().context("block tag not a string")?;
 
@@ -92,12 +238,93 @@ pub fn extract_and_format_block_tag(value: &Value) -> anyhow::Result<Option<Stri
     }
 }
 
+pub const BLOCK_FIELDS: &[&str] = &["hash", "number", "parentHash", "transactions"];
+/// Uncles come without transactions.
+pub const HEADER_FIELDS: &[&str] = &["hash", "number", "parentHash"];
+pub const TRANSACTION_FIELDS: &[&str] = &["hash", "blockHash", "from"];
+pub const RECEIPT_FIELDS: &[&str] = &["transactionHash", "blockHash", "blockNumber", "logs"];
+pub const LOG_FIELDS: &[&str] = &["address", "topics", "data"];
+
+/// Fails unless `result` is an object with all of `fields`, which may be null.
+pub fn require_fields(result: &Value, fields: &[&str]) -> anyhow::Result<()> {
+    let object = result
+        .as_object()
+        .context("expect result to be an object")?;
+
+    match fields.iter().find(|field| !object.contains_key(**field)) {
+        Some(field) => bail!("result is missing `{field}`"),
+        None => Ok(()),
+    }
+}
+
+/// Fails unless `result` is an array of objects with all of `fields`.
+pub fn require_array_of(result: &Value, fields: &[&str]) -> anyhow::Result<()> {
+    result
+        .as_array()
+        .context("expect result to be an array")?
+        .iter()
+        .try_for_each(|item| require_fields(item, fields))
+}
+
+/// Fails unless `result` is `0x` prefixed hex, e.g. a quantity or call data.
+pub fn require_hex(result: &Value) -> anyhow::Result<()> {
+    let hex = result
+        .as_str()
+        .and_then(|value| value.strip_prefix("0x"))
+        .context("expect result to be a hex string")?;
+
+    match hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        true => Ok(()),
+        false => bail!("expect result to be a hex string"),
+    }
+}
+
+/// Fails unless `field` of `result` is the hash requested in `params[0]`, e.g. the hash of a transaction fetched by
+/// hash. Guards durable entries against upstreams answering with the wrong object.
+pub fn require_requested_hash(params: &Value, result: &Value, field: &str) -> anyhow::Result<()> {
+    let requested: B256 =
+        serde_json::from_value(params[0].clone()).context("params[0] is not a valid hash")?;
+    let returned: B256 = serde_json::from_value(result[field].clone())
+        .with_context(|| format!("`{field}` is not a valid hash"))?;
+
+    match requested == returned {
+        true => Ok(()),
+        false => bail!("`{field}` {returned:#x} doesn't match the requested hash {requested:#x}"),
+    }
+}
+
 pub fn hash_string(s: &str) -> String {
     let mut hasher = sha1::Sha1::new();
     hasher.update(s.as_bytes());
     let result = hasher.finalize();
 
-    hex::encode(result.as_slice())
+    hex::encode(result)
+}
+
+/// Length of the hashed part of a shortened params key, `#` included, and thus the shortest `max_params_key_len`.
+pub const HASHED_PARAMS_KEY_LEN: usize = 41;
+
+/// BLAKE3 hash of a long params key, cut to 160 bits. Collisions would serve the result of one request to another,
+/// so unlike [`hash_string`] it uses a hash without known collision attacks.
+fn hash_params_key(s: &str) -> String {
+    hex::encode(&blake3::hash(s.as_bytes()).as_bytes()[..(HASHED_PARAMS_KEY_LEN - 1) / 2])
+}
+
+/// `params_key` if it's at most `max_len` long, hashed otherwise. The block number or hash the key starts with is
+/// kept if it fits along with the hash, so that garbage collection still knows the block of the entry. Hashed parts
+/// start with `#`, which keys of the built-in handlers don't contain, so that they can't collide with a key kept as
+/// is. `max_len` must be at least [`HASHED_PARAMS_KEY_LEN`].
+pub fn shorten_params_key(params_key: String, max_len: usize) -> String {
+    if params_key.len() <= max_len {
+        return params_key;
+    }
+
+    match params_key.split_once('-') {
+        Some((block, rest)) if block.len() + HASHED_PARAMS_KEY_LEN < max_len => {
+            format!("{block}-#{}", hash_params_key(rest))
+        }
+        _ => format!("#{}", hash_params_key(&params_key)),
+    }
 }
 
 #[cfg(test)]
@@ -175,6 +402,70 @@ mod test {
         }
     }
 
+    mod test_extract_referenced_block {
+        use super::super::*;
+        use serde_json::json;
+
+        #[test]
+        fn test_block_number() {
+            let params = json!(["0x1234567890abcdef1234567890abcdef12345678", "0x12345"]);
+            assert_eq!(extract_referenced_block(&params, 1).unwrap(), Some(0x12345));
+        }
+
+        #[test]
+        fn test_block_tag_and_hash() {
+            let params = json!([
+                "latest",
+                "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+            ]);
+            assert_eq!(extract_referenced_block(&params, 0).unwrap(), None);
+            assert_eq!(extract_referenced_block(&params, 1).unwrap(), None);
+        }
+
+        #[test]
+        fn test_missing() {
+            let params = json!([{}]);
+            assert_eq!(extract_referenced_block(&params, 1).unwrap(), None);
+        }
+    }
+
+    mod test_extract_block_transaction_entries {
+        use super::super::*;
+        use serde_json::json;
+
+        #[test]
+        fn test_full_transactions() {
+            let tx = json!({
+                "hash": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+                "blockHash": "0xabcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890",
+                "from": "0x1234567890abcdef1234567890abcdef12345678",
+            });
+            let block = json!({ "transactions": [tx] });
+
+            let entries = extract_block_transaction_entries(&block).unwrap();
+            assert_eq!(
+                entries,
+                vec![SecondaryCacheEntry {
+                    method: "eth_getTransactionByHash",
+                    params_key:
+                        "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+                            .to_string(),
+                    value: serde_json::to_string(&tx).unwrap(),
+                }]
+            );
+        }
+
+        #[test]
+        fn test_transaction_hashes_only() {
+            let block = json!({
+                "transactions": ["0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"]
+            });
+
+            let entries = extract_block_transaction_entries(&block).unwrap();
+            assert!(entries.is_empty());
+        }
+    }
+
     mod test_extract_address_cache_key {
         use super::super::*;
         use serde_json::json;
@@ -214,4 +505,68 @@ mod test {
             assert_eq!(err.to_string(), "params[1] not a valid block tag");
         }
     }
+
+    mod test_validate_result {
+        use super::super::*;
+        use serde_json::json;
+
+        #[test]
+        fn test_require_fields() {
+            let receipt = json!({
+                "transactionHash": "0x01",
+                "blockHash": null,
+                "blockNumber": null,
+                "logs": [],
+            });
+            assert!(require_fields(&receipt, RECEIPT_FIELDS).is_ok());
+
+            let err = require_fields(&json!({ "transactionHash": "0x01" }), RECEIPT_FIELDS);
+            assert_eq!(
+                err.unwrap_err().to_string(),
+                "result is missing `blockHash`"
+            );
+
+            assert!(require_fields(&json!("0x01"), RECEIPT_FIELDS).is_err());
+            assert!(require_array_of(&json!([receipt]), RECEIPT_FIELDS).is_ok());
+            assert!(require_array_of(&json!([{}]), RECEIPT_FIELDS).is_err());
+        }
+
+        #[test]
+        fn test_require_hex() {
+            assert!(require_hex(&json!("0x")).is_ok());
+            assert!(require_hex(&json!("0x1aF")).is_ok());
+            assert!(require_hex(&json!("1a")).is_err());
+            assert!(require_hex(&json!("0xzz")).is_err());
+            assert!(require_hex(&json!(1)).is_err());
+        }
+    }
+
+    mod test_shorten_params_key {
+        use super::super::*;
+
+        #[test]
+        fn test_shorten_params_key() {
+            assert_eq!(shorten_params_key("0x10-abc".to_string(), 8), "0x10-abc");
+
+            let rest = "ab".repeat(30);
+            let key = shorten_params_key(format!("0x10-{rest}"), 46);
+            assert_eq!(key, format!("0x10-#{}", hash_params_key(&rest)));
+
+            let key = shorten_params_key(format!("0x10-{rest}"), 45);
+            assert_eq!(
+                key,
+                format!("#{}", hash_params_key(&format!("0x10-{rest}")))
+            );
+
+            let long_key = format!("0x10-{}", "ab".repeat(100));
+            for max_len in [41, 45, 46, 64] {
+                let key = shorten_params_key(long_key.clone(), max_len);
+                assert!(key.len() <= max_len);
+            }
+            assert_eq!(
+                shorten_params_key(long_key, 41).len(),
+                HASHED_PARAMS_KEY_LEN
+            );
+        }
+    }
 }
@@ -33,6 +33,10 @@ impl RpcCacheHandler for Handler {
             Ok(Some(block_number))
         }
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 0)
+    }
 }
 
 #[cfg(test)]
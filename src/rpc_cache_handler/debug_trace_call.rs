@@ -41,6 +41,10 @@ impl RpcCacheHandler for Handler {
             Ok(Some(format!("{block_tag}-{tx_hash}")))
         }
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 1)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,245 @@
+//! Handlers defined in the config file, covering simple methods without writing Rust:
+//!
+//! ```yaml
+//! handlers:
+//!   - method: companyx_getInternalTxs
+//!     key: ["$[0]", "$[1].includeFailed"]
+//!     cacheable_when:
+//!       - concrete_block: 0
+//!     ttl_secs: 3600
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler, RpcCacheHandlerFactory};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DeclarativeHandlerConfig {
+    pub method: String,
+
+    /// Paths into the params making up the cache key. Parts missing from a request count as `null`.
+    pub key: Vec<JsonPath>,
+
+    /// Conditions the params must all meet for the request to be cacheable.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub cacheable_when: Vec<CachePredicate>,
+
+    /// Seconds results stay cached, forever if unset.
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum CachePredicate {
+    /// The param at this index is a block number or hash rather than a tag like `latest`.
+    ConcreteBlock(usize),
+
+    /// The value at this path is present and not `null`.
+    Present(JsonPath),
+}
+
+/// Subset of JSONPath addressing a single value, e.g. `$[1].address` or `$[0]['from']`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Index(usize),
+    Field(String),
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = anyhow::Error;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        let mut rest = path
+            .strip_prefix('$')
+            .context("json path must start with `$`")?;
+        let mut segments = vec![];
+
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                if end == 0 {
+                    bail!("empty field name in json path `{path}`");
+                }
+
+                segments.push(PathSegment::Field(after_dot[..end].to_string()));
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = after_bracket
+                    .find(']')
+                    .with_context(|| format!("unclosed `[` in json path `{path}`"))?;
+                let inner = &after_bracket[..end];
+
+                let segment = match inner
+                    .strip_prefix('\'')
+                    .and_then(|inner| inner.strip_suffix('\''))
+                {
+                    Some(field) => PathSegment::Field(field.to_string()),
+                    None => PathSegment::Index(
+                        inner
+                            .parse()
+                            .with_context(|| format!("invalid index in json path `{path}`"))?,
+                    ),
+                };
+
+                segments.push(segment);
+                rest = &after_bracket[end + 1..];
+            } else {
+                bail!("unexpected `{rest}` in json path `{path}`");
+            }
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl JsonPath {
+    fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                PathSegment::Index(index) => value.get(index),
+                PathSegment::Field(field) => value.get(field),
+            })
+    }
+}
+
+pub fn new_factory(config: &DeclarativeHandlerConfig) -> RpcCacheHandlerFactory {
+    let handler = Handler {
+        // Handlers report their method for the lifetime of the process.
+        method: Box::leak(config.method.clone().into_boxed_str()),
+        key: config.key.clone(),
+        cacheable_when: config.cacheable_when.clone(),
+        ttl: config.ttl_secs.map(Duration::from_secs),
+    };
+
+    Arc::new(move || Box::new(handler.clone()))
+}
+
+#[derive(Clone)]
+struct Handler {
+    method: &'static str,
+    key: Vec<JsonPath>,
+    cacheable_when: Vec<CachePredicate>,
+    ttl: Option<Duration>,
+}
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        self.method
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        for predicate in &self.cacheable_when {
+            let holds = match predicate {
+                CachePredicate::ConcreteBlock(index) => {
+                    let block_tag = params.get(index).context("block param missing")?;
+                    common::extract_and_format_block_tag(block_tag)
+                        .with_context(|| format!("params[{index}] not a valid block tag"))?
+                        .is_some()
+                }
+                CachePredicate::Present(path) => path.select(params).is_some_and(|v| !v.is_null()),
+            };
+
+            if !holds {
+                return Ok(None);
+            }
+        }
+
+        let parts = self
+            .key
+            .iter()
+            .map(|path| path.select(params).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        Ok(Some(common::hash_string(&serde_json::to_string(&parts)?)))
+    }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        let block_param = self
+            .cacheable_when
+            .iter()
+            .find_map(|predicate| match predicate {
+                CachePredicate::ConcreteBlock(index) => Some(*index),
+                CachePredicate::Present(_) => None,
+            });
+
+        match block_param {
+            Some(index) => common::extract_referenced_block(params, index),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn handler() -> Box<dyn RpcCacheHandler> {
+        let config: DeclarativeHandlerConfig = serde_yaml::from_str(
+            r#"
+method: companyx_getInternalTxs
+key: ["$[0]", "$[1].includeFailed"]
+cacheable_when:
+  - concrete_block: 0
+ttl_secs: 60
+"#,
+        )
+        .unwrap();
+
+        new_factory(&config)()
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        let path = JsonPath::try_from("$[1].address['from']".to_string()).unwrap();
+        assert_eq!(
+            path.select(&json!([0, {"address": {"from": "0xab"}}])),
+            Some(&json!("0xab"))
+        );
+
+        assert!(JsonPath::try_from("[0]".to_string()).is_err());
+        assert!(JsonPath::try_from("$[x]".to_string()).is_err());
+        assert!(JsonPath::try_from("$.".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_concrete_block() {
+        let handler = handler();
+
+        let key = handler
+            .extract_cache_key(&json!(["0x10", {"includeFailed": true}]))
+            .unwrap();
+        assert!(key.is_some());
+        assert_ne!(
+            key,
+            handler
+                .extract_cache_key(&json!(["0x10", {"includeFailed": false}]))
+                .unwrap()
+        );
+
+        assert_eq!(handler.extract_cache_key(&json!(["latest"])).unwrap(), None);
+        assert_eq!(
+            handler.referenced_block(&json!(["0x10"])).unwrap(),
+            Some(16)
+        );
+        assert_eq!(handler.cache_ttl(), Some(Duration::from_secs(60)));
+    }
+}
@@ -11,6 +11,10 @@ impl RpcCacheHandler for Handler {
         "eth_call"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::AtLeast(1))?;
 
@@ -41,6 +45,14 @@ impl RpcCacheHandler for Handler {
 
         Ok(Some(format!("{block_tag}-{tx_hash}")))
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 1)
+    }
+
+    fn is_permanent_error(&self, _params: &Value, error: &Value) -> bool {
+        common::is_invalid_params_error(error) || common::is_execution_reverted_error(error)
+    }
 }
 
 #[cfg(test)]
@@ -68,6 +80,20 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_permanent_error() {
+        let params = json!([{}, "0x1"]);
+
+        assert!(HANDLER.is_permanent_error(
+            &params,
+            &json!({"code": 3, "message": "execution reverted: ds-math-sub-underflow"})
+        ));
+        assert!(!HANDLER.is_permanent_error(
+            &params,
+            &json!({"code": -32000, "message": "header not found"})
+        ));
+    }
+
     #[test]
     fn test_invalid_tx() {
         let params = json!(["0xgg"]);
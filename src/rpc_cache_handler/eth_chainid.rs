@@ -1,6 +1,6 @@
 use serde_json::Value;
 
-use crate::rpc_cache_handler::RpcCacheHandler;
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
 
 #[derive(Default, Clone)]
 pub struct Handler;
@@ -10,6 +10,10 @@ impl RpcCacheHandler for Handler {
         "eth_chainId"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, _: &Value) -> anyhow::Result<Option<String>> {
         Ok(Some("static".to_string()))
     }
@@ -10,7 +10,47 @@ impl RpcCacheHandler for Handler {
         "eth_getBalance"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         common::extract_address_cache_key(params)
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler;
+
+    #[test]
+    fn test_address_normalized() {
+        let checksummed = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0", "0x1234"]);
+        let lowercase = json!(["0xc310e760778ecbca4c65b6c559874757a4c4ece0", "0x1234"]);
+
+        let cache_key = HANDLER.extract_cache_key(&checksummed).unwrap().unwrap();
+        assert_eq!(
+            cache_key,
+            "0x1234-0xc310e760778ecbca4c65b6c559874757a4c4ece0"
+        );
+        assert_eq!(
+            HANDLER.extract_cache_key(&lowercase).unwrap(),
+            Some(cache_key)
+        );
+        assert_eq!(HANDLER.referenced_block(&lowercase).unwrap(), Some(0x1234));
+    }
+
+    #[test]
+    fn test_block_tag_not_cached() {
+        let params = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0", "latest"]);
+        assert_eq!(HANDLER.extract_cache_key(&params).unwrap(), None);
+        assert_eq!(HANDLER.referenced_block(&params).unwrap(), None);
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Context;
 use serde_json::Value;
 
-use crate::rpc_cache_handler::{common, RpcCacheHandler};
+use crate::rpc_cache_handler::{common, RpcCacheHandler, SecondaryCacheEntry};
 
 #[derive(Default, Clone)]
 pub struct Handler;
@@ -11,6 +11,11 @@ impl RpcCacheHandler for Handler {
         "eth_getBlockByHash"
     }
 
+    fn validate_result(&self, params: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::BLOCK_FIELDS)?;
+        common::require_requested_hash(params, result, "hash")
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::AtLeast(1))?;
 
@@ -24,6 +29,20 @@ impl RpcCacheHandler for Handler {
             Ok(Some(block_hash))
         }
     }
+
+    fn extract_secondary_cache_entries(
+        &self,
+        _params: &Value,
+        result: &Value,
+    ) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+        let mut entries = common::extract_block_transaction_entries(result)?;
+        // The block may still be reorged out, so its number may come to refer to another block.
+        entries.extend(common::extract_block_transaction_index_entries(
+            result, false,
+        )?);
+
+        Ok(entries)
+    }
 }
 
 #[cfg(test)]
@@ -83,4 +102,26 @@ mod test {
             "params[1] not a bool"
         );
     }
+
+    #[test]
+    fn test_validate_result() {
+        let params = json!([
+            "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            false
+        ]);
+        let mut block = json!({
+            "hash": "0x1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF",
+            "number": "0x10",
+            "parentHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "transactions": [],
+        });
+        assert!(HANDLER.validate_result(&params, &block).is_ok());
+
+        block["hash"] = json!("0x0000000000000000000000000000000000000000000000000000000000000001");
+        assert!(HANDLER
+            .validate_result(&params, &block)
+            .unwrap_err()
+            .to_string()
+            .contains("doesn't match the requested hash"));
+    }
 }
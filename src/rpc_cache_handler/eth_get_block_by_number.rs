@@ -1,7 +1,9 @@
 use anyhow::Context;
 use serde_json::Value;
 
-use crate::rpc_cache_handler::{common, RpcCacheHandler};
+use crate::rpc_cache_handler::{
+    common, eth_get_block_by_hash, RpcCacheHandler, SecondaryCacheEntry,
+};
 
 #[derive(Default, Clone)]
 pub struct Handler;
@@ -11,6 +13,10 @@ impl RpcCacheHandler for Handler {
         "eth_getBlockByNumber"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::BLOCK_FIELDS)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::AtLeast(1))?;
 
@@ -28,6 +34,50 @@ impl RpcCacheHandler for Handler {
             Ok(Some(block_tag))
         }
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 0)
+    }
+
+    fn resolve_block_tag(&self, params: &Value, latest_block: u64) -> Option<Value> {
+        if params.get(0)?.as_str()? != "latest" {
+            return None;
+        }
+
+        let mut params = params.clone();
+        params[0] = Value::String(format!("0x{latest_block:x}"));
+
+        Some(params)
+    }
+
+    fn extract_secondary_cache_entries(
+        &self,
+        params: &Value,
+        result: &Value,
+    ) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+        if result["hash"].is_null() {
+            return Ok(vec![]);
+        }
+
+        // The by-hash request only differs in the first param, so its handler derives a consistent key.
+        let mut by_hash_params = params.clone();
+        by_hash_params[0] = result["hash"].clone();
+
+        let mut entries = common::extract_block_transaction_entries(result)?;
+        // Results by number are only cached once confirmed, so that the number keeps referring to this block.
+        entries.extend(common::extract_block_transaction_index_entries(
+            result, true,
+        )?);
+
+        let by_hash_entry = common::extract_secondary_cache_entry(
+            &eth_get_block_by_hash::Handler,
+            &by_hash_params,
+            result,
+        )?;
+        entries.extend(by_hash_entry);
+
+        Ok(entries)
+    }
 }
 
 #[cfg(test)]
@@ -77,4 +127,41 @@ mod test {
             "params[1] not a bool"
         );
     }
+
+    #[test]
+    fn test_resolve_block_tag() {
+        let params = json!(["latest", false]);
+        assert_eq!(
+            HANDLER.resolve_block_tag(&params, 0x1234),
+            Some(json!(["0x1234", false]))
+        );
+
+        let params = json!(["pending", false]);
+        assert_eq!(HANDLER.resolve_block_tag(&params, 0x1234), None);
+    }
+
+    #[test]
+    fn test_secondary_cache_entries() {
+        let params = json!(["0x1234", false]);
+        let result = json!({
+            "number": "0x1234",
+            "hash": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            "parentHash": "0xabcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890",
+            "transactions": [],
+        });
+
+        let entries = HANDLER
+            .extract_secondary_cache_entries(&params, &result)
+            .unwrap();
+        assert_eq!(
+            entries,
+            vec![SecondaryCacheEntry {
+                method: "eth_getBlockByHash",
+                params_key:
+                    "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef-false"
+                        .to_string(),
+                value: serde_json::to_string(&result).unwrap(),
+            }]
+        );
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Context;
 use serde_json::Value;
 
-use crate::rpc_cache_handler::{common, RpcCacheHandler};
+use crate::rpc_cache_handler::{common, RpcCacheHandler, SecondaryCacheEntry};
 
 #[derive(Default, Clone)]
 pub struct Handler;
@@ -11,6 +11,10 @@ impl RpcCacheHandler for Handler {
         "eth_getBlockReceipts"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_array_of(result, common::RECEIPT_FIELDS)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::AtLeast(1))?;
 
@@ -23,4 +27,51 @@ impl RpcCacheHandler for Handler {
 
         Ok(Some(block_tag))
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 0)
+    }
+
+    fn extract_secondary_cache_entries(
+        &self,
+        _params: &Value,
+        result: &Value,
+    ) -> anyhow::Result<Vec<SecondaryCacheEntry>> {
+        common::extract_block_receipt_entries(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler;
+
+    #[test]
+    fn test_secondary_cache_entries() {
+        let receipt = json!({
+            "transactionHash": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            "blockHash": "0xabcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890",
+            "blockNumber": "0x1234",
+            "logs": [],
+        });
+        let pending_receipt = json!({
+            "transactionHash": "0xabcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890",
+            "blockHash": null,
+        });
+
+        let entries = HANDLER
+            .extract_secondary_cache_entries(&json!(["0x1234"]), &json!([receipt, pending_receipt]))
+            .unwrap();
+        assert_eq!(
+            entries,
+            vec![SecondaryCacheEntry {
+                method: "eth_getTransactionReceipt",
+                params_key: "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+                    .to_string(),
+                value: serde_json::to_string(&receipt).unwrap(),
+            }]
+        );
+    }
 }
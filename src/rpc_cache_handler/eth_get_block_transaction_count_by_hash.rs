@@ -0,0 +1,53 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler;
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_getBlockTransactionCountByHash"
+    }
+
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        let params = common::require_array_params(params, common::ParamsSpec::Exact(1))?;
+
+        let block_hash = common::extract_and_format_block_hash(&params[0])
+            .context("params[0] not a valid block hash")?;
+
+        Ok(Some(block_hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler;
+
+    #[test]
+    fn test_normal_case() {
+        let params = json!(["0x1234567890ABCDEF1234567890abcdef1234567890abcdef1234567890abcdef"]);
+        let cache_key = HANDLER.extract_cache_key(&params).unwrap().unwrap();
+        assert_eq!(
+            cache_key,
+            "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+        );
+    }
+
+    #[test]
+    fn test_invalid_block_hash() {
+        let params = json!(["0x1234"]);
+        assert_eq!(
+            HANDLER.extract_cache_key(&params).unwrap_err().to_string(),
+            "params[0] not a valid block hash"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler;
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_getBlockTransactionCountByNumber"
+    }
+
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        let params = common::require_array_params(params, common::ParamsSpec::Exact(1))?;
+
+        common::extract_and_format_block_number(&params[0])
+            .context("params[0] not a valid block number")
+    }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler;
+
+    #[test]
+    fn test_normal_case() {
+        let params = json!(["0x012345"]);
+        let cache_key = HANDLER.extract_cache_key(&params).unwrap().unwrap();
+        assert_eq!(cache_key, "0x12345");
+        assert_eq!(HANDLER.referenced_block(&params).unwrap(), Some(0x12345));
+    }
+
+    #[test]
+    fn test_block_tags_not_cached() {
+        for block_tag in ["latest", "pending", "safe", "finalized"] {
+            let params = json!([block_tag]);
+            assert_eq!(HANDLER.extract_cache_key(&params).unwrap(), None);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use serde_json::Value;
 
-use crate::rpc_cache_handler::RpcCacheHandler;
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
 
 #[derive(Default, Clone)]
 pub struct Handler {
@@ -12,7 +12,19 @@ impl RpcCacheHandler for Handler {
         "eth_getCode"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         self.inner.extract_cache_key(params)
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        self.inner.referenced_block(params)
+    }
+
+    fn requires_finality(&self) -> bool {
+        true
+    }
 }
@@ -14,6 +14,10 @@ impl RpcCacheHandler for Handler {
         "eth_getLogs"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_array_of(result, common::LOG_FIELDS)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = &require_array_params(params, common::ParamsSpec::Exact(1))?;
         let filter = &params[0];
@@ -60,6 +64,26 @@ impl RpcCacheHandler for Handler {
 
         Ok(cache_key)
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        let params = require_array_params(params, common::ParamsSpec::Exact(1))?;
+
+        // The start of the range is the oldest block the filter needs.
+        match &params[0]["fromBlock"] {
+            Value::Null => Ok(None),
+            from_block => common::parse_block_number(from_block),
+        }
+    }
+
+    fn newest_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        let params = require_array_params(params, common::ParamsSpec::Exact(1))?;
+
+        // Filters ending at a block tag have no cache key, so they're never cached anyway.
+        match &params[0]["toBlock"] {
+            Value::Null => Ok(None),
+            to_block => common::parse_block_number(to_block),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -178,4 +202,25 @@ mod test {
         let err = HANDLER.extract_cache_key(&params).unwrap_err();
         assert_eq!(err.to_string(), "expect a valid block hash");
     }
+
+    #[test]
+    fn test_referenced_block() {
+        let params = json!([{ "fromBlock": "0x429d3b", "toBlock": "latest" }]);
+        assert_eq!(HANDLER.referenced_block(&params).unwrap(), Some(0x429d3b));
+
+        let params = json!([{ "blockHash": "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef" }]);
+        assert_eq!(HANDLER.referenced_block(&params).unwrap(), None);
+    }
+
+    #[test]
+    fn test_newest_block() {
+        // Logs up to the head may still be reorged, however old the start of the range is.
+        let params = json!([{ "fromBlock": "0x1", "toBlock": "0x429d3c" }]);
+        assert_eq!(HANDLER.referenced_block(&params).unwrap(), Some(0x1));
+        assert_eq!(HANDLER.newest_block(&params).unwrap(), Some(0x429d3c));
+
+        let params = json!([{ "fromBlock": "0x1", "toBlock": "latest" }]);
+        assert_eq!(HANDLER.newest_block(&params).unwrap(), None);
+        assert_eq!(HANDLER.extract_cache_key(&params).unwrap(), None);
+    }
 }
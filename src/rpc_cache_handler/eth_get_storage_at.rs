@@ -12,11 +12,19 @@ impl RpcCacheHandler for Handler {
         "eth_getStorageAt"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::AtLeast(2))?;
 
-        let block_tag = common::extract_and_format_block_tag(&params[2])
-            .context("params[2] is not a valid block tag")?;
+        // An omitted block tag means `latest`.
+        let block_tag = match params.get(2) {
+            Some(block_tag) => common::extract_and_format_block_tag(block_tag)
+                .context("params[2] is not a valid block tag")?,
+            None => None,
+        };
         let block_tag = match block_tag {
             Some(block_tag) => block_tag,
             None => return Ok(None),
@@ -44,6 +52,14 @@ impl RpcCacheHandler for Handler {
 
         Ok(Some(format!("{block_tag}-{lowercase_address}-{slot}")))
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 2)
+    }
+
+    fn requires_finality(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
@@ -91,6 +107,10 @@ mod test {
         let params = json!(["0x12341324", "0x1234", "earliest"]);
         let cache_key = HANDLER.extract_cache_key(&params).unwrap();
         assert_eq!(cache_key, None);
+
+        let params = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0", "0x1234"]);
+        let cache_key = HANDLER.extract_cache_key(&params).unwrap();
+        assert_eq!(cache_key, None);
     }
 
     #[test]
@@ -12,6 +12,10 @@ impl RpcCacheHandler for Handler {
         "eth_getTransactionByBlockHashAndIndex"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::TRANSACTION_FIELDS)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::Exact(2))?;
 
@@ -12,6 +12,10 @@ impl RpcCacheHandler for Handler {
         "eth_getTransactionByBlockNumberAndIndex"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::TRANSACTION_FIELDS)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::Exact(2))?;
 
@@ -31,6 +35,10 @@ impl RpcCacheHandler for Handler {
     fn extract_cache_value(&self, result: &Value) -> anyhow::Result<(bool, String)> {
         common::extract_transaction_cache_value(result)
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        common::extract_referenced_block(params, 0)
+    }
 }
 
 #[cfg(test)]
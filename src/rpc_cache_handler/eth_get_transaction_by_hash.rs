@@ -12,6 +12,11 @@ impl RpcCacheHandler for Handler {
         "eth_getTransactionByHash"
     }
 
+    fn validate_result(&self, params: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::TRANSACTION_FIELDS)?;
+        common::require_requested_hash(params, result, "hash")
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         self.inner.extract_cache_key(params)
     }
@@ -19,4 +24,31 @@ impl RpcCacheHandler for Handler {
     fn extract_cache_value(&self, result: &Value) -> anyhow::Result<(bool, String)> {
         common::extract_transaction_cache_value(result)
     }
+
+    fn is_pending(&self, result: &Value) -> bool {
+        result.is_object() && result["blockHash"].is_null()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler {
+        inner: super::super::eth_get_transaction_receipt::Handler,
+    };
+
+    #[test]
+    fn test_pending() {
+        let pending = json!({ "hash": "0x01", "blockHash": null, "from": "0x02" });
+        assert!(HANDLER.is_pending(&pending));
+        assert!(!HANDLER.extract_cache_value(&pending).unwrap().0);
+
+        let mined = json!({ "hash": "0x01", "blockHash": "0x03", "from": "0x02" });
+        assert!(!HANDLER.is_pending(&mined));
+        assert!(HANDLER.extract_cache_value(&mined).unwrap().0);
+
+        assert!(!HANDLER.is_pending(&Value::Null));
+    }
 }
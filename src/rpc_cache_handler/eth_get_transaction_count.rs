@@ -1,6 +1,6 @@
 use serde_json::Value;
 
-use crate::rpc_cache_handler::RpcCacheHandler;
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
 
 #[derive(Default, Clone)]
 pub struct Handler {
@@ -12,7 +12,47 @@ impl RpcCacheHandler for Handler {
         "eth_getTransactionCount"
     }
 
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         self.inner.extract_cache_key(params)
     }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        self.inner.referenced_block(params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_concrete_block() {
+        let handler = Handler::default();
+
+        let params = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0", "0x1234"]);
+        let cache_key = handler.extract_cache_key(&params).unwrap().unwrap();
+        assert_eq!(
+            cache_key,
+            "0x1234-0xc310e760778ecbca4c65b6c559874757a4c4ece0"
+        );
+        assert_eq!(handler.referenced_block(&params).unwrap(), Some(0x1234));
+    }
+
+    #[test]
+    fn test_block_tags_not_cached() {
+        let handler = Handler::default();
+
+        for block_tag in ["latest", "pending", "safe", "finalized"] {
+            let params = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0", block_tag]);
+            assert_eq!(handler.extract_cache_key(&params).unwrap(), None);
+        }
+
+        let params = json!(["0xC310e760778ECBca4C65B6C559874757A4c4Ece0"]);
+        assert_eq!(handler.extract_cache_key(&params).unwrap(), None);
+    }
 }
@@ -12,6 +12,11 @@ impl RpcCacheHandler for Handler {
         "eth_getTransactionReceipt"
     }
 
+    fn validate_result(&self, params: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::RECEIPT_FIELDS)?;
+        common::require_requested_hash(params, result, "transactionHash")
+    }
+
     fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
         let params = common::require_array_params(params, common::ParamsSpec::Exact(1))?;
         let tx_hash: B256 = serde_json::from_value(params[0].clone())
@@ -0,0 +1,22 @@
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler {
+    inner: super::eth_get_transaction_by_block_hash_and_index::Handler,
+}
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_getUncleByBlockHashAndIndex"
+    }
+
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::HEADER_FIELDS)
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        self.inner.extract_cache_key(params)
+    }
+}
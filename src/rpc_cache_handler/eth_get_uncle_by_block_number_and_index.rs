@@ -0,0 +1,45 @@
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler {
+    inner: super::eth_get_transaction_by_block_number_and_index::Handler,
+}
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_getUncleByBlockNumberAndIndex"
+    }
+
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_fields(result, common::HEADER_FIELDS)
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        self.inner.extract_cache_key(params)
+    }
+
+    fn referenced_block(&self, params: &Value) -> anyhow::Result<Option<u64>> {
+        self.inner.referenced_block(params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normal_case() {
+        let handler = Handler::default();
+
+        let params = json!(["0x12345", "0x1"]);
+        let cache_key = handler.extract_cache_key(&params).unwrap().unwrap();
+        assert_eq!(cache_key, "0x12345-1");
+        assert_eq!(handler.referenced_block(&params).unwrap(), Some(0x12345));
+
+        let params = json!(["latest", "0x1"]);
+        assert_eq!(handler.extract_cache_key(&params).unwrap(), None);
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler;
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_getUncleCountByBlockHash"
+    }
+
+    fn validate_result(&self, _: &Value, result: &Value) -> anyhow::Result<()> {
+        common::require_hex(result)
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        let params = common::require_array_params(params, common::ParamsSpec::Exact(1))?;
+
+        let block_hash = common::extract_and_format_block_hash(&params[0])
+            .context("params[0] not a valid block hash")?;
+
+        Ok(Some(block_hash))
+    }
+}
@@ -0,0 +1,23 @@
+use serde_json::Value;
+
+use crate::rpc_cache_handler::RpcCacheHandler;
+
+/// `eth/68`, the wire protocol spoken by current execution clients.
+const PROTOCOL_VERSION: &str = "0x44";
+
+#[derive(Default, Clone)]
+pub struct Handler;
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "eth_protocolVersion"
+    }
+
+    fn extract_cache_key(&self, _: &Value) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn local_result(&self, _: &Value) -> Option<anyhow::Result<Value>> {
+        Some(Ok(Value::String(PROTOCOL_VERSION.to_string())))
+    }
+}
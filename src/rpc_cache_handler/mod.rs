@@ -1,11 +1,15 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Result;
 use serde_json::Value;
 
-mod common;
+pub mod common;
 mod debug_trace_block_by_hash;
 mod debug_trace_block_by_number;
 mod debug_trace_call;
 mod debug_trace_transaction;
+pub mod declarative;
 mod eth_call;
 mod eth_chainid;
 mod eth_estimate_gas;
@@ -13,6 +17,8 @@ mod eth_get_balance;
 mod eth_get_block_by_hash;
 mod eth_get_block_by_number;
 mod eth_get_block_receipts;
+mod eth_get_block_transaction_count_by_hash;
+mod eth_get_block_transaction_count_by_number;
 mod eth_get_code;
 mod eth_get_logs;
 mod eth_get_storage_at;
@@ -21,27 +27,107 @@ mod eth_get_transaction_by_block_number_and_index;
 mod eth_get_transaction_by_hash;
 mod eth_get_transaction_count;
 mod eth_get_transaction_receipt;
+mod eth_get_uncle_by_block_hash_and_index;
+mod eth_get_uncle_by_block_number_and_index;
+mod eth_get_uncle_count_by_block_hash;
+mod eth_get_uncle_count_by_block_number;
+mod eth_protocol_version;
+mod plugins;
+#[cfg(test)]
+pub mod testing;
+pub mod wasm;
+mod web3_sha3;
 
 pub trait RpcCacheHandler: Send + Sync {
     fn method_name(&self) -> &'static str;
 
     fn extract_cache_key(&self, params: &Value) -> Result<Option<String>>;
 
+    /// Result computed by the proxy itself, for pure methods that never need the cache or an upstream.
+    /// `None` for methods answered by upstreams.
+    fn local_result(&self, _params: &Value) -> Option<Result<Value>> {
+        None
+    }
+
+    /// Block number pinned by the request, `None` if it refers to a block tag, a block hash or no block at all.
+    fn referenced_block(&self, _params: &Value) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Newest block number the result depends on, which has to be confirmed before the result is cached. The
+    /// referenced block for requests about a single block.
+    fn newest_block(&self, params: &Value) -> Result<Option<u64>> {
+        self.referenced_block(params)
+    }
+
+    /// Whether results may only be cached once their block is finalized, e.g. account state that a reorg
+    /// would change. Falls back to the confirmation depth on chains not reporting a finalized block.
+    fn requires_finality(&self) -> bool {
+        false
+    }
+
+    /// Checks that a non-null result has the shape of the method's results, e.g. that a receipt has a
+    /// `blockHash`, and that it's the object requested by `params`. Invalid results are forwarded but never
+    /// cached, so a faulty upstream can't poison the cache.
+    fn validate_result(&self, _params: &Value, _result: &Value) -> Result<()> {
+        Ok(())
+    }
+
     fn extract_cache_value(&self, result: &Value) -> Result<(bool, String)> {
         Ok((!result.is_null(), serde_json::to_string(result)?))
     }
+
+    /// Whether `error` is the final answer to a request with `params`, so that it may be cached like a
+    /// result. Only invalid params count by default since most errors are transient.
+    fn is_permanent_error(&self, _params: &Value, error: &Value) -> bool {
+        common::is_invalid_params_error(error)
+    }
+
+    /// Whether `result` describes something not final yet, e.g. a transaction not mined yet. Pending results
+    /// are only cached for a short while if at all, even for methods whose results never change otherwise.
+    fn is_pending(&self, _result: &Value) -> bool {
+        false
+    }
+
+    /// How long results stay cached, `None` for results that never change.
+    fn cache_ttl(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Params with block tags resolved against the latest block, so that e.g. a `latest` request shares
+    /// the cache entry of the equivalent numbered request. `None` keeps the params unchanged.
+    fn resolve_block_tag(&self, _params: &Value, _latest_block: u64) -> Option<Value> {
+        None
+    }
+
+    /// Entries of other methods that can be derived from a cacheable result, e.g. the by-hash entry of a
+    /// block fetched by number.
+    fn extract_secondary_cache_entries(
+        &self,
+        _params: &Value,
+        _result: &Value,
+    ) -> Result<Vec<SecondaryCacheEntry>> {
+        Ok(vec![])
+    }
 }
 
-pub type RpcCacheHandlerFactory = fn() -> Box<dyn RpcCacheHandler>;
+#[derive(Debug, PartialEq)]
+pub struct SecondaryCacheEntry {
+    pub method: &'static str,
+    pub params_key: String,
+    pub value: String,
+}
+
+pub type RpcCacheHandlerFactory = Arc<dyn Fn() -> Box<dyn RpcCacheHandler> + Send + Sync>;
 
-pub fn get_factory<T>() -> fn() -> Box<dyn RpcCacheHandler>
+pub fn get_factory<T>() -> RpcCacheHandlerFactory
 where
     T: Default + RpcCacheHandler + 'static,
 {
-    || Box::<T>::default()
+    Arc::new(|| Box::<T>::default())
 }
 
-pub fn factories() -> Vec<RpcCacheHandlerFactory> {
+fn builtin_factories() -> Vec<RpcCacheHandlerFactory> {
     vec![
         get_factory::<debug_trace_block_by_hash::Handler>(),
         get_factory::<debug_trace_block_by_number::Handler>(),
@@ -54,6 +140,8 @@ pub fn factories() -> Vec<RpcCacheHandlerFactory> {
         get_factory::<eth_get_block_by_hash::Handler>(),
         get_factory::<eth_get_block_by_number::Handler>(),
         get_factory::<eth_get_block_receipts::Handler>(),
+        get_factory::<eth_get_block_transaction_count_by_hash::Handler>(),
+        get_factory::<eth_get_block_transaction_count_by_number::Handler>(),
         get_factory::<eth_get_code::Handler>(),
         get_factory::<eth_get_logs::Handler>(),
         get_factory::<eth_get_storage_at::Handler>(),
@@ -62,5 +150,38 @@ pub fn factories() -> Vec<RpcCacheHandlerFactory> {
         get_factory::<eth_get_transaction_by_hash::Handler>(),
         get_factory::<eth_get_transaction_count::Handler>(),
         get_factory::<eth_get_transaction_receipt::Handler>(),
+        get_factory::<eth_get_uncle_by_block_hash_and_index::Handler>(),
+        get_factory::<eth_get_uncle_by_block_number_and_index::Handler>(),
+        get_factory::<eth_get_uncle_count_by_block_hash::Handler>(),
+        get_factory::<eth_get_uncle_count_by_block_number::Handler>(),
+        get_factory::<eth_protocol_version::Handler>(),
+        get_factory::<web3_sha3::Handler>(),
     ]
 }
+
+/// Handlers available to the chains. Handlers registered later replace earlier ones of the same method,
+/// so plugins may override built-in handlers.
+pub struct HandlerRegistry {
+    factories: Vec<RpcCacheHandlerFactory>,
+}
+
+impl HandlerRegistry {
+    /// Built-in handlers followed by the ones of the plugin directory.
+    pub fn load() -> Self {
+        let mut registry = Self {
+            factories: builtin_factories(),
+        };
+        plugins::register_plugins(&mut registry);
+
+        registry
+    }
+
+    pub fn register_factory(&mut self, factory: RpcCacheHandlerFactory) -> &mut Self {
+        self.factories.push(factory);
+        self
+    }
+
+    pub fn factories(&self) -> &[RpcCacheHandlerFactory] {
+        &self.factories
+    }
+}
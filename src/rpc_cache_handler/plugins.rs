@@ -0,0 +1,3 @@
+//! Handlers compiled in from the plugin directory, generated by `build.rs`.
+
+include!(concat!(env!("OUT_DIR"), "/plugins.rs"));
@@ -0,0 +1,169 @@
+//! Checks handlers against fixtures of requests and results as real nodes send them, stored as arrays of cases in
+//! `src/rpc_cache_handler/fixtures/<chain>/<method>.json`. Chains get their own fixtures where their nodes answer
+//! differently, e.g. Arbitrum receipts with L1 gas fields or Polygon state sync transactions.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::rpc_cache_handler::RpcCacheHandler;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    /// What the case covers, reported when it fails.
+    pub name: String,
+    pub params: Value,
+    #[serde(default)]
+    pub result: Value,
+    pub expect: Expectation,
+}
+
+/// How the handler must treat a case. Omitted checks are skipped.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expectation {
+    /// Params key of the request, `null` if it must not be cached.
+    #[serde(deserialize_with = "present")]
+    pub key: Option<Option<String>>,
+    /// Part of the error the params must be rejected with. Nothing else is checked then.
+    pub key_error: Option<String>,
+    #[serde(deserialize_with = "present")]
+    pub referenced_block: Option<Option<u64>>,
+    /// Result computed by the proxy for a pure method. Nothing else is checked then.
+    pub local_result: Option<Value>,
+    /// Whether `validate_result` rejects the result, e.g. a block that isn't the requested one.
+    pub invalid: bool,
+    pub cacheable: bool,
+    pub pending: bool,
+    /// Number of entries of other methods derived from the result.
+    pub secondary_entries: Option<usize>,
+}
+
+/// Tells a field set to `null` apart from an omitted one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/rpc_cache_handler/fixtures")
+}
+
+/// Cases of `method` of every chain, named `<chain>/<case>`.
+pub fn load_fixtures(method: &str) -> Vec<(String, Fixture)> {
+    let mut chains = std::fs::read_dir(fixtures_dir())
+        .expect("fail to read fixtures directory")
+        .map(|entry| entry.expect("fail to read fixtures directory").path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    chains.sort();
+
+    let mut fixtures = vec![];
+    for chain_dir in chains {
+        let path = chain_dir.join(format!("{method}.json"));
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+
+        let chain = chain_dir.file_name().unwrap().to_string_lossy().to_string();
+        let cases: Vec<Fixture> = serde_json::from_str(&content)
+            .unwrap_or_else(|err| panic!("fail to parse {}: {err}", path.display()));
+
+        fixtures.extend(
+            cases
+                .into_iter()
+                .map(|case| (format!("{chain}/{}", case.name), case)),
+        );
+    }
+
+    fixtures
+}
+
+/// Checks `handler` against every case of its method, which must have at least one.
+pub fn assert_fixtures(handler: &dyn RpcCacheHandler) {
+    let method = handler.method_name();
+    let fixtures = load_fixtures(method);
+    assert!(!fixtures.is_empty(), "{method} has no fixtures");
+
+    for (name, fixture) in &fixtures {
+        assert_fixture(handler, &format!("{method} {name}"), fixture);
+    }
+}
+
+fn assert_fixture(handler: &dyn RpcCacheHandler, name: &str, fixture: &Fixture) {
+    let Fixture {
+        params,
+        result,
+        expect,
+        ..
+    } = fixture;
+
+    if let Some(expected) = &expect.local_result {
+        let local_result = handler.local_result(params);
+        let local_result = local_result.unwrap_or_else(|| panic!("{name}: no local result"));
+        assert_eq!(&local_result.unwrap(), expected, "{name}: local result");
+        return;
+    }
+
+    let key = handler.extract_cache_key(params);
+    if let Some(expected) = &expect.key_error {
+        let err = key.expect_err(&format!("{name}: params must be rejected"));
+        assert!(
+            format!("{err:#}").contains(expected.as_str()),
+            "{name}: unexpected error {err:#}"
+        );
+        return;
+    }
+
+    let key = key.unwrap_or_else(|err| panic!("{name}: fail to extract key: {err:#}"));
+    if let Some(expected) = &expect.key {
+        assert_eq!(&key, expected, "{name}: key");
+    }
+
+    if let Some(expected) = &expect.referenced_block {
+        let referenced_block = handler.referenced_block(params).unwrap();
+        assert_eq!(&referenced_block, expected, "{name}: referenced block");
+    }
+
+    if !result.is_null() {
+        let validation = handler.validate_result(params, result);
+        assert_eq!(
+            validation.is_err(),
+            expect.invalid,
+            "{name}: validation {validation:?}"
+        );
+    }
+
+    let (cacheable, _) = handler.extract_cache_value(result).unwrap();
+    assert_eq!(cacheable, expect.cacheable, "{name}: cacheable");
+    assert_eq!(
+        handler.is_pending(result),
+        expect.pending,
+        "{name}: pending"
+    );
+
+    if let Some(expected) = expect.secondary_entries {
+        let entries = handler
+            .extract_secondary_cache_entries(params, result)
+            .unwrap();
+        assert_eq!(entries.len(), expected, "{name}: secondary entries");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc_cache_handler::builtin_factories;
+
+    #[test]
+    fn test_builtin_handlers() {
+        for factory in builtin_factories() {
+            assert_fixtures(factory().as_ref());
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Handlers backed by WASM modules, so that operators can cache exotic methods without rebuilding.
+//!
+//! A module exports its `memory` and the following functions. Inputs and outputs are UTF-8 JSON in the
+//! module's memory, outputs are returned as `(ptr << 32) | len`.
+//!
+//! * `alloc(len: i32) -> i32` reserves `len` bytes for an input. The host never frees them, so modules
+//!   usually hand out the same buffer on every call.
+//! * `extract_cache_key(ptr: i32, len: i32) -> i64` takes the request params and returns `{"ok": key}`,
+//!   where a `null` key means the request isn't cacheable, or `{"error": message}`.
+//! * `extract_cache_value(ptr: i32, len: i32) -> i64` (optional) takes the result and returns
+//!   `{"ok": value}`, where a `null` value means the result isn't cacheable, or `{"error": message}`.
+//!   Results are cached as is if the module doesn't export it.
+//!
+//! Modules may be built for `wasm32-wasi`, WASI imports are provided without access to the host.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use serde_json::Value;
+use wasmtime::{Engine, Linker, Memory, Module, Store, TypedFunc};
+use wasmtime_wasi::sync::WasiCtxBuilder;
+use wasmtime_wasi::WasiCtx;
+
+use crate::rpc_cache_handler::{RpcCacheHandler, RpcCacheHandlerFactory};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WasmHandlerConfig {
+    /// Method served by the module, e.g. `companyx_getInternalTxs`.
+    pub method: String,
+
+    /// Path of the `.wasm` module.
+    pub module: PathBuf,
+}
+
+pub fn load_factory(config: &WasmHandlerConfig) -> anyhow::Result<RpcCacheHandlerFactory> {
+    let engine = Engine::default();
+    let module = Module::from_file(&engine, &config.module)
+        .with_context(|| format!("fail to load wasm module {}", config.module.display()))?;
+
+    // Handlers report their method for the lifetime of the process.
+    let method: &'static str = Box::leak(config.method.clone().into_boxed_str());
+
+    new_factory(method, module)
+}
+
+fn new_factory(method: &'static str, module: Module) -> anyhow::Result<RpcCacheHandlerFactory> {
+    let mut linker = Linker::new(module.engine());
+    wasmtime_wasi::sync::add_to_linker(&mut linker, |ctx| ctx)?;
+
+    // Instantiate once upfront so that broken modules fail on startup instead of in the factory.
+    Handler::instantiate(method, &linker, &module)
+        .with_context(|| format!("fail to instantiate wasm handler for {method}"))?;
+
+    Ok(Arc::new(move || {
+        // It's safe to unwrap here because the same module has been instantiated above.
+        Box::new(Handler::instantiate(method, &linker, &module).unwrap())
+    }))
+}
+
+struct Handler {
+    method: &'static str,
+    // Calls into a module instance can't run concurrently.
+    instance: Mutex<Instance>,
+}
+
+struct Instance {
+    store: Store<WasiCtx>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    extract_cache_key: TypedFunc<(i32, i32), i64>,
+    extract_cache_value: Option<TypedFunc<(i32, i32), i64>>,
+}
+
+impl Handler {
+    fn instantiate(
+        method: &'static str,
+        linker: &Linker<WasiCtx>,
+        module: &Module,
+    ) -> anyhow::Result<Self> {
+        let mut store = Store::new(module.engine(), WasiCtxBuilder::new().build());
+        let instance = linker.instantiate(&mut store, module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("module does not export `memory`")?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let extract_cache_key = instance.get_typed_func(&mut store, "extract_cache_key")?;
+        let extract_cache_value = instance
+            .get_typed_func(&mut store, "extract_cache_value")
+            .ok();
+
+        Ok(Self {
+            method,
+            instance: Mutex::new(Instance {
+                store,
+                memory,
+                alloc,
+                extract_cache_key,
+                extract_cache_value,
+            }),
+        })
+    }
+}
+
+impl Instance {
+    fn call(&mut self, func: TypedFunc<(i32, i32), i64>, input: &Value) -> anyhow::Result<Value> {
+        let input = serde_json::to_vec(input)?;
+        let input_len = i32::try_from(input.len()).context("input too large")?;
+
+        let input_ptr = self.alloc.call(&mut self.store, input_len)?;
+        self.memory
+            .write(&mut self.store, input_ptr as u32 as usize, &input)?;
+
+        let packed = func.call(&mut self.store, (input_ptr, input_len))? as u64;
+        let output_ptr = (packed >> 32) as usize;
+        let output_len = (packed & 0xffff_ffff) as usize;
+
+        let output = self
+            .memory
+            .data(&self.store)
+            .get(output_ptr..output_ptr + output_len)
+            .context("output out of module memory")?;
+
+        let mut output: Value =
+            serde_json::from_slice(output).context("output is not valid json")?;
+
+        if !output["error"].is_null() {
+            bail!("{}", output["error"]);
+        }
+
+        Ok(output["ok"].take())
+    }
+}
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        self.method
+    }
+
+    fn extract_cache_key(&self, params: &Value) -> anyhow::Result<Option<String>> {
+        let mut instance = self.instance.lock().unwrap();
+        let func = instance.extract_cache_key;
+
+        match instance.call(func, params)? {
+            Value::Null => Ok(None),
+            Value::String(key) => Ok(Some(key)),
+            key => bail!("cache key not a string: {key}"),
+        }
+    }
+
+    fn extract_cache_value(&self, result: &Value) -> anyhow::Result<(bool, String)> {
+        let mut instance = self.instance.lock().unwrap();
+
+        let Some(func) = instance.extract_cache_value else {
+            return Ok((!result.is_null(), serde_json::to_string(result)?));
+        };
+
+        match instance.call(func, result)? {
+            Value::Null => Ok((false, String::new())),
+            Value::String(value) => Ok((true, value)),
+            value => bail!("cache value not a string: {value}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract_cache_key() {
+        // Answers every request with the key `k`.
+        let wat = r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 0) "{\"ok\":\"k\"}")
+              (func (export "alloc") (param i32) (result i32) i32.const 1024)
+              (func (export "extract_cache_key") (param i32 i32) (result i64) i64.const 10))
+        "#;
+        let module = Module::new(&Engine::default(), wat).unwrap();
+        let handler = new_factory("companyx_getInternalTxs", module).unwrap()();
+
+        assert_eq!(handler.method_name(), "companyx_getInternalTxs");
+        assert_eq!(
+            handler
+                .extract_cache_key(&serde_json::json!(["0x1"]))
+                .unwrap(),
+            Some("k".to_string())
+        );
+        assert_eq!(
+            handler
+                .extract_cache_value(&serde_json::json!("0x2"))
+                .unwrap(),
+            (true, "\"0x2\"".to_string())
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use alloy_primitives::{keccak256, Bytes};
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::rpc_cache_handler::{common, RpcCacheHandler};
+
+#[derive(Default, Clone)]
+pub struct Handler;
+
+impl RpcCacheHandler for Handler {
+    fn method_name(&self) -> &'static str {
+        "web3_sha3"
+    }
+
+    fn extract_cache_key(&self, _: &Value) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn local_result(&self, params: &Value) -> Option<anyhow::Result<Value>> {
+        let result = common::require_array_params(params, common::ParamsSpec::Exact(1))
+            .and_then(|params| {
+                serde_json::from_value::<Bytes>(params[0].clone())
+                    .context("params[0] is not valid hex data")
+            })
+            .map(|data| Value::String(format!("{:#x}", keccak256(data))));
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    static HANDLER: Handler = Handler;
+
+    #[test]
+    fn test_normal_case() {
+        let params = json!(["0x68656c6c6f20776f726c64"]);
+        let result = HANDLER.local_result(&params).unwrap().unwrap();
+        assert_eq!(
+            result,
+            json!("0x47173285a8d7341e5e972fc677286384f802f8ef42a5ec5f03bbfa254cb01fad")
+        );
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let params = json!(["hello world"]);
+        assert_eq!(
+            HANDLER
+                .local_result(&params)
+                .unwrap()
+                .unwrap_err()
+                .to_string(),
+            "params[0] is not valid hex data"
+        );
+
+        let params = json!([]);
+        assert_eq!(
+            HANDLER
+                .local_result(&params)
+                .unwrap()
+                .unwrap_err()
+                .to_string(),
+            "expected 1 params, got 0"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::json_rpc::{JsonRpcRequest, RequestId};
+use crate::ChainState;
+
+/// A cache hit picked for verification against the upstream.
+pub struct SampledHit {
+    pub method: String,
+    pub params: Value,
+    pub cache_key: String,
+    pub cached_value: Arc<Value>,
+}
+
+/// Re-queries the upstream for every sampled hit and reports results that differ from the cached value,
+/// which usually points at a handler deriving the same cache key for requests with different results.
+pub async fn verify_cache_hits(chain_state: Arc<ChainState>, hits: Vec<SampledHit>) {
+    // It's safe to unwrap here because a number is always a valid request id.
+    let request_id = RequestId::try_from(json!(1)).unwrap();
+
+    for hit in hits {
+        let upstream = chain_state.route(&hit.method, &hit.params).pick();
+
+        let request = JsonRpcRequest::new(Some(&request_id), &hit.method, &hit.params);
+
+        let mut response = match upstream.request(&request).await {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::warn!(
+                    method = hit.method,
+                    "fail to verify cache hit with key {}: {err:#}",
+                    hit.cache_key
+                );
+                continue;
+            }
+        };
+
+        if !response["error"].is_null() {
+            tracing::warn!(
+                method = hit.method,
+                "fail to verify cache hit with key {}, upstream returned error: {}",
+                hit.cache_key,
+                response["error"]
+            );
+            continue;
+        }
+
+        let upstream_value = response["result"].take();
+
+        if upstream_value != *hit.cached_value {
+            tracing::error!(
+                method = hit.method,
+                params = format_args!("{}", hit.params),
+                upstream = upstream.url.as_str(),
+                "cache mismatch for key {}, cached: {}, upstream: {}",
+                hit.cache_key,
+                hit.cached_value,
+                upstream_value
+            );
+        }
+    }
+}
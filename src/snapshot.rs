@@ -0,0 +1,137 @@
+use serde_json::{json, Value};
+
+use crate::json_rpc::DefinedError;
+use crate::rpc_cache_handler::common::parse_block_number;
+
+/// Position of the block parameter of methods taking one, which defaults to `latest` when omitted.
+fn block_param_index(method: &str) -> Option<usize> {
+    let index = match method {
+        "eth_getBlockByNumber"
+        | "eth_getBlockTransactionCountByNumber"
+        | "eth_getUncleCountByBlockNumber"
+        | "eth_getTransactionByBlockNumberAndIndex"
+        | "eth_getUncleByBlockNumberAndIndex"
+        | "eth_getBlockReceipts"
+        | "debug_traceBlockByNumber"
+        | "trace_block"
+        | "trace_replayBlockTransactions" => 0,
+        "eth_getBalance"
+        | "eth_getCode"
+        | "eth_getTransactionCount"
+        | "eth_call"
+        | "eth_estimateGas"
+        | "eth_createAccessList"
+        | "eth_feeHistory"
+        | "debug_traceCall" => 1,
+        "eth_getStorageAt" | "eth_getProof" | "trace_call" => 2,
+        _ => return None,
+    };
+
+    Some(index)
+}
+
+/// Rewrites block tags in `params` of `method` to `pinned_block`, so that the chain looks frozen at that
+/// height, and rejects requests for blocks above it. Requests by hash can't be checked and pass unchanged.
+pub fn pin_params(
+    method: &str,
+    mut params: Value,
+    pinned_block: u64,
+) -> Result<Value, DefinedError> {
+    let pinned = json!(format!("0x{pinned_block:x}"));
+
+    let result = match (method, &mut params) {
+        ("eth_getLogs" | "eth_newFilter", Value::Array(params)) => match params.first_mut() {
+            Some(Value::Object(filter)) if !filter.contains_key("blockHash") => {
+                ["fromBlock", "toBlock"].into_iter().try_for_each(|field| {
+                    let value = filter.entry(field).or_insert_with(|| pinned.clone());
+                    pin_block(value, &pinned, pinned_block)
+                })
+            }
+            _ => Ok(()),
+        },
+        (method, Value::Array(params)) => match block_param_index(method) {
+            Some(index) if params.len() == index => {
+                params.push(pinned.clone());
+                Ok(())
+            }
+            Some(index) => match params.get_mut(index) {
+                Some(value) => pin_block(value, &pinned, pinned_block),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    };
+
+    result.map_err(|block| {
+        DefinedError::BeyondPinnedBlock(Some(json!({
+            "block": format!("0x{block:x}"),
+            "pinned_block": pinned,
+        })))
+    })?;
+
+    Ok(params)
+}
+
+/// Fails with the requested block if it's above the pinned one.
+fn pin_block(value: &mut Value, pinned: &Value, pinned_block: u64) -> Result<(), u64> {
+    match value {
+        Value::String(tag)
+            if matches!(tag.as_str(), "latest" | "pending" | "safe" | "finalized") =>
+        {
+            *value = pinned.clone();
+        }
+        // EIP-1898 block parameter
+        Value::Object(block) => {
+            if let Some(number) = block.get_mut("blockNumber") {
+                return pin_block(number, pinned, pinned_block);
+            }
+        }
+        _ => {
+            if let Ok(Some(block)) = parse_block_number(value) {
+                if block > pinned_block {
+                    return Err(block);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pin_params() {
+        let params = pin_params("eth_getBalance", json!(["0xab", "latest"]), 16).unwrap();
+        assert_eq!(params, json!(["0xab", "0x10"]));
+
+        let params = pin_params("eth_call", json!([{}]), 16).unwrap();
+        assert_eq!(params, json!([{}, "0x10"]));
+
+        let params = pin_params("eth_call", json!([{}, { "blockNumber": "safe" }]), 16).unwrap();
+        assert_eq!(params, json!([{}, { "blockNumber": "0x10" }]));
+
+        let params = pin_params("eth_getBlockByNumber", json!(["0x8", false]), 16).unwrap();
+        assert_eq!(params, json!(["0x8", false]));
+
+        let err = pin_params("eth_getBlockByNumber", json!(["0x11", false]), 16).unwrap_err();
+        assert_eq!(err.code_and_message().0, -32057);
+
+        let params = pin_params("eth_chainId", json!([]), 16).unwrap();
+        assert_eq!(params, json!([]));
+    }
+
+    #[test]
+    fn test_pin_logs_filter() {
+        let params = pin_params("eth_getLogs", json!([{ "fromBlock": "0x1" }]), 16).unwrap();
+        assert_eq!(params, json!([{ "fromBlock": "0x1", "toBlock": "0x10" }]));
+
+        let params = pin_params("eth_getLogs", json!([{ "blockHash": "0xab" }]), 16).unwrap();
+        assert_eq!(params, json!([{ "blockHash": "0xab" }]));
+
+        assert!(pin_params("eth_getLogs", json!([{ "toBlock": "0x20" }]), 16).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::json_rpc::{JsonRpcResponse, RequestId};
+
+/// Matches any single param in a params pattern.
+const WILDCARD: &str = "*";
+
+/// A fixed answer to requests of `method`, e.g. a polite error for `eth_sendRawTransaction` or a pinned
+/// `eth_gasPrice` in test environments.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StaticResponseConfig {
+    pub method: String,
+
+    /// Matches requests with as many params, each equal to the given one or to anything for `"*"`. Requests
+    /// with any params match if unset.
+    #[serde(default)]
+    pub params: Option<Vec<Value>>,
+
+    #[serde(flatten)]
+    pub answer: StaticAnswer,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum StaticAnswer {
+    Result(Value),
+    /// A JSON-RPC error object, e.g. `{ code: -32000, message: "not supported" }`.
+    Error(Value),
+}
+
+impl StaticResponseConfig {
+    pub fn matches(&self, method: &str, params: &Value) -> bool {
+        if self.method != method {
+            return false;
+        }
+
+        let Some(pattern) = &self.params else {
+            return true;
+        };
+
+        match params.as_array() {
+            Some(params) => {
+                params.len() == pattern.len()
+                    && pattern
+                        .iter()
+                        .zip(params)
+                        .all(|(expected, param)| expected == WILDCARD || expected == param)
+            }
+            None => pattern.is_empty() && params.is_null(),
+        }
+    }
+
+    pub fn response(&self, id: RequestId) -> JsonRpcResponse {
+        match &self.answer {
+            StaticAnswer::Result(result) => JsonRpcResponse::from_result(id, result.clone()),
+            StaticAnswer::Error(error) => {
+                JsonRpcResponse::from_custom_error(Some(id), error.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse_config(yaml: &str) -> StaticResponseConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let config = parse_config("{ method: eth_gasPrice, result: '0x1' }");
+        assert!(config.matches("eth_gasPrice", &json!([])));
+        assert!(!config.matches("eth_blockNumber", &json!([])));
+
+        let config =
+            parse_config("{ method: eth_getBalance, params: ['0xab', '*'], result: '0x0' }");
+        assert!(config.matches("eth_getBalance", &json!(["0xab", "latest"])));
+        assert!(!config.matches("eth_getBalance", &json!(["0xcd", "latest"])));
+        assert!(!config.matches("eth_getBalance", &json!(["0xab"])));
+    }
+
+    #[test]
+    fn test_answer() {
+        let config = parse_config(
+            "{ method: eth_sendRawTransaction, error: { code: -32000, message: read-only } }",
+        );
+        assert!(matches!(
+            config.answer,
+            StaticAnswer::Error(ref error) if error["code"] == -32000
+        ));
+
+        assert!(serde_yaml::from_str::<StaticResponseConfig>("{ method: eth_call }").is_err());
+    }
+}
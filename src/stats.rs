@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use dashmap::DashMap;
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+
+use crate::canary::Canary;
+use crate::mirror::Mirror;
+use crate::AppState;
+
+/// Upper bound of recorded latencies, slower requests are clamped to it.
+const MAX_LATENCY_MICROS: u64 = 60_000_000;
+
+/// A metric name paired with the counter it reads.
+type Counter<T> = (&'static str, fn(&T) -> &AtomicU64);
+
+/// Estimated cost of upstream requests per method, e.g. after the compute units billed by Alchemy or the credits
+/// billed by Infura.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComputeUnitsConfig {
+    /// Cost of methods missing from `methods`.
+    pub default: u64,
+    pub methods: HashMap<String, u64>,
+}
+
+impl Default for ComputeUnitsConfig {
+    fn default() -> Self {
+        Self {
+            default: 1,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+impl ComputeUnitsConfig {
+    pub fn cost(&self, method: &str) -> u64 {
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+}
+
+/// Request counters and upstream latency histograms of a chain, per method.
+#[derive(Default)]
+pub struct ChainStats {
+    methods: DashMap<String, MethodStats>,
+    pub gc_reclaimed_entries: AtomicU64,
+    pub gc_reclaimed_bytes: AtomicU64,
+    pub quota_evicted_entries: AtomicU64,
+    pub quota_evicted_bytes: AtomicU64,
+    /// Size of the entries kept forever as of the last quota check, for chains with a quota.
+    pub stored_bytes: AtomicU64,
+    /// Requests failed because every connection of the cache pool was in use.
+    pub cache_pool_exhausted: AtomicU64,
+    /// Upstream results not cached because they didn't have the shape of the method's results.
+    pub invalid_results: AtomicU64,
+}
+
+struct MethodStats {
+    cache_hits: u64,
+    upstream_latency_micros: Histogram<u64>,
+}
+
+impl MethodStats {
+    fn new() -> Self {
+        Self {
+            cache_hits: 0,
+            // It's safe to unwrap here because the bounds are valid.
+            upstream_latency_micros: Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, 3).unwrap(),
+        }
+    }
+}
+
+impl ChainStats {
+    pub fn record_cache_hit(&self, method: &str) {
+        self.method_stats(method).cache_hits += 1;
+    }
+
+    /// Only recorded for requests sent to the upstream on their own, not as part of a batch.
+    pub fn record_upstream_latency(&self, method: &str, latency: Duration) {
+        let micros = (latency.as_micros() as u64).clamp(1, MAX_LATENCY_MICROS);
+        self.method_stats(method)
+            .upstream_latency_micros
+            .saturating_record(micros);
+    }
+
+    fn method_stats(&self, method: &str) -> dashmap::mapref::one::RefMut<'_, String, MethodStats> {
+        // Avoid allocating the method name for methods seen before.
+        if let Some(stats) = self.methods.get_mut(method) {
+            return stats;
+        }
+
+        self.methods
+            .entry(method.to_string())
+            .or_insert_with(MethodStats::new)
+    }
+
+    fn snapshot(&self) -> BTreeMap<String, MethodSnapshot> {
+        self.methods
+            .iter()
+            .map(|entry| {
+                let histogram = &entry.upstream_latency_micros;
+                let quantile_ms =
+                    |quantile: f64| histogram.value_at_quantile(quantile) as f64 / 1000.0;
+
+                let snapshot = MethodSnapshot {
+                    cache_hits: entry.cache_hits,
+                    upstream_requests: histogram.len(),
+                    upstream_latency_ms: LatencySnapshot {
+                        p50: quantile_ms(0.5),
+                        p95: quantile_ms(0.95),
+                        p99: quantile_ms(0.99),
+                    },
+                };
+
+                (entry.key().clone(), snapshot)
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct ChainSnapshot {
+    id_mismatches: u64,
+    gc_reclaimed_entries: u64,
+    gc_reclaimed_bytes: u64,
+    quota_evicted_entries: u64,
+    quota_evicted_bytes: u64,
+    stored_bytes: u64,
+    cache_pool_exhausted: u64,
+    invalid_results: u64,
+    /// Only for chains with a canary.
+    canary: Option<CanarySnapshot>,
+    methods: BTreeMap<String, MethodSnapshot>,
+}
+
+#[derive(Serialize)]
+struct CanarySnapshot {
+    requests: u64,
+    mismatches: u64,
+    errors: u64,
+}
+
+#[derive(Serialize)]
+struct MethodSnapshot {
+    cache_hits: u64,
+    upstream_requests: u64,
+    upstream_latency_ms: LatencySnapshot,
+}
+
+#[derive(Serialize)]
+struct LatencySnapshot {
+    p50: f64,
+    p95: f64,
+    p99: f64,
+}
+
+#[derive(Serialize)]
+struct ChainUsage {
+    /// Cost of the requests answered by the cache, which would have been sent upstream otherwise.
+    saved_compute_units: u64,
+    /// Keyed by host, so that api keys in urls aren't exposed.
+    upstreams: BTreeMap<String, UpstreamUsage>,
+}
+
+#[derive(Serialize, Default)]
+struct UpstreamUsage {
+    requests: u64,
+    compute_units: u64,
+    methods: BTreeMap<String, MethodUsage>,
+}
+
+#[derive(Serialize, Default)]
+struct MethodUsage {
+    requests: u64,
+    compute_units: u64,
+}
+
+#[derive(Serialize)]
+struct Health {
+    /// Whether each configured chain is being served, chains whose upstreams are unreachable at startup aren't.
+    chains: BTreeMap<String, bool>,
+}
+
+/// Answers as long as the server is up, with the chains it serves so far for readiness checks.
+#[actix_web::get("/health")]
+pub async fn health(data: web::Data<AppState>) -> HttpResponse {
+    let chains = data
+        .chains
+        .iter()
+        .map(|(name, slot)| (name.clone(), slot.get().is_some()))
+        .collect();
+
+    HttpResponse::Ok().json(Health { chains })
+}
+
+#[actix_web::get("/stats")]
+pub async fn chain_stats(data: web::Data<AppState>) -> HttpResponse {
+    let chains = data
+        .ready_chains()
+        .map(|(name, chain_state)| {
+            let snapshot = ChainSnapshot {
+                id_mismatches: chain_state.id_mismatches.load(Ordering::Relaxed),
+                gc_reclaimed_entries: chain_state
+                    .stats
+                    .gc_reclaimed_entries
+                    .load(Ordering::Relaxed),
+                gc_reclaimed_bytes: chain_state.stats.gc_reclaimed_bytes.load(Ordering::Relaxed),
+                quota_evicted_entries: chain_state
+                    .stats
+                    .quota_evicted_entries
+                    .load(Ordering::Relaxed),
+                quota_evicted_bytes: chain_state
+                    .stats
+                    .quota_evicted_bytes
+                    .load(Ordering::Relaxed),
+                stored_bytes: chain_state.stats.stored_bytes.load(Ordering::Relaxed),
+                cache_pool_exhausted: chain_state
+                    .stats
+                    .cache_pool_exhausted
+                    .load(Ordering::Relaxed),
+                invalid_results: chain_state.stats.invalid_results.load(Ordering::Relaxed),
+                canary: chain_state.upstreams.canary().map(|canary| CanarySnapshot {
+                    requests: canary.requests.load(Ordering::Relaxed),
+                    mismatches: canary.mismatches.load(Ordering::Relaxed),
+                    errors: canary.errors.load(Ordering::Relaxed),
+                }),
+                methods: chain_state.stats.snapshot(),
+            };
+
+            (name.clone(), snapshot)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    HttpResponse::Ok().json(chains)
+}
+
+/// Requests sent to each upstream and their estimated cost, per chain.
+#[actix_web::get("/stats/upstream-usage")]
+pub async fn upstream_usage(data: web::Data<AppState>) -> HttpResponse {
+    let compute_units = &data.compute_units;
+
+    let chains = data
+        .ready_chains()
+        .map(|(name, chain_state)| {
+            let mut upstreams = BTreeMap::<String, UpstreamUsage>::new();
+
+            for upstream in chain_state
+                .upstreams
+                .pools()
+                .flat_map(|pool| pool.upstreams())
+            {
+                let host = upstream
+                    .url
+                    .host_str()
+                    .unwrap_or(upstream.url.as_str())
+                    .to_string();
+                let usage = upstreams.entry(host).or_default();
+
+                for (method, requests) in upstream.usage() {
+                    let cost = requests * compute_units.cost(&method);
+                    usage.requests += requests;
+                    usage.compute_units += cost;
+
+                    let method_usage = usage.methods.entry(method).or_default();
+                    method_usage.requests += requests;
+                    method_usage.compute_units += cost;
+                }
+            }
+
+            let saved_compute_units = chain_state
+                .stats
+                .snapshot()
+                .iter()
+                .map(|(method, stats)| stats.cache_hits * compute_units.cost(method))
+                .sum();
+
+            let usage = ChainUsage {
+                saved_compute_units,
+                upstreams,
+            };
+
+            (name.clone(), usage)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    HttpResponse::Ok().json(chains)
+}
+
+/// Same numbers as `/stats` in the Prometheus text format.
+#[actix_web::get("/metrics")]
+pub async fn metrics(data: web::Data<AppState>) -> HttpResponse {
+    let chains = data.ready_chains().collect::<BTreeMap<_, _>>();
+    let mut out = String::new();
+
+    // Writing to a string never fails.
+    let _ = writeln!(out, "# TYPE cached_eth_rpc_chain_up gauge");
+    for (name, slot) in data.chains.iter().collect::<BTreeMap<_, _>>() {
+        let _ = writeln!(
+            out,
+            "cached_eth_rpc_chain_up{{chain=\"{name}\"}} {}",
+            u8::from(slot.get().is_some())
+        );
+    }
+
+    let _ = writeln!(out, "# TYPE cached_eth_rpc_id_mismatches_total counter");
+    for (name, chain_state) in &chains {
+        let _ = writeln!(
+            out,
+            "cached_eth_rpc_id_mismatches_total{{chain=\"{name}\"}} {}",
+            chain_state.id_mismatches.load(Ordering::Relaxed)
+        );
+    }
+
+    let counters: [Counter<ChainStats>; 6] = [
+        ("gc_reclaimed_entries_total", |stats| {
+            &stats.gc_reclaimed_entries
+        }),
+        ("gc_reclaimed_bytes_total", |stats| {
+            &stats.gc_reclaimed_bytes
+        }),
+        ("quota_evicted_entries_total", |stats| {
+            &stats.quota_evicted_entries
+        }),
+        ("quota_evicted_bytes_total", |stats| {
+            &stats.quota_evicted_bytes
+        }),
+        ("cache_pool_exhausted_total", |stats| {
+            &stats.cache_pool_exhausted
+        }),
+        ("invalid_results_total", |stats| &stats.invalid_results),
+    ];
+    for (metric, counter) in counters {
+        let _ = writeln!(out, "# TYPE cached_eth_rpc_{metric} counter");
+        for (name, chain_state) in &chains {
+            let _ = writeln!(
+                out,
+                "cached_eth_rpc_{metric}{{chain=\"{name}\"}} {}",
+                counter(&chain_state.stats).load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    let _ = writeln!(out, "# TYPE cached_eth_rpc_stored_bytes gauge");
+    for (name, chain_state) in &chains {
+        let _ = writeln!(
+            out,
+            "cached_eth_rpc_stored_bytes{{chain=\"{name}\"}} {}",
+            chain_state.stats.stored_bytes.load(Ordering::Relaxed)
+        );
+    }
+
+    let canary_counters: [Counter<Canary>; 3] = [
+        ("canary_requests_total", |canary| &canary.requests),
+        ("canary_mismatches_total", |canary| &canary.mismatches),
+        ("canary_errors_total", |canary| &canary.errors),
+    ];
+    for (metric, counter) in canary_counters {
+        let _ = writeln!(out, "# TYPE cached_eth_rpc_{metric} counter");
+        for (name, chain_state) in &chains {
+            if let Some(canary) = chain_state.upstreams.canary() {
+                let _ = writeln!(
+                    out,
+                    "cached_eth_rpc_{metric}{{chain=\"{name}\"}} {}",
+                    counter(canary).load(Ordering::Relaxed)
+                );
+            }
+        }
+    }
+
+    let mirror_counters: [Counter<Mirror>; 2] = [
+        ("mirrored_batches_total", |mirror| &mirror.requests),
+        ("mirror_dropped_batches_total", |mirror| &mirror.dropped),
+    ];
+    for (metric, counter) in mirror_counters {
+        let _ = writeln!(out, "# TYPE cached_eth_rpc_{metric} counter");
+        for (name, chain_state) in &chains {
+            if let Some(mirror) = chain_state.upstreams.mirror() {
+                let _ = writeln!(
+                    out,
+                    "cached_eth_rpc_{metric}{{chain=\"{name}\"}} {}",
+                    counter(mirror).load(Ordering::Relaxed)
+                );
+            }
+        }
+    }
+
+    let snapshots = chains
+        .iter()
+        .map(|(name, chain_state)| (name, chain_state.stats.snapshot()))
+        .collect::<Vec<_>>();
+
+    let _ = writeln!(out, "# TYPE cached_eth_rpc_cache_hits_total counter");
+    for (name, methods) in &snapshots {
+        for (method, stats) in methods {
+            let _ = writeln!(
+                out,
+                "cached_eth_rpc_cache_hits_total{{chain=\"{name}\",method=\"{method}\"}} {}",
+                stats.cache_hits
+            );
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# TYPE cached_eth_rpc_upstream_latency_seconds summary"
+    );
+    for (name, methods) in &snapshots {
+        for (method, stats) in methods {
+            let labels = format!("chain=\"{name}\",method=\"{method}\"");
+            let latency = &stats.upstream_latency_ms;
+
+            for (quantile, ms) in [
+                ("0.5", latency.p50),
+                ("0.95", latency.p95),
+                ("0.99", latency.p99),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "cached_eth_rpc_upstream_latency_seconds{{{labels},quantile=\"{quantile}\"}} {}",
+                    ms / 1000.0
+                );
+            }
+
+            let _ = writeln!(
+                out,
+                "cached_eth_rpc_upstream_latency_seconds_count{{{labels}}} {}",
+                stats.upstream_requests
+            );
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
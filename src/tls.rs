@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> anyhow::Result<ServerConfig> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("fail to build tls server config")?;
+
+    Ok(config)
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let file = File::open(path)
+        .with_context(|| format!("fail to open tls cert file {}", path.display()))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .context("fail to parse tls cert file")?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+
+    if certs.is_empty() {
+        bail!("no certificate found in {}", path.display());
+    }
+
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    let file = File::open(path)
+        .with_context(|| format!("fail to open tls key file {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+
+    while let Some(item) =
+        rustls_pemfile::read_one(&mut reader).context("fail to parse tls key file")?
+    {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                return Ok(PrivateKey(key))
+            }
+            _ => continue,
+        }
+    }
+
+    bail!("no private key found in {}", path.display())
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{keccak256, Bytes, B256};
+use anyhow::Context;
+use dashmap::DashMap;
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::json_rpc::{DefinedError, JsonRpcResponse};
+use crate::{ChainState, RpcRequest};
+
+pub const SEND_RAW_TRANSACTION: &str = "eth_sendRawTransaction";
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionConfig {
+    /// Sends transactions to every upstream of the pool at once for faster propagation.
+    pub broadcast: bool,
+
+    /// A raw transaction sent again within this many seconds is answered with its hash instead of being sent
+    /// again, e.g. for wallets retrying aggressively. Disabled if `0`.
+    pub dedupe_window_secs: u64,
+}
+
+/// When each recently sent transaction was sent, by hash.
+#[derive(Default)]
+pub struct RecentTransactions {
+    sent: DashMap<B256, Instant>,
+}
+
+impl RecentTransactions {
+    /// Records a transaction about to be sent. Returns `false` if it was already sent within `window`, in which
+    /// case it shouldn't be sent again.
+    pub fn record(&self, tx_hash: B256, window: Duration) -> bool {
+        let now = Instant::now();
+        self.sent
+            .retain(|_, sent_at| now.duration_since(*sent_at) < window);
+
+        match self.sent.entry(tx_hash) {
+            dashmap::mapref::entry::Entry::Occupied(_) => false,
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+
+    /// Forgets a transaction that failed to be sent, so that it can be retried right away.
+    pub fn forget(&self, tx_hash: &B256) {
+        self.sent.remove(tx_hash);
+    }
+}
+
+/// Hash of the raw transaction in `params`, which is also the result of sending it.
+pub fn transaction_hash(params: &Value) -> Option<B256> {
+    let raw_tx: Bytes = serde_json::from_value(params.get(0)?.clone()).ok()?;
+
+    Some(keccak256(raw_tx))
+}
+
+/// Sends a transaction to every upstream it's routed to at once and answers with the first successful response.
+/// The other upstreams keep propagating it in the background.
+pub async fn broadcast(
+    chain_state: Arc<ChainState>,
+    rpc_request: RpcRequest,
+    forwarded_headers: HeaderMap,
+) -> (usize, JsonRpcResponse) {
+    let (index, id) = (rpc_request.index, rpc_request.id.clone());
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+
+    actix_web::rt::spawn(async move {
+        let upstreams = chain_state
+            .route(&rpc_request.method, &rpc_request.params)
+            .upstreams();
+        let rpc_request = &rpc_request;
+        let forwarded_headers = &forwarded_headers;
+
+        let mut requests = upstreams
+            .iter()
+            .map(|upstream| async move {
+                let response = upstream
+                    .request_with_headers(rpc_request, forwarded_headers)
+                    .await;
+                (upstream, response)
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut sender = Some(sender);
+        let mut last_response = None;
+
+        while let Some((upstream, response)) = requests.next().await {
+            match response {
+                Ok(response) if response["error"].is_null() => {
+                    if let Some(sender) = sender.take() {
+                        let _ = sender.send(Ok(response));
+                    }
+                }
+                Ok(response) => last_response = Some(Ok(response)),
+                Err(err) => {
+                    tracing::warn!(
+                        "fail to broadcast transaction to {} because: {err:#}",
+                        upstream.url
+                    );
+                    last_response = Some(Err(err));
+                }
+            }
+        }
+
+        // No upstream accepted the transaction, the last rejection is forwarded.
+        if let (Some(sender), Some(last_response)) = (sender, last_response) {
+            let _ = sender.send(last_response);
+        }
+    });
+
+    let response = receiver
+        .await
+        .context("no upstream to broadcast to")
+        .and_then(|response| response);
+
+    let response = match response {
+        Ok(mut response) => match response["error"].take() {
+            Value::Null => JsonRpcResponse::from_result(id, response["result"].take()),
+            error => JsonRpcResponse::from_custom_error(Some(id), error),
+        },
+        Err(err) => JsonRpcResponse::from_error(
+            Some(id),
+            DefinedError::UpstreamUnavailable(Some(json!({
+                "reason": format!("{err:#}"),
+            }))),
+        ),
+    };
+
+    (index, response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let recent = RecentTransactions::default();
+        let window = Duration::from_secs(60);
+
+        assert!(recent.record(B256::ZERO, window));
+        assert!(!recent.record(B256::ZERO, window));
+        assert!(recent.record(B256::repeat_byte(1), window));
+
+        recent.forget(&B256::ZERO);
+        assert!(recent.record(B256::ZERO, window));
+
+        assert!(recent.record(B256::repeat_byte(2), Duration::ZERO));
+        assert!(recent.record(B256::repeat_byte(2), Duration::ZERO));
+    }
+
+    #[test]
+    fn test_transaction_hash() {
+        let params = json!(["0x68656c6c6f20776f726c64"]);
+        assert_eq!(
+            transaction_hash(&params).map(|hash| format!("{hash:#x}")),
+            Some("0x47173285a8d7341e5e972fc677286384f802f8ef42a5ec5f03bbfa254cb01fad".to_string())
+        );
+
+        assert_eq!(transaction_hash(&json!([])), None);
+        assert_eq!(transaction_hash(&json!(["not hex"])), None);
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::Url;
+use serde_json::Value;
+
+use super::Transport;
+use crate::jwt::JwtSecret;
+
+pub struct HttpTransport {
+    client: reqwest::Client,
+    url: Url,
+    /// Requests carry a freshly issued token if set, e.g. for the authenticated RPC of a node.
+    jwt_secret: Option<Arc<JwtSecret>>,
+}
+
+impl HttpTransport {
+    pub fn new(client: reqwest::Client, url: Url, jwt_secret: Option<Arc<JwtSecret>>) -> Self {
+        Self {
+            client,
+            url,
+            jwt_secret,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn request(&self, body: Vec<u8>, headers: &HeaderMap) -> anyhow::Result<Value> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .headers(headers.clone())
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(jwt_secret) = &self.jwt_secret {
+            request = request.bearer_auth(jwt_secret.issue());
+        }
+
+        let result = request.body(body).send().await?.json::<Value>().await?;
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use super::Transport;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Talks to a colocated node over its IPC socket, e.g. `ipc:///data/geth.ipc`. Every request opens its own
+/// connection, which is cheap for unix sockets and keeps replies from interleaving.
+pub struct IpcTransport {
+    path: PathBuf,
+    /// Time the node has to accept, read and answer a request, forever if unset.
+    timeout: Option<Duration>,
+}
+
+impl IpcTransport {
+    pub fn new(path: PathBuf, timeout: Option<Duration>) -> Self {
+        Self { path, timeout }
+    }
+
+    async fn exchange(&self, body: &[u8]) -> anyhow::Result<Value> {
+        let mut stream = UnixStream::connect(&self.path)
+            .await
+            .with_context(|| format!("fail to connect to {}", self.path.display()))?;
+
+        stream.write_all(body).await?;
+
+        // The node doesn't delimit replies, so read until the first JSON value is complete.
+        let mut scanner = JsonBoundaryScanner::default();
+        let mut buffer = Vec::with_capacity(READ_BUFFER_SIZE);
+        let mut chunk = vec![0u8; READ_BUFFER_SIZE];
+
+        loop {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                bail!("ipc connection closed before a complete response was received");
+            }
+
+            let offset = buffer.len();
+            buffer.extend_from_slice(&chunk[..n]);
+
+            if let Some(end) = scanner.feed(&chunk[..n]) {
+                return serde_json::from_slice(&buffer[..offset + end])
+                    .context("invalid response from ipc upstream");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for IpcTransport {
+    async fn request(&self, body: Vec<u8>, _headers: &HeaderMap) -> anyhow::Result<Value> {
+        match self.timeout {
+            Some(timeout) => actix_web::rt::time::timeout(timeout, self.exchange(&body))
+                .await
+                .with_context(|| format!("ipc request timed out after {timeout:?}"))?,
+            None => self.exchange(&body).await,
+        }
+    }
+}
+
+/// Finds the end of the first top-level JSON object or array in a byte stream without parsing it.
+#[derive(Default)]
+struct JsonBoundaryScanner {
+    depth: usize,
+    started: bool,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonBoundaryScanner {
+    /// Feeds the next bytes of the stream. Returns the offset in `bytes` just past the end of the value
+    /// once it's complete.
+    fn feed(&mut self, bytes: &[u8]) -> Option<usize> {
+        for (i, byte) in bytes.iter().enumerate() {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    self.started = true;
+                }
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.started && self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_complete_value() {
+        let mut scanner = JsonBoundaryScanner::default();
+        let bytes = br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}{"id":2}"#;
+        assert_eq!(scanner.feed(bytes), Some(39));
+    }
+
+    #[test]
+    fn test_split_value() {
+        let mut scanner = JsonBoundaryScanner::default();
+        assert_eq!(scanner.feed(br#"[{"result":"}]\"{"#), None);
+        assert_eq!(scanner.feed(br#""},"#), None);
+        assert_eq!(scanner.feed(br#"{"result":[]}] "#), Some(14));
+    }
+}
@@ -0,0 +1,57 @@
+pub mod http;
+#[cfg(unix)]
+pub mod ipc;
+pub mod ws;
+
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde_json::Value;
+
+use crate::jwt::JwtSecret;
+
+/// Carries serialized JSON-RPC payloads to an upstream node and returns its decoded reply.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// `headers` are only sent by transports that have headers, i.e. http.
+    async fn request(&self, body: Vec<u8>, headers: &HeaderMap) -> anyhow::Result<Value>;
+}
+
+/// The http client of a chain, and its request timeout which the other transports apply too.
+#[derive(Clone, Default)]
+pub struct UpstreamClient {
+    pub http: reqwest::Client,
+    pub timeout: Option<Duration>,
+}
+
+pub fn new_transport(
+    url: &Url,
+    client: &UpstreamClient,
+    jwt_secret: Option<&Arc<JwtSecret>>,
+) -> anyhow::Result<Box<dyn Transport>> {
+    let transport: Box<dyn Transport> = match url.scheme() {
+        "http" | "https" => Box::new(http::HttpTransport::new(
+            client.http.clone(),
+            url.clone(),
+            jwt_secret.cloned(),
+        )),
+        _ if jwt_secret.is_some() => bail!("jwt authentication is only supported over http: {url}"),
+        "ws" | "wss" => Box::new(ws::WsTransport::new(url.clone(), client.timeout)),
+        #[cfg(unix)]
+        "ipc" => Box::new(ipc::IpcTransport::new(
+            PathBuf::from(url.path()),
+            client.timeout,
+        )),
+        #[cfg(not(unix))]
+        "ipc" => bail!("ipc upstreams are unsupported on this platform: {url}"),
+        scheme => bail!("unsupported upstream scheme `{scheme}` in {url}"),
+    };
+
+    Ok(transport)
+}
@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::{future, SinkExt, StreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::Transport;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+type PendingRequests = DashMap<u64, oneshot::Sender<Value>>;
+
+/// Sends all requests over one persistent websocket connection, e.g. `wss://node:8546`. Requests are
+/// given connection-unique ids so that replies can be matched no matter which caller sent them, and
+/// batches are split into individual calls that run concurrently. The connection is opened by the first
+/// request.
+pub struct WsTransport {
+    /// Time an upstream has to answer a request, forever if unset.
+    timeout: Option<Duration>,
+    next_id: AtomicU64,
+    pending: Arc<PendingRequests>,
+    outgoing: mpsc::UnboundedSender<String>,
+    /// Taken by the first request to start the connection task.
+    connection: Mutex<Option<(Url, mpsc::UnboundedReceiver<String>)>>,
+}
+
+impl WsTransport {
+    pub fn new(url: Url, timeout: Option<Duration>) -> Self {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+
+        Self {
+            timeout,
+            next_id: AtomicU64::new(1),
+            pending: Arc::new(PendingRequests::new()),
+            outgoing,
+            connection: Mutex::new(Some((url, outgoing_rx))),
+        }
+    }
+
+    fn connect(&self) {
+        let connection = self.connection.lock().unwrap().take();
+
+        if let Some((url, outgoing_rx)) = connection {
+            actix_web::rt::spawn(run_connection(url, self.pending.clone(), outgoing_rx));
+        }
+    }
+
+    async fn call(&self, mut request: Value) -> anyhow::Result<Value> {
+        self.connect();
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let original_id = std::mem::replace(&mut request["id"], json!(id));
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(id, tx);
+
+        if self.outgoing.send(request.to_string()).is_err() {
+            self.pending.remove(&id);
+            anyhow::bail!("websocket connection task stopped");
+        }
+
+        let response = match self.timeout {
+            Some(timeout) => match actix_web::rt::time::timeout(timeout, rx).await {
+                Ok(response) => response,
+                Err(_) => {
+                    self.pending.remove(&id);
+                    anyhow::bail!("websocket request timed out after {timeout:?}");
+                }
+            },
+            None => rx.await,
+        };
+        let mut response = response.context("websocket connection to upstream lost")?;
+        response["id"] = original_id;
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Transport for WsTransport {
+    async fn request(&self, body: Vec<u8>, _headers: &HeaderMap) -> anyhow::Result<Value> {
+        let payload: Value = serde_json::from_slice(&body)?;
+
+        match payload {
+            Value::Array(requests) => {
+                let responses =
+                    future::try_join_all(requests.into_iter().map(|request| self.call(request)))
+                        .await?;
+                Ok(Value::Array(responses))
+            }
+            request => self.call(request).await,
+        }
+    }
+}
+
+/// Keeps the connection up, reconnecting after failures. In-flight and queued requests fail whenever
+/// the connection drops since there is no telling whether the upstream has processed them.
+async fn run_connection(
+    url: Url,
+    pending: Arc<PendingRequests>,
+    mut outgoing: mpsc::UnboundedReceiver<String>,
+) {
+    loop {
+        match tokio_tungstenite::connect_async(url.as_str()).await {
+            Ok((stream, _)) => {
+                tracing::info!("websocket connected to {url}");
+                let (mut sink, mut stream) = stream.split();
+
+                loop {
+                    tokio::select! {
+                        message = outgoing.recv() => match message {
+                            Some(message) => {
+                                if let Err(err) = sink.send(Message::Text(message)).await {
+                                    tracing::warn!("fail to send to websocket {url}: {err}");
+                                    break;
+                                }
+                            }
+                            // The transport has been dropped.
+                            None => return,
+                        },
+                        message = stream.next() => match message {
+                            Some(Ok(Message::Text(text))) => dispatch_response(&pending, &text),
+                            Some(Ok(Message::Close(_))) | None => {
+                                tracing::warn!("websocket {url} closed by upstream");
+                                break;
+                            }
+                            Some(Ok(_)) => {}
+                            Some(Err(err)) => {
+                                tracing::warn!("fail to read from websocket {url}: {err}");
+                                break;
+                            }
+                        },
+                    }
+                }
+            }
+            Err(err) => tracing::warn!("fail to connect to websocket {url}: {err}"),
+        }
+
+        pending.clear();
+        while outgoing.try_recv().is_ok() {}
+
+        actix_web::rt::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+fn dispatch_response(pending: &PendingRequests, text: &str) {
+    let response: Value = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::warn!("invalid websocket message: {err}");
+            return;
+        }
+    };
+
+    // Subscription notifications carry no id.
+    let Some(id) = response["id"].as_u64() else {
+        return;
+    };
+
+    if let Some((_, sender)) = pending.remove(&id) {
+        let _ = sender.send(response);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_response() {
+        let pending = PendingRequests::new();
+        let (tx, mut rx) = oneshot::channel();
+        pending.insert(7, tx);
+
+        dispatch_response(
+            &pending,
+            r#"{"jsonrpc":"2.0","method":"eth_subscription","params":{}}"#,
+        );
+        dispatch_response(&pending, r#"{"jsonrpc":"2.0","id":8,"result":"0x2"}"#);
+        assert!(rx.try_recv().is_err());
+
+        dispatch_response(&pending, r#"{"jsonrpc":"2.0","id":7,"result":"0x1"}"#);
+        assert_eq!(rx.try_recv().unwrap()["result"], "0x1");
+        assert!(pending.is_empty());
+    }
+}
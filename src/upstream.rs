@@ -0,0 +1,810 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use dashmap::DashMap;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::canary::{Canary, CanaryConfig};
+use crate::jwt::JwtSecret;
+use crate::mirror::{Mirror, MirrorConfig};
+use crate::transport::{self, Transport, UpstreamClient};
+
+/// Weight of the newest sample in the latency moving average.
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Latency charged to an upstream when a request to it fails, so that fast failures (e.g. connection
+/// refused) don't make a broken upstream look like the quickest one.
+const FAILURE_PENALTY: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalanceStrategy {
+    #[default]
+    RoundRobin,
+    LeastLatency,
+    Weighted,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamConfig {
+    pub url: Url,
+
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+
+    /// Keys substituted for `{api_key}` in `url`, so that the rate limit of a single key doesn't cap the
+    /// upstream. Requests move on to the next key when one is rate limited.
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+
+    #[serde(default)]
+    pub api_key_rotation: ApiKeyRotation,
+
+    /// Hex encoded HS256 secret, e.g. geth's `jwtsecret`, authenticating requests to an http upstream.
+    #[serde(default)]
+    pub jwt_secret_file: Option<PathBuf>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl From<Url> for UpstreamConfig {
+    fn from(url: Url) -> Self {
+        Self {
+            url,
+            weight: default_weight(),
+            api_keys: vec![],
+            api_key_rotation: Default::default(),
+            jwt_secret_file: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyRotation {
+    /// Every request starts with the next key.
+    #[default]
+    RoundRobin,
+
+    /// Requests stick to one key until it gets rate limited.
+    OnRateLimit,
+}
+
+const API_KEY_PLACEHOLDER: &str = "{api_key}";
+
+/// `url` with the `{api_key}` placeholder replaced by `api_key`. The placeholder is percent-encoded when
+/// it's part of the path.
+fn expand_api_key(url: &Url, api_key: &str) -> anyhow::Result<Url> {
+    let url = url.as_str();
+    let encoded_placeholder = "%7Bapi_key%7D";
+
+    if !url.contains(API_KEY_PLACEHOLDER) && !url.contains(encoded_placeholder) {
+        anyhow::bail!(
+            "api keys are configured but {url} has no `{API_KEY_PLACEHOLDER}` placeholder"
+        );
+    }
+
+    let expanded = url
+        .replace(encoded_placeholder, api_key)
+        .replace(API_KEY_PLACEHOLDER, api_key);
+
+    Url::parse(&expanded).context("invalid url after substituting the api key")
+}
+
+pub struct Upstream {
+    /// Configured url, with the `{api_key}` placeholder if any so that keys don't end up in logs.
+    pub url: Url,
+    weight: u32,
+    /// One transport per api key, or a single one without api keys.
+    transports: Vec<Box<dyn Transport>>,
+    api_key_rotation: ApiKeyRotation,
+    /// Index of the transport the next request starts with.
+    current_transport: AtomicUsize,
+    /// Exponentially weighted moving average of the request latency in microseconds. Zero means no
+    /// sample has been recorded yet.
+    latency_ewma_micros: AtomicU64,
+    /// Failed requests since the last successful one.
+    consecutive_failures: AtomicU32,
+    /// Requests sent per method, including retries with other api keys.
+    usage: DashMap<String, u64>,
+}
+
+impl Upstream {
+    pub fn new(config: UpstreamConfig, http_client: &UpstreamClient) -> anyhow::Result<Self> {
+        let jwt_secret = match &config.jwt_secret_file {
+            Some(path) => Some(Arc::new(JwtSecret::load(path)?)),
+            None => None,
+        };
+        let jwt_secret = jwt_secret.as_ref();
+
+        let transports = match config.api_keys.is_empty() {
+            true => vec![transport::new_transport(
+                &config.url,
+                http_client,
+                jwt_secret,
+            )?],
+            false => config
+                .api_keys
+                .iter()
+                .map(|api_key| {
+                    let url = expand_api_key(&config.url, api_key)?;
+                    transport::new_transport(&url, http_client, jwt_secret)
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+
+        Ok(Self {
+            url: config.url,
+            weight: config.weight,
+            transports,
+            api_key_rotation: config.api_key_rotation,
+            current_transport: AtomicUsize::new(0),
+            latency_ewma_micros: AtomicU64::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            usage: DashMap::new(),
+        })
+    }
+
+    /// Sends `body` with the next api key, moving on to the other keys while they are rate limited or
+    /// fail. The last outcome is returned if no key succeeds.
+    pub async fn request<T: Serialize + ?Sized>(&self, body: &T) -> anyhow::Result<Value> {
+        self.request_with_headers(body, &HeaderMap::new()).await
+    }
+
+    /// Like [`Upstream::request`], sending `headers` along to http upstreams.
+    pub async fn request_with_headers<T: Serialize + ?Sized>(
+        &self,
+        body: &T,
+        headers: &HeaderMap,
+    ) -> anyhow::Result<Value> {
+        let body = serde_json::to_vec(body)?;
+        let methods = request_methods(&body);
+
+        let count = self.transports.len();
+        let start = match self.api_key_rotation {
+            ApiKeyRotation::RoundRobin => self.current_transport.fetch_add(1, Ordering::Relaxed),
+            ApiKeyRotation::OnRateLimit => self.current_transport.load(Ordering::Relaxed),
+        };
+
+        let mut outcome = None;
+
+        for attempt in 0..count {
+            let index = (start + attempt) % count;
+            for method in &methods {
+                *self.usage.entry(method.clone()).or_default() += 1;
+            }
+
+            let result = self.transports[index].request(body.clone(), headers).await;
+
+            let exhausted = match &result {
+                Ok(response) => is_rate_limited(response),
+                Err(_) => true,
+            };
+            if !exhausted || count == 1 {
+                return result;
+            }
+
+            if self.api_key_rotation == ApiKeyRotation::OnRateLimit {
+                let _ = self.current_transport.compare_exchange(
+                    start + attempt,
+                    start + attempt + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            }
+
+            tracing::warn!(
+                "api key #{index} of {} is exhausted, trying the next one",
+                self.url
+            );
+            outcome = Some(result);
+        }
+
+        // It's safe to unwrap here because there is at least one transport.
+        outcome.unwrap()
+    }
+
+    /// Requests sent so far per method.
+    pub fn usage(&self) -> BTreeMap<String, u64> {
+        self.usage
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        match self.latency_ewma_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    pub fn record_latency(&self, latency: Duration) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.update_latency_ewma(latency);
+    }
+
+    /// Returns the number of consecutive failures including this one.
+    pub fn record_failure(&self, latency: Duration) -> u32 {
+        self.update_latency_ewma(latency.max(FAILURE_PENALTY));
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn update_latency_ewma(&self, latency: Duration) {
+        let sample = latency.as_micros().max(1) as f64;
+
+        // Concurrent updates may overwrite each other, which is fine for a moving average.
+        let next = match self.latency_ewma_micros.load(Ordering::Relaxed) {
+            0 => sample,
+            prev => EWMA_ALPHA * sample + (1.0 - EWMA_ALPHA) * prev as f64,
+        };
+
+        self.latency_ewma_micros
+            .store(next as u64, Ordering::Relaxed);
+    }
+}
+
+pub struct UpstreamPool {
+    upstreams: Vec<Upstream>,
+    strategy: LoadBalanceStrategy,
+    counter: AtomicUsize,
+}
+
+impl UpstreamPool {
+    pub fn new(
+        upstreams: Vec<UpstreamConfig>,
+        strategy: LoadBalanceStrategy,
+        http_client: &UpstreamClient,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(!upstreams.is_empty(), "upstream pool must not be empty");
+
+        let upstreams = upstreams
+            .into_iter()
+            .map(|config| Upstream::new(config, http_client))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            upstreams,
+            strategy,
+            counter: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn upstreams(&self) -> &[Upstream] {
+        &self.upstreams
+    }
+
+    /// The picked upstream followed by the others of the pool, which requests fail over to.
+    pub fn failover_order(&self) -> impl Iterator<Item = &Upstream> {
+        let picked = self.pick();
+        let start = self
+            .upstreams
+            .iter()
+            .position(|upstream| std::ptr::eq(upstream, picked))
+            .unwrap_or_default();
+
+        self.upstreams
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(self.upstreams.len())
+    }
+
+    pub fn pick(&self) -> &Upstream {
+        if self.upstreams.len() == 1 {
+            return &self.upstreams[0];
+        }
+
+        match self.strategy {
+            LoadBalanceStrategy::RoundRobin => {
+                let n = self.counter.fetch_add(1, Ordering::Relaxed);
+                &self.upstreams[n % self.upstreams.len()]
+            }
+            LoadBalanceStrategy::LeastLatency => {
+                // Upstreams without samples are tried first so that every upstream gets measured.
+                self.upstreams
+                    .iter()
+                    .min_by_key(|upstream| upstream.latency().unwrap_or_default())
+                    .unwrap()
+            }
+            LoadBalanceStrategy::Weighted => {
+                let total_weight: u64 = self.upstreams.iter().map(|u| u.weight as u64).sum();
+                if total_weight == 0 {
+                    return &self.upstreams[0];
+                }
+
+                let n = self.counter.fetch_add(1, Ordering::Relaxed) as u64;
+                let mut slot = n % total_weight;
+
+                for upstream in &self.upstreams {
+                    if slot < upstream.weight as u64 {
+                        return upstream;
+                    }
+                    slot -= upstream.weight as u64;
+                }
+
+                unreachable!("slot is always smaller than the total weight")
+            }
+        }
+    }
+}
+
+/// Whether an upstream response, a single one or a whole batch, only reports rate limiting.
+pub fn is_rate_limited(response: &Value) -> bool {
+    let is_rate_limit_error = |response: &Value| {
+        let error = &response["error"];
+        let message = error["message"].as_str().unwrap_or_default().to_lowercase();
+
+        matches!(error["code"].as_i64(), Some(429 | -32005))
+            || message.contains("rate limit")
+            || message.contains("too many requests")
+    };
+
+    match response {
+        Value::Array(responses) => {
+            !responses.is_empty() && responses.iter().all(is_rate_limit_error)
+        }
+        response => is_rate_limit_error(response),
+    }
+}
+
+/// Methods of a serialized request or batch. Params are skipped without being parsed.
+fn request_methods(body: &[u8]) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct Request {
+        method: String,
+    }
+
+    let requests = match body.first() {
+        Some(b'[') => serde_json::from_slice::<Vec<Request>>(body).ok(),
+        _ => serde_json::from_slice::<Request>(body)
+            .ok()
+            .map(|request| vec![request]),
+    };
+
+    requests
+        .unwrap_or_default()
+        .into_iter()
+        .map(|request| request.method)
+        .collect()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MethodRouteConfig {
+    /// Method names served by this route. A trailing `*` matches by prefix, e.g. `debug_*`.
+    pub methods: Vec<String>,
+
+    pub upstreams: Vec<UpstreamConfig>,
+
+    #[serde(default)]
+    pub load_balance: LoadBalanceStrategy,
+}
+
+struct MethodRoute {
+    patterns: Vec<String>,
+    upstreams: UpstreamPool,
+}
+
+impl MethodRoute {
+    fn matches(&self, method: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => method.starts_with(prefix),
+                None => method == pattern,
+            })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HistoricalRouteConfig {
+    /// Requests referencing a block more than this many blocks behind the head go to `upstreams`.
+    pub min_block_age: u64,
+
+    pub upstreams: Vec<UpstreamConfig>,
+
+    #[serde(default)]
+    pub load_balance: LoadBalanceStrategy,
+}
+
+struct HistoricalRoute {
+    min_block_age: u64,
+    upstreams: UpstreamPool,
+}
+
+/// Picks the upstream pool a request is sent to. Method routes are checked first, in the configured order,
+/// then the historical route. Requests matching none of them go to the default pool.
+pub struct UpstreamRouter {
+    default: UpstreamPool,
+    method_routes: Vec<MethodRoute>,
+    historical_route: Option<HistoricalRoute>,
+    /// Tried only when every upstream of the routed pool failed.
+    fallback: Option<UpstreamPool>,
+    /// Mirrored a sample of requests to without serving its responses.
+    canary: Option<Arc<Canary>>,
+    /// Copied uncached batches without serving its responses.
+    mirror: Option<Arc<Mirror>>,
+    /// Namespaces, e.g. `trace`, that some upstream of the default or historical pool doesn't support.
+    unsupported_namespaces: BTreeSet<String>,
+}
+
+impl UpstreamRouter {
+    pub fn new(
+        default: UpstreamPool,
+        method_routes: Vec<MethodRouteConfig>,
+        historical_route: Option<HistoricalRouteConfig>,
+        fallback_upstreams: Vec<UpstreamConfig>,
+        canary: Option<CanaryConfig>,
+        mirror: Option<MirrorConfig>,
+        http_client: &UpstreamClient,
+    ) -> anyhow::Result<Self> {
+        let method_routes = method_routes
+            .into_iter()
+            .map(|route| {
+                Ok(MethodRoute {
+                    patterns: route.methods,
+                    upstreams: UpstreamPool::new(route.upstreams, route.load_balance, http_client)?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let historical_route = historical_route
+            .map(|route| {
+                Ok::<_, anyhow::Error>(HistoricalRoute {
+                    min_block_age: route.min_block_age,
+                    upstreams: UpstreamPool::new(route.upstreams, route.load_balance, http_client)?,
+                })
+            })
+            .transpose()?;
+
+        let fallback = match fallback_upstreams.is_empty() {
+            true => None,
+            false => Some(UpstreamPool::new(
+                fallback_upstreams,
+                LoadBalanceStrategy::RoundRobin,
+                http_client,
+            )?),
+        };
+
+        let canary = canary
+            .map(|config| Canary::new(&config, http_client).map(Arc::new))
+            .transpose()?;
+        let mirror = mirror
+            .map(|config| Mirror::new(&config, http_client).map(Arc::new))
+            .transpose()?;
+
+        Ok(Self {
+            default,
+            method_routes,
+            historical_route,
+            fallback,
+            canary,
+            mirror,
+            unsupported_namespaces: BTreeSet::new(),
+        })
+    }
+
+    pub fn route(
+        &self,
+        method: &str,
+        referenced_block: Option<u64>,
+        latest_block: Option<u64>,
+    ) -> &UpstreamPool {
+        if let Some(route) = self
+            .method_routes
+            .iter()
+            .find(|route| route.matches(method))
+        {
+            return &route.upstreams;
+        }
+
+        if let (Some(route), Some(block), Some(latest)) =
+            (&self.historical_route, referenced_block, latest_block)
+        {
+            if block.saturating_add(route.min_block_age) < latest {
+                return &route.upstreams;
+            }
+        }
+
+        &self.default
+    }
+
+    pub fn default_pool(&self) -> &UpstreamPool {
+        &self.default
+    }
+
+    pub fn fallback_pool(&self) -> Option<&UpstreamPool> {
+        self.fallback.as_ref()
+    }
+
+    pub fn canary(&self) -> Option<&Arc<Canary>> {
+        self.canary.as_ref()
+    }
+
+    pub fn mirror(&self) -> Option<&Arc<Mirror>> {
+        self.mirror.as_ref()
+    }
+
+    /// Upstreams of the pools requests go to unless they match a method route.
+    pub fn unrouted_upstreams(&self) -> impl Iterator<Item = &Upstream> {
+        std::iter::once(&self.default)
+            .chain(self.historical_route.iter().map(|route| &route.upstreams))
+            .flat_map(|pool| pool.upstreams())
+    }
+
+    pub fn set_unsupported_namespaces(&mut self, namespaces: BTreeSet<String>) {
+        self.unsupported_namespaces = namespaces;
+    }
+
+    /// Namespace of `method` if the upstreams it's sent to don't support it. Methods of a method route are assumed
+    /// to be supported by its upstreams.
+    pub fn unsupported_namespace<'a>(&self, method: &'a str) -> Option<&'a str> {
+        let (namespace, _) = method.split_once('_')?;
+
+        if !self.unsupported_namespaces.contains(namespace)
+            || self.method_routes.iter().any(|route| route.matches(method))
+        {
+            return None;
+        }
+
+        Some(namespace)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &UpstreamPool> {
+        std::iter::once(&self.default)
+            .chain(self.method_routes.iter().map(|route| &route.upstreams))
+            .chain(self.historical_route.iter().map(|route| &route.upstreams))
+            .chain(self.fallback.iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool(weights: &[u32], strategy: LoadBalanceStrategy) -> UpstreamPool {
+        let upstreams = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| UpstreamConfig {
+                weight: *weight,
+                ..Url::parse(&format!("http://upstream-{i}")).unwrap().into()
+            })
+            .collect();
+
+        UpstreamPool::new(upstreams, strategy, &UpstreamClient::default()).unwrap()
+    }
+
+    fn route_config(methods: &[&str], host: &str) -> MethodRouteConfig {
+        MethodRouteConfig {
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            upstreams: vec![Url::parse(&format!("http://{host}")).unwrap().into()],
+            load_balance: Default::default(),
+        }
+    }
+
+    fn picked_hosts(pool: &UpstreamPool, n: usize) -> Vec<String> {
+        (0..n)
+            .map(|_| pool.pick().url.host_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_api_key() {
+        let url = Url::parse("https://eth-mainnet.g.alchemy.com/v2/{api_key}").unwrap();
+        assert_eq!(
+            expand_api_key(&url, "abc").unwrap().as_str(),
+            "https://eth-mainnet.g.alchemy.com/v2/abc"
+        );
+
+        let url = Url::parse("https://node.example.com/?key={api_key}").unwrap();
+        assert_eq!(
+            expand_api_key(&url, "abc").unwrap().as_str(),
+            "https://node.example.com/?key=abc"
+        );
+
+        let url = Url::parse("https://node.example.com/").unwrap();
+        assert!(expand_api_key(&url, "abc").is_err());
+    }
+
+    #[test]
+    fn test_empty_pool() {
+        let pool = UpstreamPool::new(
+            vec![],
+            LoadBalanceStrategy::RoundRobin,
+            &UpstreamClient::default(),
+        );
+        assert!(pool.is_err());
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let pool = pool(&[1, 1, 1], LoadBalanceStrategy::RoundRobin);
+        assert_eq!(
+            picked_hosts(&pool, 4),
+            vec!["upstream-0", "upstream-1", "upstream-2", "upstream-0"]
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let pool = pool(&[3, 1], LoadBalanceStrategy::Weighted);
+        assert_eq!(
+            picked_hosts(&pool, 4),
+            vec!["upstream-0", "upstream-0", "upstream-0", "upstream-1"]
+        );
+    }
+
+    #[test]
+    fn test_least_latency() {
+        let pool = pool(&[1, 1], LoadBalanceStrategy::LeastLatency);
+        pool.upstreams()[0].record_latency(Duration::from_millis(50));
+        pool.upstreams()[1].record_latency(Duration::from_millis(20));
+        assert_eq!(picked_hosts(&pool, 1), vec!["upstream-1"]);
+
+        assert_eq!(
+            pool.upstreams()[1].record_failure(Duration::from_millis(1)),
+            1
+        );
+        assert_eq!(picked_hosts(&pool, 1), vec!["upstream-0"]);
+    }
+
+    #[test]
+    fn test_consecutive_failures() {
+        let pool = pool(&[1], LoadBalanceStrategy::RoundRobin);
+        let upstream = &pool.upstreams()[0];
+
+        upstream.record_failure(Duration::ZERO);
+        assert_eq!(upstream.record_failure(Duration::ZERO), 2);
+
+        upstream.record_latency(Duration::from_millis(1));
+        assert_eq!(upstream.record_failure(Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn test_failover_order() {
+        let pool = pool(&[1, 1, 1], LoadBalanceStrategy::RoundRobin);
+        pool.pick();
+
+        let hosts = pool
+            .failover_order()
+            .map(|upstream| upstream.url.host_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, vec!["upstream-1", "upstream-2", "upstream-0"]);
+    }
+
+    #[test]
+    fn test_is_rate_limited() {
+        let limited = serde_json::json!({"error": {"code": -32005, "message": "limit exceeded"}});
+        let other = serde_json::json!({"error": {"code": -32000, "message": "execution reverted"}});
+
+        assert!(is_rate_limited(&limited));
+        assert!(!is_rate_limited(&other));
+        assert!(is_rate_limited(&serde_json::json!([
+            limited.clone(),
+            limited.clone()
+        ])));
+        assert!(!is_rate_limited(&serde_json::json!([limited, other])));
+        assert!(!is_rate_limited(&serde_json::json!({"result": "0x1"})));
+    }
+
+    #[test]
+    fn test_request_methods() {
+        let batch = serde_json::to_vec(&serde_json::json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_call", "params": [{}, "latest"] },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_blockNumber", "params": [] },
+        ]))
+        .unwrap();
+        assert_eq!(request_methods(&batch), ["eth_call", "eth_blockNumber"]);
+
+        let single = br#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}"#;
+        assert_eq!(request_methods(single), ["eth_chainId"]);
+
+        assert!(request_methods(b"{}").is_empty());
+    }
+
+    #[test]
+    fn test_method_routes() {
+        let router = UpstreamRouter::new(
+            pool(&[1], LoadBalanceStrategy::RoundRobin),
+            vec![
+                route_config(&["debug_*", "trace_*"], "archive"),
+                route_config(&["eth_getLogs"], "logs"),
+            ],
+            None,
+            vec![],
+            None,
+            None,
+            &UpstreamClient::default(),
+        )
+        .unwrap();
+
+        let routed_host = |method: &str| {
+            router
+                .route(method, None, None)
+                .pick()
+                .url
+                .host_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(routed_host("debug_traceTransaction"), "archive");
+        assert_eq!(routed_host("trace_block"), "archive");
+        assert_eq!(routed_host("eth_getLogs"), "logs");
+        assert_eq!(routed_host("eth_getLogsFoo"), "upstream-0");
+        assert_eq!(routed_host("eth_call"), "upstream-0");
+        assert_eq!(router.pools().count(), 3);
+    }
+
+    #[test]
+    fn test_unsupported_namespace() {
+        let mut router = UpstreamRouter::new(
+            pool(&[1], LoadBalanceStrategy::RoundRobin),
+            vec![route_config(&["trace_*"], "archive")],
+            None,
+            vec![],
+            None,
+            None,
+            &UpstreamClient::default(),
+        )
+        .unwrap();
+        router.set_unsupported_namespaces(BTreeSet::from(["trace".to_string(), "ots".to_string()]));
+
+        assert_eq!(router.unsupported_namespace("ots_getApiLevel"), Some("ots"));
+        assert_eq!(router.unsupported_namespace("trace_block"), None);
+        assert_eq!(router.unsupported_namespace("debug_traceTransaction"), None);
+        assert_eq!(router.unsupported_namespace("web3"), None);
+    }
+
+    #[test]
+    fn test_historical_route() {
+        let router = UpstreamRouter::new(
+            pool(&[1], LoadBalanceStrategy::RoundRobin),
+            vec![route_config(&["debug_*"], "debug")],
+            Some(HistoricalRouteConfig {
+                min_block_age: 100,
+                upstreams: vec![Url::parse("http://archive").unwrap().into()],
+                load_balance: Default::default(),
+            }),
+            vec![Url::parse("http://public").unwrap().into()],
+            None,
+            None,
+            &UpstreamClient::default(),
+        )
+        .unwrap();
+
+        let routed_host = |method: &str, block: Option<u64>, latest: Option<u64>| {
+            router
+                .route(method, block, latest)
+                .pick()
+                .url
+                .host_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(routed_host("eth_call", Some(899), Some(1000)), "archive");
+        assert_eq!(routed_host("eth_call", Some(900), Some(1000)), "upstream-0");
+        assert_eq!(routed_host("eth_call", None, Some(1000)), "upstream-0");
+        assert_eq!(routed_host("eth_call", Some(1), None), "upstream-0");
+        assert_eq!(routed_host("debug_traceCall", Some(1), Some(1000)), "debug");
+        assert_eq!(router.pools().count(), 4);
+        assert_eq!(
+            router.fallback_pool().unwrap().pick().url.host_str(),
+            Some("public")
+        );
+    }
+}
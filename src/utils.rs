@@ -1,8 +1,8 @@
-use reqwest::Url;
-use serde::Serialize;
 use serde_json::{json, Value};
 
-pub async fn get_chain_id(client: &reqwest::Client, rpc_url: &str) -> anyhow::Result<u64> {
+use crate::upstream::Upstream;
+
+pub async fn get_chain_id(upstream: &Upstream) -> anyhow::Result<u64> {
     let request_payload = json!({
         "jsonrpc": "2.0",
         "method": "eth_chainId",
@@ -10,27 +10,44 @@ pub async fn get_chain_id(client: &reqwest::Client, rpc_url: &str) -> anyhow::Re
         "id": 1
     });
 
-    let response = client.post(rpc_url).json(&request_payload).send().await?;
-
-    let json: Value = response.json().await?;
+    let json: Value = upstream.request(&request_payload).await?;
     match json["result"].as_str() {
         Some(chain_id) => Ok(u64::from_str_radix(&chain_id[2..], 16)?),
         None => Err(anyhow::anyhow!("fail to get chain id: {json}")),
     }
 }
 
-pub async fn do_rpc_request<T: Serialize + ?Sized>(
-    client: &reqwest::Client,
-    rpc_url: Url,
-    body: &T,
-) -> anyhow::Result<Value> {
-    let result = client
-        .post(rpc_url)
-        .json(body)
-        .send()
-        .await?
-        .json::<Value>()
-        .await?;
-
-    Ok(result)
+pub async fn get_block_number(upstream: &Upstream) -> anyhow::Result<u64> {
+    let request_payload = json!({
+        "jsonrpc": "2.0",
+        "method": "eth_blockNumber",
+        "params": [],
+        "id": 1
+    });
+
+    let json: Value = upstream.request(&request_payload).await?;
+    match json["result"].as_str() {
+        Some(block_number) => Ok(u64::from_str_radix(&block_number[2..], 16)?),
+        None => Err(anyhow::anyhow!("fail to get block number: {json}")),
+    }
+}
+
+/// Number of the finalized block, `None` if the upstream doesn't know one yet.
+pub async fn get_finalized_block_number(upstream: &Upstream) -> anyhow::Result<Option<u64>> {
+    let request_payload = json!({
+        "jsonrpc": "2.0",
+        "method": "eth_getBlockByNumber",
+        "params": ["finalized", false],
+        "id": 1
+    });
+
+    let json: Value = upstream.request(&request_payload).await?;
+    if !json["error"].is_null() {
+        return Err(anyhow::anyhow!("fail to get finalized block: {json}"));
+    }
+
+    match json["result"]["number"].as_str() {
+        Some(block_number) => Ok(Some(u64::from_str_radix(&block_number[2..], 16)?)),
+        None => Ok(None),
+    }
 }